@@ -0,0 +1,26 @@
+use cashu_test::crypto::{HashToCurveCache, Secret};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn hash_to_curve(c: &mut Criterion) {
+    let secrets: Vec<Secret> = (0..100).map(|_| Secret::random()).collect();
+
+    c.bench_function("hash_to_curve", |b| {
+        b.iter(|| {
+            for secret in &secrets {
+                black_box(secret.hash_to_curve());
+            }
+        })
+    });
+
+    let cache = HashToCurveCache::new(secrets.len());
+    c.bench_function("hash_to_curve_cached", |b| {
+        b.iter(|| {
+            for secret in &secrets {
+                black_box(cache.hash_to_curve(secret));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, hash_to_curve);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+use cashu_test::crypto::{MintKey, Secret, UnblindedKey};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use k256::SecretKey;
+
+fn verify_batch(c: &mut Criterion) {
+    let sk = SecretKey::from_slice(&[7; 32]).unwrap();
+    let key = MintKey::from(sk.clone());
+    let r = SecretKey::from_slice(&[1; 32]).unwrap();
+    let items: Vec<(Secret, UnblindedKey)> = (0..100)
+        .map(|_| {
+            let secret = Secret::random();
+            let c = secret
+                .blinded_message(&r)
+                .unwrap()
+                .blinded_key(sk.clone())
+                .unwrap()
+                .unblind(&r, &key.public_key())
+                .unwrap();
+            (secret, c)
+        })
+        .collect();
+
+    c.bench_function("verify", |b| {
+        b.iter(|| {
+            for (secret, c) in &items {
+                black_box(key.verify(secret, c));
+            }
+        })
+    });
+
+    c.bench_function("verify_batch", |b| {
+        b.iter(|| black_box(key.verify_batch(&items)))
+    });
+}
+
+criterion_group!(benches, verify_batch);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+// Exercise a mint end to end: fetch keys, mint against a (regtest) invoice, swap, and verify the
+// DLEQ proofs on the result.
+//
+//     cargo run --example verify --features client -- http://localhost:3338 [amount]
+
+use std::{thread, time::Duration};
+
+use cashu_test::{Error, KeysetCache, MintClient, MintConnector, TokenBuilder, Unit, Wallet};
+use url::Url;
+
+const SEND_AMOUNT: u64 = 10;
+
+fn main() -> Result<(), Error> {
+    let mut args = std::env::args().skip(1);
+    let url = args.next().expect("usage: verify <mint url> [amount]");
+    let url = Url::parse(&url).expect("invalid mint url");
+    let amount = args
+        .next()
+        .map(|a| a.parse().expect("invalid amount"))
+        .unwrap_or(64);
+
+    let client = MintClient::new(url.clone())?;
+
+    println!("Fetching keys from {url}");
+    let info = client.get_info()?;
+    println!("  {}", info.name.as_deref().unwrap_or("unnamed mint"));
+    if !info.supports_mint_method("bolt11", &Unit::Sat) {
+        eprintln!("mint doesn't support minting bolt11/sat");
+        std::process::exit(1);
+    }
+    let keyset = client.active_keyset(&Unit::Sat)?;
+    println!(
+        "  active keyset {} with {} keys",
+        keyset.id,
+        keyset.keys.len()
+    );
+
+    let seed: [u8; 64] = std::array::from_fn(|_| rand::random());
+    let keyset_infos = client.get_keysets()?.keysets;
+    let mut wallet = Wallet::new(&client, &seed, vec![keyset], &keyset_infos)?;
+
+    let mut quote = wallet.mint_quote(amount)?;
+    println!("Minting {amount} sat with quote {}", quote.quote);
+    println!("  pay: {}", quote.request);
+    while !quote.can_mint() {
+        println!("  quote is {:?}, waiting for payment...", quote.state());
+        thread::sleep(Duration::from_secs(2));
+        quote = client.check_mint_quote(&quote.quote)?;
+    }
+    let minted = wallet.mint(&quote, amount)?;
+    println!("  minted {minted} sat, balance {}", wallet.balance());
+
+    println!("Swapping for {SEND_AMOUNT} sat");
+    let proofs = wallet.send(SEND_AMOUNT)?;
+    println!(
+        "  sent {} proofs, balance {}",
+        proofs.as_ref().len(),
+        wallet.balance()
+    );
+
+    let token = TokenBuilder::new()
+        .unit("sat")
+        .add_proofs(url, proofs)
+        .build();
+
+    println!("Verifying DLEQ proofs");
+    token.verify_all_dleq(&KeysetCache::new(&client))?;
+    println!("  ok");
+
+    println!("{}", token.serialize()?);
+    Ok(())
+}
@@ -0,0 +1,31 @@
+pub const MSAT_PER_SAT: u64 = 1000;
+
+pub fn sat_to_msat(sat: u64) -> Option<u64> {
+    sat.checked_mul(MSAT_PER_SAT)
+}
+
+// Rounds up, so a fee reserve derived from a msat amount never underfunds a melt
+pub fn msat_to_sat_ceil(msat: u64) -> u64 {
+    msat.div_ceil(MSAT_PER_SAT)
+}
+
+pub fn msat_to_sat_floor(msat: u64) -> u64 {
+    msat / MSAT_PER_SAT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msat_to_sat() {
+        assert_eq!(msat_to_sat_ceil(0), 0);
+        assert_eq!(msat_to_sat_ceil(1), 1);
+        assert_eq!(msat_to_sat_ceil(999), 1);
+        assert_eq!(msat_to_sat_ceil(1000), 1);
+        assert_eq!(msat_to_sat_ceil(1001), 2);
+        assert_eq!(msat_to_sat_floor(1999), 1);
+        assert_eq!(sat_to_msat(21), Some(21_000));
+        assert_eq!(sat_to_msat(u64::MAX), None);
+    }
+}
@@ -0,0 +1,74 @@
+// The BDHKE math (NUT-00) on bare curve types, using nothing from std so it can be built for
+// embedded signers with `default-features = false`. The types in `crypto` wrap these.
+
+use k256::elliptic_curve::Error;
+use k256::{PublicKey, SecretKey};
+use sha2::{Digest, Sha256};
+
+const DOMAIN_SEPARATOR: &[u8] = b"Secp256k1_HashToCurve_Cashu_";
+
+// Y: the first SHA-256 in the chain hash, hash(hash), ... that's an x coordinate on the curve
+pub fn hash_to_curve(message: &[u8]) -> PublicKey {
+    let mut point = [0x02; 33];
+    point[1..].copy_from_slice(&Sha256::digest(message));
+    loop {
+        if let Ok(pk) = PublicKey::from_sec1_bytes(&point) {
+            return pk;
+        }
+        let next = Sha256::digest(&point[1..]);
+        point[1..].copy_from_slice(&next);
+    }
+}
+
+// Y, domain-separated: the message is hashed once with a domain separator, then hashed with a
+// little-endian counter until the result is an x coordinate on the curve
+pub fn hash_to_curve_v2(message: &[u8]) -> PublicKey {
+    hash_to_curve_v2_with_counter(message).0
+}
+
+// The point along with the counter that reached it
+pub(crate) fn hash_to_curve_v2_with_counter(message: &[u8]) -> (PublicKey, u32) {
+    let message_hash = Sha256::new()
+        .chain_update(DOMAIN_SEPARATOR)
+        .chain_update(message)
+        .finalize();
+    let mut point = [0x02; 33];
+    let mut counter = 0u32;
+    loop {
+        let hash = Sha256::new()
+            .chain_update(message_hash)
+            .chain_update(counter.to_le_bytes())
+            .finalize();
+        point[1..].copy_from_slice(&hash);
+        if let Ok(pk) = PublicKey::from_sec1_bytes(&point) {
+            return (pk, counter);
+        }
+        counter += 1;
+    }
+}
+
+// B_ = Y + r*G
+pub fn blind(y: &PublicKey, r: &SecretKey) -> Result<PublicKey, Error> {
+    PublicKey::try_from(y.to_projective() + r.public_key().to_projective())
+}
+
+// C_ = k*B_
+pub fn sign(blinded_message: &PublicKey, k: &SecretKey) -> Result<PublicKey, Error> {
+    PublicKey::try_from(blinded_message.to_projective() * *k.to_nonzero_scalar())
+}
+
+// C = C_ - r*K
+pub fn unblind(
+    blinded_key: &PublicKey,
+    r: &SecretKey,
+    mint_key: &PublicKey,
+) -> Result<PublicKey, Error> {
+    PublicKey::try_from(
+        blinded_key.to_projective() - mint_key.to_projective() * *r.to_nonzero_scalar(),
+    )
+}
+
+// C == k*Y
+pub fn verify(y: &PublicKey, unblinded_key: &PublicKey, k: &SecretKey) -> bool {
+    y.to_projective() * *k.to_nonzero_scalar() == unblinded_key.to_projective()
+}
@@ -0,0 +1,58 @@
+use lightning_invoice::Bolt11Invoice;
+use sha2::{Digest, Sha256};
+
+// The amount an invoice requests, in whole sats rounded up, or None for an amountless invoice
+pub fn invoice_amount_sat(invoice: &str) -> Result<Option<u64>, crate::Error> {
+    let invoice: Bolt11Invoice = invoice.trim().parse()?;
+    Ok(invoice.amount_milli_satoshis().map(crate::msat_to_sat_ceil))
+}
+
+// Whether a hex preimage, e.g. the one a mint returns after a melt, hashes to the invoice's
+// payment hash, proving the invoice was paid
+pub fn verify_preimage(invoice: &str, preimage: &str) -> Result<bool, crate::Error> {
+    let invoice: Bolt11Invoice = invoice.trim().parse()?;
+    let hash = Sha256::digest(hex::decode(preimage.trim())?);
+    Ok(hash[..] == AsRef::<[u8]>::as_ref(invoice.payment_hash())[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::invoice;
+
+    #[test]
+    fn test_invoice_amount() {
+        let fixed = invoice(Some(21_000), [1; 32]);
+        assert_eq!(invoice_amount_sat(&fixed).unwrap(), Some(21));
+
+        // Part of a sat still needs a whole sat to pay
+        let fractional = invoice(Some(21_500), [1; 32]);
+        assert_eq!(invoice_amount_sat(&fractional).unwrap(), Some(22));
+    }
+
+    #[test]
+    fn test_amountless_invoice() {
+        let amountless = invoice(None, [1; 32]);
+        assert_eq!(invoice_amount_sat(&amountless).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_invoice() {
+        assert!(matches!(
+            invoice_amount_sat("lnbc1notaninvoice"),
+            Err(crate::Error::Bolt11(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_preimage() {
+        let preimage = [7u8; 32];
+        let paid = invoice(Some(21_000), Sha256::digest(preimage).into());
+        assert!(verify_preimage(&paid, &hex::encode(preimage)).unwrap());
+        assert!(!verify_preimage(&paid, &hex::encode([8u8; 32])).unwrap());
+        assert!(matches!(
+            verify_preimage(&paid, "not hex"),
+            Err(crate::Error::HexConversion(_))
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// TLV types from BOLT 12
+const OFFER_CURRENCY: u64 = 6;
+const OFFER_AMOUNT: u64 = 8;
+
+// The amount a bolt12 offer asks for in msats, or None when it leaves the amount to the payer. So
+// is an amount in a fiat currency, which can't be converted here.
+pub fn offer_amount_msat(offer: &str) -> Result<Option<u64>, crate::Error> {
+    let records = offer_records(offer).ok_or(crate::Error::InvalidOffer)?;
+    if records.iter().any(|(kind, _)| *kind == OFFER_CURRENCY) {
+        return Ok(None);
+    }
+    match records.iter().find(|(kind, _)| *kind == OFFER_AMOUNT) {
+        Some((_, value)) if value.len() <= 8 => {
+            Ok(Some(value.iter().fold(0, |n, b| n << 8 | *b as u64)))
+        }
+        Some(_) => Err(crate::Error::InvalidOffer),
+        None => Ok(None),
+    }
+}
+
+pub fn is_offer(request: &str) -> bool {
+    request.trim().to_lowercase().starts_with("lno1")
+}
+
+// An offer is bech32 without a checksum, and may be split into parts joined by "+" and
+// whitespace. The data is a stream of TLV records.
+fn offer_records(offer: &str) -> Option<Vec<(u64, Vec<u8>)>> {
+    let offer: String = offer
+        .split('+')
+        .map(str::trim)
+        .collect::<String>()
+        .to_lowercase();
+    let data = offer.strip_prefix("lno1")?;
+
+    let mut bytes = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in data.chars() {
+        acc = acc << 5 | CHARSET.find(c)? as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    let mut records = Vec::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        let kind = read_bigsize(&mut rest)?;
+        let len = read_bigsize(&mut rest)? as usize;
+        if len > rest.len() {
+            return None;
+        }
+        let (value, tail) = rest.split_at(len);
+        records.push((kind, value.to_vec()));
+        rest = tail;
+    }
+    Some(records)
+}
+
+fn read_bigsize(data: &mut &[u8]) -> Option<u64> {
+    let (first, rest) = data.split_first()?;
+    let width = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => {
+            *data = rest;
+            return Some(*n as u64);
+        }
+    };
+    if rest.len() < width {
+        return None;
+    }
+    let (value, rest) = rest.split_at(width);
+    *data = rest;
+    Some(value.iter().fold(0, |n, b| n << 8 | *b as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Offers for "coffee" at 21 sats, for "coffee" at any amount, and for "big" at 100m sats
+    const OFFER: &str =
+        "lno1pqp9yzq2qe3k7enxv4j3vggry3jnatzrgjyqqtxqdwlm0ug0uxyerc6lnljrqtd75mfr20wq4vwq";
+    const AMOUNTLESS: &str =
+        "lno1pgrxxmmxvejk293pqvjx204vgdzgsqpvcp4mldl3plscny0rt707gvpdh6ndydfacz43c";
+    const LARGE: &str =
+        "lno1pqz3wjrkaqqq5qmzd9n3vggry3jnatzrgjyqqtxqdwlm0ug0uxyerc6lnljrqtd75mfr20wq4vwq";
+
+    #[test]
+    fn test_offer_amount() {
+        assert_eq!(offer_amount_msat(OFFER).unwrap(), Some(21_000));
+        assert_eq!(offer_amount_msat(AMOUNTLESS).unwrap(), None);
+        assert_eq!(offer_amount_msat(LARGE).unwrap(), Some(100_000_000_000));
+        assert!(is_offer(OFFER));
+        assert!(!is_offer(&crate::mock::invoice(Some(1000), [1; 32])));
+
+        // Split offers and upper case read the same
+        let (head, tail) = OFFER.split_at(30);
+        let split = format!("{head}+\n  {tail}");
+        assert_eq!(offer_amount_msat(&split).unwrap(), Some(21_000));
+        assert_eq!(
+            offer_amount_msat(&OFFER.to_uppercase()).unwrap(),
+            Some(21_000)
+        );
+    }
+
+    #[test]
+    fn test_invalid_offer() {
+        for offer in ["lnbc1notanoffer", "lno1bio", &OFFER[..40]] {
+            assert!(
+                matches!(offer_amount_msat(offer), Err(crate::Error::InvalidOffer)),
+                "{offer}"
+            );
+        }
+    }
+}
@@ -0,0 +1,613 @@
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use reqwest::{Proxy, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use url::Url;
+
+use crate::{
+    BlindedMessage, BlindedSignature, CheckStateRequest, CheckStateResponse, ErrorResponse,
+    KeysResponse, Keyset, KeysetCache, KeysetsResponse, MeltMethod, MeltQuote, MeltQuoteRequest,
+    MeltRequest, MintConnector, MintInfo, MintQuote, MintQuoteRequest, MintRequest, MintResponse,
+    RestoreRequest, RestoreResponse, SwapRequest, SwapResponse, Unit,
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_RETRY_WAIT: Duration = Duration::from_secs(5);
+
+// A blocking HTTP client for a mint's v1 API
+pub struct MintClient {
+    url: Url,
+    http: Client,
+    retry_rate_limited: bool,
+    max_retry_wait: Duration,
+    verify_dleq: bool,
+    // Keysets fetched to verify DLEQ proofs, fetched through the client itself
+    keysets: KeysetCache<()>,
+}
+
+impl MintClient {
+    // A client with the default configuration. Building one can still fail, e.g. when the TLS
+    // backend can't be set up.
+    pub fn new(url: Url) -> Result<MintClient, crate::Error> {
+        MintClient::builder(url).build()
+    }
+
+    pub fn builder(url: Url) -> MintClientBuilder {
+        MintClientBuilder {
+            url,
+            timeout: DEFAULT_TIMEOUT,
+            proxy: None,
+            user_agent: None,
+            headers: Vec::new(),
+            retry_rate_limited: false,
+            max_retry_wait: DEFAULT_MAX_RETRY_WAIT,
+            verify_dleq: false,
+        }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    // The keyset a wallet mints and swaps into for this unit
+    pub fn active_keyset(&self, unit: &Unit) -> Result<Keyset, crate::Error> {
+        let keysets = self.get_keysets()?;
+        let id = &keysets.active(unit)?.id;
+        self.get_keyset(id)?
+            .into_keysets()
+            .into_iter()
+            .find(|k| k.as_ref().map_or(true, |k| &k.id == id))
+            .unwrap_or_else(|| Err(crate::Error::UnknownKeyset(id.clone())))
+    }
+
+    // Check the DLEQ of each signature against the output it answers, erroring at the first
+    // signature without a valid one. Signatures are indexed in order, and there must be one for
+    // every output.
+    fn check_dleq(
+        &self,
+        outputs: &[BlindedMessage],
+        signatures: &[BlindedSignature],
+    ) -> Result<(), crate::Error> {
+        if signatures.len() != outputs.len() {
+            return Err(crate::Error::InvalidResponse(format!(
+                "{} signatures for {} outputs",
+                signatures.len(),
+                outputs.len()
+            )));
+        }
+        if !self.verify_dleq {
+            return Ok(());
+        }
+        for (index, (output, signature)) in outputs.iter().zip(signatures).enumerate() {
+            let id = signature
+                .id
+                .as_deref()
+                .or(output.id.as_deref())
+                .ok_or(crate::Error::MissingKeysetId(vec![index]))?;
+            if signature.dleq.is_none() {
+                return Err(crate::Error::MissingDleq(index));
+            }
+            let mint_key = self.keysets.mint_key_from(self, id, signature.amount)?;
+            if !signature.verify_dleq_received(output, &mint_key) {
+                return Err(crate::Error::InvalidDleq(index));
+            }
+        }
+        Ok(())
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/v1/{path}", self.url.as_str().trim_end_matches('/'))
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, crate::Error> {
+        self.send(|| self.http.get(self.endpoint(path)))
+    }
+
+    fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, crate::Error> {
+        self.send(|| self.http.post(self.endpoint(path)).json(body))
+    }
+
+    fn send<T: DeserializeOwned>(
+        &self,
+        request: impl Fn() -> RequestBuilder,
+    ) -> Result<T, crate::Error> {
+        match read_response(request().send()?) {
+            Err(crate::Error::RateLimited {
+                retry_after: Some(delay),
+            }) if self.retry_rate_limited && delay <= self.max_retry_wait => {
+                thread::sleep(delay);
+                read_response(request().send()?)
+            }
+            result => result,
+        }
+    }
+}
+
+pub struct MintClientBuilder {
+    url: Url,
+    timeout: Duration,
+    proxy: Option<String>,
+    user_agent: Option<String>,
+    headers: Vec<(String, String)>,
+    retry_rate_limited: bool,
+    max_retry_wait: Duration,
+    verify_dleq: bool,
+}
+
+impl MintClientBuilder {
+    pub fn timeout(mut self, timeout: Duration) -> MintClientBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    // Route all requests through a proxy, e.g. socks5h://127.0.0.1:9050 to reach .onion mints
+    // over Tor. The socks5h scheme resolves hostnames through the proxy.
+    pub fn proxy(mut self, url: impl Into<String>) -> MintClientBuilder {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> MintClientBuilder {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    // A header sent with every request, e.g. for an authenticating reverse proxy
+    pub fn header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> MintClientBuilder {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    // When the mint rate limits a request and says when to come back, wait and retry it once
+    pub fn retry_rate_limited(mut self, retry: bool) -> MintClientBuilder {
+        self.retry_rate_limited = retry;
+        self
+    }
+
+    // The longest Retry-After worth waiting out. Longer delays are returned as RateLimited for
+    // the caller to schedule.
+    pub fn max_retry_wait(mut self, wait: Duration) -> MintClientBuilder {
+        self.max_retry_wait = wait;
+        self
+    }
+
+    // Verify the NUT-12 DLEQ proof of every signature the mint returns from minting, swapping and
+    // restoring. Mints without NUT-12 send none, so every such call fails with this on.
+    pub fn verify_dleq(mut self, verify: bool) -> MintClientBuilder {
+        self.verify_dleq = verify;
+        self
+    }
+
+    pub fn build(self) -> Result<MintClient, crate::Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let invalid = || crate::Error::InvalidHeader(name.clone());
+            headers.insert(
+                HeaderName::try_from(name.as_str()).map_err(|_| invalid())?,
+                HeaderValue::try_from(value.as_str()).map_err(|_| invalid())?,
+            );
+        }
+        let mut http = Client::builder()
+            .timeout(self.timeout)
+            .default_headers(headers);
+        if let Some(proxy) = &self.proxy {
+            http = http.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            http = http.user_agent(user_agent);
+        }
+        Ok(MintClient {
+            url: self.url,
+            http: http.build()?,
+            retry_rate_limited: self.retry_rate_limited,
+            max_retry_wait: self.max_retry_wait,
+            verify_dleq: self.verify_dleq,
+            keysets: KeysetCache::new(()),
+        })
+    }
+}
+
+fn read_response<T: DeserializeOwned>(response: Response) -> Result<T, crate::Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json()?);
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(crate::Error::RateLimited {
+            retry_after: retry_after(&response),
+        });
+    }
+    Err(parse_error(status.as_u16(), &response.text()?))
+}
+
+// Only the delay-seconds form of Retry-After is understood, not an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+// Mints report failures as a JSON body with a code and detail. Anything else is reported by
+// status alone.
+pub(crate) fn parse_error(status: u16, body: &str) -> crate::Error {
+    match serde_json::from_str::<ErrorResponse>(body) {
+        Ok(error) => crate::Error::Mint {
+            code: error.code,
+            detail: error.detail,
+        },
+        Err(_) => crate::Error::HttpStatus(status),
+    }
+}
+
+impl MintConnector for MintClient {
+    fn get_info(&self) -> Result<MintInfo, crate::Error> {
+        self.get("info")
+    }
+
+    fn get_keys(&self) -> Result<KeysResponse, crate::Error> {
+        self.get("keys")
+    }
+
+    fn get_keyset(&self, id: &str) -> Result<KeysResponse, crate::Error> {
+        self.get(&format!("keys/{id}"))
+    }
+
+    fn get_keysets(&self) -> Result<KeysetsResponse, crate::Error> {
+        self.get("keysets")
+    }
+
+    fn restore(&self, request: &RestoreRequest) -> Result<RestoreResponse, crate::Error> {
+        let response: RestoreResponse = self.post("restore", request)?;
+        self.check_dleq(&response.outputs, &response.signatures)?;
+        Ok(response)
+    }
+
+    fn check_state(&self, request: &CheckStateRequest) -> Result<CheckStateResponse, crate::Error> {
+        self.post("checkstate", request)
+    }
+
+    fn mint_quote(&self, request: &MintQuoteRequest) -> Result<MintQuote, crate::Error> {
+        self.post("mint/quote/bolt11", request)
+    }
+
+    fn check_mint_quote(&self, quote: &str) -> Result<MintQuote, crate::Error> {
+        self.get(&format!("mint/quote/bolt11/{quote}"))
+    }
+
+    fn mint(&self, request: &MintRequest) -> Result<MintResponse, crate::Error> {
+        let response: MintResponse = self.post("mint/bolt11", request)?;
+        self.check_dleq(&request.outputs, &response.signatures)?;
+        Ok(response)
+    }
+
+    fn swap(&self, request: &SwapRequest) -> Result<SwapResponse, crate::Error> {
+        let response: SwapResponse = self.post("swap", request)?;
+        self.check_dleq(&request.outputs, &response.signatures)?;
+        Ok(response)
+    }
+
+    fn melt_quote(
+        &self,
+        method: MeltMethod,
+        request: &MeltQuoteRequest,
+    ) -> Result<MeltQuote, crate::Error> {
+        self.post(&method.quote_path(), request)
+    }
+
+    fn melt(&self, method: MeltMethod, request: &MeltRequest) -> Result<MeltQuote, crate::Error> {
+        self.post(&method.melt_path(), request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    use super::*;
+
+    // Serve the given raw HTTP responses, one per connection, returning the mint's URL
+    fn serve<R: AsRef<str> + Send + 'static>(responses: Vec<R>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    let header = line.to_lowercase();
+                    if let Some(value) = header.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+                stream.write_all(response.as_ref().as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    fn json_response(body: &impl Serialize) -> String {
+        let body = serde_json::to_string(body).unwrap();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    // Serve one response, sending back the request line and body it answered
+    fn serve_recording(response: &'static str) -> (Url, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let header = line.to_lowercase();
+                if let Some(value) = header.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+            let request_line = request_line.trim().to_string();
+            sender
+                .send((request_line, String::from_utf8(body).unwrap()))
+                .unwrap();
+        });
+        (url, receiver)
+    }
+
+    const RATE_LIMITED: &str =
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const KEYSETS: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 15\r\nConnection: close\r\n\r\n{\"keysets\": []}";
+
+    #[test]
+    fn test_rate_limited() {
+        let client = MintClient::new(serve(vec![RATE_LIMITED])).unwrap();
+        assert!(matches!(
+            client.get_keysets(),
+            Err(crate::Error::RateLimited {
+                retry_after: Some(delay)
+            }) if delay == Duration::ZERO
+        ));
+
+        let client = MintClient::new(serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: Wed, 21 Oct 2015 07:28:00 GMT\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])).unwrap();
+        assert!(matches!(
+            client.get_keysets(),
+            Err(crate::Error::RateLimited { retry_after: None })
+        ));
+    }
+
+    #[test]
+    fn test_rate_limited_retry() {
+        let url = serve(vec![RATE_LIMITED, KEYSETS]);
+        let client = MintClient::builder(url)
+            .retry_rate_limited(true)
+            .build()
+            .unwrap();
+        assert!(client.get_keysets().unwrap().keysets.is_empty());
+
+        // Only one retry is made
+        let url = serve(vec![RATE_LIMITED, RATE_LIMITED]);
+        let client = MintClient::builder(url)
+            .retry_rate_limited(true)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.get_keysets(),
+            Err(crate::Error::RateLimited { .. })
+        ));
+
+        // Delays past the limit aren't waited out
+        let url = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 60\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let client = MintClient::builder(url)
+            .retry_rate_limited(true)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.get_keysets(),
+            Err(crate::Error::RateLimited {
+                retry_after: Some(delay)
+            }) if delay == Duration::from_secs(60)
+        ));
+    }
+
+    #[test]
+    fn test_verify_dleq() {
+        let mint = crate::mock::MockMint::new("mint");
+        let secret = crate::crypto::Secret::from_utf8("secret").unwrap();
+        let r = k256::SecretKey::random(&mut rand::thread_rng());
+        let output = BlindedMessage {
+            amount: 8,
+            id: Some(mint.keyset.id.to_string()),
+            blinded_message: secret
+                .blinded_message(&r)
+                .unwrap()
+                .to_compressed_bytes()
+                .to_vec(),
+        };
+        let request = SwapRequest {
+            inputs: Vec::new().into(),
+            outputs: vec![output.clone()],
+        };
+        let keys = json_response(&mint.get_keyset(&mint.keyset.id).unwrap());
+        let signature = mint.sign(&output);
+        let mut invalid = signature.clone();
+        invalid.dleq.as_mut().unwrap().s[31] ^= 1;
+        let swapped = |signature: &BlindedSignature| {
+            json_response(&SwapResponse {
+                signatures: vec![signature.clone()],
+            })
+        };
+        let verifying = |responses| {
+            MintClient::builder(serve(responses))
+                .verify_dleq(true)
+                .build()
+                .unwrap()
+        };
+
+        // Keys fetched for the first swap are reused for the next
+        let client = verifying(vec![swapped(&signature), keys.clone(), swapped(&signature)]);
+        assert_eq!(
+            client.swap(&request).unwrap().signatures,
+            vec![signature.clone()]
+        );
+        assert_eq!(client.swap(&request).unwrap().signatures, vec![signature]);
+
+        let client = verifying(vec![swapped(&invalid), keys]);
+        assert!(matches!(
+            client.swap(&request),
+            Err(crate::Error::InvalidDleq(0))
+        ));
+
+        // Off by default
+        let client = MintClient::new(serve(vec![swapped(&invalid)])).unwrap();
+        assert!(client.swap(&request).is_ok());
+
+        // A signature for every output, whether or not DLEQs are checked
+        let client = MintClient::new(serve(vec![json_response(&SwapResponse {
+            signatures: Vec::new(),
+        })]))
+        .unwrap();
+        assert!(matches!(
+            client.swap(&request),
+            Err(crate::Error::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let client = MintClient::new(Url::parse("https://mint.example.com/").unwrap()).unwrap();
+        assert_eq!(client.endpoint("keys"), "https://mint.example.com/v1/keys");
+
+        let client = MintClient::new(Url::parse("https://example.com/cashu").unwrap()).unwrap();
+        assert_eq!(client.endpoint("swap"), "https://example.com/cashu/v1/swap");
+    }
+
+    #[test]
+    fn test_builder() {
+        let url = Url::parse("http://mintabcdefgh.onion").unwrap();
+        let builder = MintClient::builder(url.clone());
+        assert_eq!(builder.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(builder.max_retry_wait, DEFAULT_MAX_RETRY_WAIT);
+
+        let builder = builder
+            .timeout(Duration::from_secs(90))
+            .proxy("socks5h://127.0.0.1:9050")
+            .user_agent("cashu-test/0.1")
+            .header("x-api-key", "secret");
+        assert_eq!(builder.timeout, Duration::from_secs(90));
+        assert_eq!(builder.proxy.as_deref(), Some("socks5h://127.0.0.1:9050"));
+        assert_eq!(builder.user_agent.as_deref(), Some("cashu-test/0.1"));
+        assert_eq!(
+            builder.headers,
+            vec![("x-api-key".to_string(), "secret".to_string())]
+        );
+        let client = builder.build().unwrap();
+        assert_eq!(client.url(), &url);
+
+        assert!(matches!(
+            MintClient::builder(url.clone())
+                .proxy("not a proxy")
+                .build(),
+            Err(crate::Error::Http(_))
+        ));
+        assert!(matches!(
+            MintClient::builder(url).header("bad header", "x").build(),
+            Err(crate::Error::InvalidHeader(name)) if name == "bad header"
+        ));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = parse_error(400, r#"{"code": 11001, "detail": "Token already spent"}"#);
+        assert!(matches!(error, crate::Error::Mint { code: 11001, .. }));
+        assert!(matches!(
+            parse_error(400, r#"{"detail": "Lightning payment failed"}"#),
+            crate::Error::Mint { code: 0, detail } if detail == "Lightning payment failed"
+        ));
+        assert!(matches!(
+            parse_error(502, "<html>Bad Gateway</html>"),
+            crate::Error::HttpStatus(502)
+        ));
+    }
+
+    #[test]
+    fn test_melt_quote_amountless() {
+        const QUOTE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 55\r\nConnection: close\r\n\r\n{\"quote\":\"q\",\"amount\":21,\"fee_reserve\":1,\"expiry\":null}";
+        let (url, recorded) = serve_recording(QUOTE);
+        let request = MeltQuoteRequest {
+            request: crate::mock::invoice(None, [1; 32]),
+            unit: "sat".to_string(),
+            options: Some(crate::MeltOptions {
+                amountless: Some(crate::Amountless {
+                    amount_msat: 21_000,
+                }),
+            }),
+        };
+        let quote = MintClient::new(url)
+            .unwrap()
+            .melt_quote(MeltMethod::Bolt11, &request)
+            .unwrap();
+        assert_eq!(quote.amount, 21);
+        let (_, body) = recorded.recv().unwrap();
+        assert!(body.ends_with(r#","unit":"sat","options":{"amountless":{"amount_msat":21000}}}"#));
+    }
+
+    #[test]
+    fn test_melt_quote_method() {
+        const QUOTE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 55\r\nConnection: close\r\n\r\n{\"quote\":\"q\",\"amount\":21,\"fee_reserve\":1,\"expiry\":null}";
+        let offer =
+            "lno1pqp9yzq2qe3k7enxv4j3vggry3jnatzrgjyqqtxqdwlm0ug0uxyerc6lnljrqtd75mfr20wq4vwq";
+        let invoice = crate::mock::invoice(Some(21_000), [1; 32]);
+
+        let mut requests = Vec::new();
+        for (method, request) in [
+            (MeltMethod::Bolt11, invoice),
+            (MeltMethod::Bolt12, offer.to_string()),
+        ] {
+            let (url, recorded) = serve_recording(QUOTE);
+            let client = MintClient::new(url).unwrap();
+            let request = MeltQuoteRequest {
+                request,
+                unit: "sat".to_string(),
+                options: None,
+            };
+            assert_eq!(client.melt_quote(method, &request).unwrap().amount, 21);
+            requests.push(recorded.recv().unwrap());
+        }
+        assert_eq!(requests[0].0, "POST /v1/melt/quote/bolt11 HTTP/1.1");
+        assert_eq!(requests[1].0, "POST /v1/melt/quote/bolt12 HTTP/1.1");
+        assert!(requests[0].1.contains("lnbc"));
+        assert_eq!(
+            requests[1].1,
+            format!(r#"{{"request":"{offer}","unit":"sat"}}"#)
+        );
+    }
+}
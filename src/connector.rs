@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use k256::PublicKey;
+
+use crate::{
+    CheckStateRequest, CheckStateResponse, KeysResponse, Keyset, KeysetsResponse, MeltMethod,
+    MeltQuote, MeltQuoteRequest, MeltRequest, MintInfo, MintQuote, MintQuoteRequest, MintRequest,
+    MintResponse, RestoreRequest, RestoreResponse, SwapRequest, SwapResponse,
+};
+
+pub trait MintConnector {
+    fn get_info(&self) -> Result<MintInfo, crate::Error>;
+
+    fn get_keys(&self) -> Result<KeysResponse, crate::Error>;
+
+    fn get_keyset(&self, id: &str) -> Result<KeysResponse, crate::Error>;
+
+    fn get_keysets(&self) -> Result<KeysetsResponse, crate::Error>;
+
+    fn restore(&self, request: &RestoreRequest) -> Result<RestoreResponse, crate::Error>;
+
+    fn check_state(&self, request: &CheckStateRequest) -> Result<CheckStateResponse, crate::Error>;
+
+    fn mint_quote(&self, request: &MintQuoteRequest) -> Result<MintQuote, crate::Error>;
+
+    fn check_mint_quote(&self, quote: &str) -> Result<MintQuote, crate::Error>;
+
+    fn mint(&self, request: &MintRequest) -> Result<MintResponse, crate::Error>;
+
+    fn swap(&self, request: &SwapRequest) -> Result<SwapResponse, crate::Error>;
+
+    fn melt_quote(
+        &self,
+        method: MeltMethod,
+        request: &MeltQuoteRequest,
+    ) -> Result<MeltQuote, crate::Error>;
+
+    // The quote's state after the melt: paid, or pending while the payment is in flight
+    fn melt(&self, method: MeltMethod, request: &MeltRequest) -> Result<MeltQuote, crate::Error>;
+}
+
+impl<T: MintConnector + ?Sized> MintConnector for &T {
+    fn get_info(&self) -> Result<MintInfo, crate::Error> {
+        (**self).get_info()
+    }
+
+    fn get_keys(&self) -> Result<KeysResponse, crate::Error> {
+        (**self).get_keys()
+    }
+
+    fn get_keyset(&self, id: &str) -> Result<KeysResponse, crate::Error> {
+        (**self).get_keyset(id)
+    }
+
+    fn get_keysets(&self) -> Result<KeysetsResponse, crate::Error> {
+        (**self).get_keysets()
+    }
+
+    fn restore(&self, request: &RestoreRequest) -> Result<RestoreResponse, crate::Error> {
+        (**self).restore(request)
+    }
+
+    fn check_state(&self, request: &CheckStateRequest) -> Result<CheckStateResponse, crate::Error> {
+        (**self).check_state(request)
+    }
+
+    fn mint_quote(&self, request: &MintQuoteRequest) -> Result<MintQuote, crate::Error> {
+        (**self).mint_quote(request)
+    }
+
+    fn check_mint_quote(&self, quote: &str) -> Result<MintQuote, crate::Error> {
+        (**self).check_mint_quote(quote)
+    }
+
+    fn mint(&self, request: &MintRequest) -> Result<MintResponse, crate::Error> {
+        (**self).mint(request)
+    }
+
+    fn swap(&self, request: &SwapRequest) -> Result<SwapResponse, crate::Error> {
+        (**self).swap(request)
+    }
+
+    fn melt_quote(
+        &self,
+        method: MeltMethod,
+        request: &MeltQuoteRequest,
+    ) -> Result<MeltQuote, crate::Error> {
+        (**self).melt_quote(method, request)
+    }
+
+    fn melt(&self, method: MeltMethod, request: &MeltRequest) -> Result<MeltQuote, crate::Error> {
+        (**self).melt(method, request)
+    }
+}
+
+// Fetches keysets from the mint on first use, including inactive ones
+pub struct KeysetCache<C> {
+    client: C,
+    keysets: RefCell<HashMap<String, Keyset>>,
+}
+
+impl<C> KeysetCache<C> {
+    pub fn new(client: C) -> KeysetCache<C> {
+        KeysetCache {
+            client,
+            keysets: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Like get, but fetching through `client` on a miss. This lets a client keep a cache of its
+    // own keysets.
+    pub fn get_from(&self, client: &impl MintConnector, id: &str) -> Result<Keyset, crate::Error> {
+        if let Some(keyset) = self.keysets.borrow().get(id) {
+            return Ok(keyset.clone());
+        }
+        for keyset in client.get_keyset(id)?.into_keysets() {
+            let keyset = keyset?;
+            if keyset.id == id {
+                self.keysets
+                    .borrow_mut()
+                    .insert(keyset.id.to_string(), keyset.clone());
+                return Ok(keyset);
+            }
+        }
+        Err(crate::Error::UnknownKeyset(id.to_string()))
+    }
+
+    pub fn mint_key_from(
+        &self,
+        client: &impl MintConnector,
+        id: &str,
+        amount: u64,
+    ) -> Result<PublicKey, crate::Error> {
+        self.get_from(client, id)?
+            .keys
+            .get(&amount)
+            .copied()
+            .ok_or(crate::Error::UnknownAmount(amount))
+    }
+}
+
+impl<C: MintConnector> KeysetCache<C> {
+    pub fn get(&self, id: &str) -> Result<Keyset, crate::Error> {
+        self.get_from(&self.client, id)
+    }
+
+    pub fn mint_key(&self, id: &str, amount: u64) -> Result<PublicKey, crate::Error> {
+        self.mint_key_from(&self.client, id, amount)
+    }
+}
@@ -1,49 +1,152 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
 use derive_more::{AsRef, From, Into};
-use k256::{ProjectivePoint, PublicKey, Scalar, SecretKey};
+use k256::elliptic_curve::ops::Reduce;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::{ProjectivePoint, PublicKey, Scalar, SecretKey, U256};
 use sha2::{Digest, Sha256};
 
-#[derive(Debug, PartialEq, Eq, AsRef, Into, From)]
+// The bytes are only reachable through AsRef for hashing and through expose_bytes, so a secret
+// can't be converted into a plain Vec by accident
+#[derive(PartialEq, Eq, AsRef)]
 pub struct Secret(Vec<u8>);
 
+pub const MAX_SECRET_LENGTH: usize = 1024;
+
+// Only the length is shown, so secrets don't end up in logs
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<redacted>, {})", self.0.len())
+    }
+}
+
 impl Secret {
     pub fn random() -> Secret {
         let c: [u8; 20] = rand::random();
         Secret(c.to_vec())
     }
 
+    // Secrets are between 1 and MAX_SECRET_LENGTH bytes
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Secret, crate::Error> {
+        if bytes.is_empty() || bytes.len() > MAX_SECRET_LENGTH {
+            return Err(crate::Error::InvalidSecretLength(bytes.len()));
+        }
+        Ok(Secret(bytes))
+    }
+
+    pub fn expose_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
     pub fn from_hex(data: &str) -> Result<Secret, crate::Error> {
-        Ok(Secret(hex::decode(data)?))
+        Secret::from_bytes(hex::decode(data)?)
     }
 
-    pub fn hash_to_curve(&self) -> PublicKey {
-        let mut s = Sha256::digest(&self.0);
-        let mut v = Vec::new();
-        loop {
-            v.clear();
-            v.extend(b"\x02");
-            v.extend(&s);
-            if let Ok(pk) = k256::PublicKey::from_sec1_bytes(&v) {
-                return pk;
-            }
-            s = Sha256::digest(&s);
+    // The string's bytes as-is, which is how proofs carry their secrets
+    pub fn from_utf8(data: &str) -> Result<Secret, crate::Error> {
+        Secret::from_bytes(data.as_bytes().to_vec())
+    }
+
+    // The secret as it appears in a proof. Secrets are normally text, either hex or a NUT-10
+    // well-known secret, and are passed through. Raw bytes are hex-encoded, but since proofs
+    // commit to the bytes of their secret string, such a secret has to be blinded in that
+    // hex form to be usable in a proof.
+    pub fn as_str(&self) -> Cow<'_, str> {
+        match std::str::from_utf8(&self.0) {
+            Ok(s) => Cow::Borrowed(s),
+            Err(_) => Cow::Owned(hex::encode(&self.0)),
         }
     }
 
+    pub fn hash_to_curve(&self) -> PublicKey {
+        crate::bdhke::hash_to_curve(&self.0)
+    }
+
+    pub fn hash_to_curve_v2(&self) -> PublicKey {
+        crate::bdhke::hash_to_curve_v2(&self.0)
+    }
+
     pub fn blinded_message(
         &self,
         blinding_factor: &k256::SecretKey,
     ) -> Result<BlindedMessage, crate::Error> {
-        // Convert myself into a point
-        let secret_key = self.hash_to_curve();
-        let secret_point: ProjectivePoint = secret_key.into();
+        blind_point(&self.hash_to_curve(), blinding_factor)
+    }
+}
+
+// B_ = Y + r*G, for a Y = hash_to_curve(secret) the caller already has
+pub fn blind_point(y: &PublicKey, r: &SecretKey) -> Result<BlindedMessage, crate::Error> {
+    Ok(BlindedMessage(crate::bdhke::blind(y, r)?))
+}
 
-        // Convert blinding factor into a point
-        let bf_key = blinding_factor.public_key();
-        let blind_point: ProjectivePoint = bf_key.into();
+// A blinding factor r in [1, n), redrawing from the RNG until its bytes are one. SecretKey
+// rejects zero, which would leave B_ = Y unblinded, and anything past the group order.
+pub fn random_blinding_factor<R: rand::RngCore + rand::CryptoRng>(rng: &mut R) -> SecretKey {
+    let mut bytes = [0; 32];
+    loop {
+        rng.fill_bytes(&mut bytes);
+        if let Ok(r) = SecretKey::from_slice(&bytes) {
+            return r;
+        }
+    }
+}
 
-        let new_point = secret_point + blind_point;
+// Hex is decoded when it parses, and anything else is taken as raw UTF-8. The two readings are
+// ambiguous: a proof's secret "deadbeef" is four ASCII bytes on the wire, but this parses it
+// as two. Use from_hex or from_utf8 when the encoding is known.
+impl FromStr for Secret {
+    type Err = crate::Error;
 
-        Ok(BlindedMessage(new_point.try_into()?))
+    fn from_str(s: &str) -> Result<Secret, crate::Error> {
+        Secret::from_hex(s).or_else(|_| Secret::from_utf8(s))
+    }
+}
+
+// Memoizes hash_to_curve by secret bytes, for verifiers that see the same secrets repeatedly.
+// Callers opt in by holding a cache, which stops growing once it reaches its capacity.
+#[derive(Debug)]
+pub struct HashToCurveCache {
+    capacity: usize,
+    points: Mutex<HashMap<Vec<u8>, PublicKey>>,
+}
+
+impl HashToCurveCache {
+    pub fn new(capacity: usize) -> HashToCurveCache {
+        HashToCurveCache {
+            capacity,
+            points: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn hash_to_curve(&self, secret: &Secret) -> PublicKey {
+        self.hash_bytes(&secret.0)
+    }
+
+    // For proofs, whose secrets arrive as strings that haven't been checked against the length
+    // limits. Hashing them is still well defined, and the mint rejects any that are out of range.
+    pub(crate) fn hash_bytes(&self, secret: &[u8]) -> PublicKey {
+        let mut points = self.points.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(point) = points.get(secret) {
+            return *point;
+        }
+        let point = crate::bdhke::hash_to_curve(secret);
+        if points.len() < self.capacity {
+            points.insert(secret.to_vec(), point);
+        }
+        point
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
@@ -52,31 +155,255 @@ pub struct BlindedMessage(PublicKey);
 
 impl BlindedMessage {
     pub fn blinded_key(&self, sk: SecretKey) -> Result<BlindedKey, crate::Error> {
-        let pk = self.0;
-        let point = pk.to_projective();
-        let scalar = sk.as_scalar_primitive();
-        let scalar: Scalar = scalar.into();
-        let new_point = point * scalar;
-        let new_pk: PublicKey = new_point.try_into()?;
-        Ok(BlindedKey(new_pk))
+        Ok(BlindedKey(crate::bdhke::sign(&self.0, &sk)?))
+    }
+
+    // Sign as the mint, along with a NUT-12 proof that the same key `a` was used as for `A`
+    pub fn blinded_key_with_dleq(
+        &self,
+        sk: &SecretKey,
+    ) -> Result<(BlindedKey, Dleq), crate::Error> {
+        let blinded_key = self.blinded_key(sk.clone())?;
+        let nonce = SecretKey::random(&mut rand::thread_rng());
+        let r1 = nonce.public_key();
+        let r2: PublicKey = (self.0.to_projective() * *nonce.to_nonzero_scalar()).try_into()?;
+        let e = hash_e(&[&r1, &r2, &sk.public_key(), &blinded_key.0]);
+        let s = *nonce.to_nonzero_scalar() + e * *sk.to_nonzero_scalar();
+        Ok((blinded_key, Dleq { e, s }))
     }
 
     pub fn from_hex(data: &str) -> Result<BlindedMessage, crate::Error> {
         let data = hex::decode(data)?;
-        let key = PublicKey::from_sec1_bytes(&data)?;
+        let key = parse_point(&data)?;
         Ok(BlindedMessage(key))
     }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_compressed_bytes())
+    }
+
+    pub fn to_compressed_bytes(&self) -> [u8; 33] {
+        compressed_bytes(&self.0)
+    }
+
+    pub fn from_compressed_bytes(data: &[u8; 33]) -> Result<BlindedMessage, crate::Error> {
+        Ok(BlindedMessage(parse_point(data)?))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, AsRef, Into, From)]
 pub struct BlindedKey(PublicKey);
 
 impl BlindedKey {
+    pub fn unblind(
+        &self,
+        blinding_factor: &SecretKey,
+        mint_key: &PublicKey,
+    ) -> Result<UnblindedKey, crate::Error> {
+        Ok(UnblindedKey(crate::bdhke::unblind(
+            &self.0,
+            blinding_factor,
+            mint_key,
+        )?))
+    }
+
     pub fn from_hex(data: &str) -> Result<BlindedKey, crate::Error> {
         let data = hex::decode(data)?;
-        let key = PublicKey::from_sec1_bytes(&data)?;
+        let key = parse_point(&data)?;
         Ok(BlindedKey(key))
     }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_compressed_bytes())
+    }
+
+    pub fn to_compressed_bytes(&self) -> [u8; 33] {
+        compressed_bytes(&self.0)
+    }
+
+    pub fn from_compressed_bytes(data: &[u8; 33]) -> Result<BlindedKey, crate::Error> {
+        Ok(BlindedKey(parse_point(data)?))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, AsRef, Into, From)]
+pub struct UnblindedKey(PublicKey);
+
+impl UnblindedKey {
+    pub fn from_hex(data: &str) -> Result<UnblindedKey, crate::Error> {
+        let data = hex::decode(data)?;
+        let key = parse_point(&data)?;
+        Ok(UnblindedKey(key))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_compressed_bytes())
+    }
+
+    pub fn to_compressed_bytes(&self) -> [u8; 33] {
+        compressed_bytes(&self.0)
+    }
+
+    pub fn from_compressed_bytes(data: &[u8; 33]) -> Result<UnblindedKey, crate::Error> {
+        Ok(UnblindedKey(parse_point(data)?))
+    }
+}
+
+// A mint's private key for one amount
+#[derive(Debug, Clone, PartialEq, Eq, AsRef, Into, From)]
+pub struct MintKey(SecretKey);
+
+impl MintKey {
+    pub fn public_key(&self) -> PublicKey {
+        self.0.public_key()
+    }
+
+    // C == k * hash_to_curve(secret)
+    pub fn verify(&self, secret: &Secret, unblinded_key: &UnblindedKey) -> bool {
+        crate::bdhke::verify(&secret.hash_to_curve(), &unblinded_key.0, &self.0)
+    }
+
+    // Sign outputs for issuance, each with a DLEQ proof. The keyset must hold this key for each
+    // output's amount.
+    pub fn sign_outputs(
+        &self,
+        outputs: &[crate::BlindedMessage],
+        keyset: &crate::Keyset,
+    ) -> Result<Vec<crate::BlindedSignature>, crate::Error> {
+        let public_key = self.public_key();
+        outputs
+            .iter()
+            .map(|output| {
+                if keyset.keys.get(&output.amount) != Some(&public_key) {
+                    return Err(crate::Error::UnknownAmount(output.amount));
+                }
+                let blinded_message = BlindedMessage(parse_point(&output.blinded_message)?);
+                let (blinded_key, dleq) = blinded_message.blinded_key_with_dleq(&self.0)?;
+                Ok(crate::BlindedSignature {
+                    id: Some(keyset.id.clone()),
+                    amount: output.amount,
+                    blinded_key: blinded_key.to_compressed_bytes().to_vec(),
+                    dleq: Some(crate::BlindedSignatureDleq {
+                        e: dleq.e().to_vec(),
+                        s: dleq.s().to_vec(),
+                    }),
+                })
+            })
+            .collect()
+    }
+
+    // Like verify for each item, but with the scalar taken once and points compared in
+    // projective form, skipping a field inversion per item
+    pub fn verify_batch(&self, items: &[(Secret, UnblindedKey)]) -> Vec<bool> {
+        let k = *self.0.to_nonzero_scalar();
+        items
+            .iter()
+            .map(|(secret, unblinded_key)| {
+                secret.hash_to_curve().to_projective() * k == unblinded_key.0.to_projective()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dleq {
+    e: Scalar,
+    s: Scalar,
+}
+
+impl Dleq {
+    pub fn from_bytes(e: &[u8], s: &[u8]) -> Result<Dleq, crate::Error> {
+        Ok(Dleq {
+            e: scalar_from_bytes(e)?,
+            s: scalar_from_bytes(s)?,
+        })
+    }
+
+    pub fn e(&self) -> [u8; 32] {
+        self.e.to_bytes().into()
+    }
+
+    pub fn s(&self) -> [u8; 32] {
+        self.s.to_bytes().into()
+    }
+
+    // Verify the proof on a blinded signature, given the blinded message it signed
+    pub fn verify(
+        &self,
+        mint_key: &PublicKey,
+        blinded_message: &BlindedMessage,
+        blinded_key: &BlindedKey,
+    ) -> bool {
+        // R1 = s*G - e*A, R2 = s*B_ - e*C_
+        let r1 = ProjectivePoint::GENERATOR * self.s - mint_key.to_projective() * self.e;
+        let r2 =
+            blinded_message.0.to_projective() * self.s - blinded_key.0.to_projective() * self.e;
+        let (Ok(r1), Ok(r2)) = (PublicKey::try_from(r1), PublicKey::try_from(r2)) else {
+            return false;
+        };
+        hash_e(&[&r1, &r2, mint_key, &blinded_key.0]) == self.e
+    }
+
+    // Verify the proof on an unblinded proof, by first reblinding it with the blinding factor r
+    pub fn verify_unblinded(
+        &self,
+        mint_key: &PublicKey,
+        secret: &Secret,
+        unblinded_key: &UnblindedKey,
+        blinding_factor: &SecretKey,
+    ) -> bool {
+        // B_ = Y + r*G, C_ = C + r*A
+        let r = *blinding_factor.to_nonzero_scalar();
+        let Ok(blinded_message) = secret.blinded_message(blinding_factor) else {
+            return false;
+        };
+        let blinded_key = unblinded_key.0.to_projective() + mint_key.to_projective() * r;
+        let Ok(blinded_key) = PublicKey::try_from(blinded_key) else {
+            return false;
+        };
+        self.verify(mint_key, &blinded_message, &BlindedKey(blinded_key))
+    }
+}
+
+// SEC1-encoded bytes as a point, failing with InvalidPoint for anything off the curve
+pub(crate) fn parse_point(bytes: &[u8]) -> Result<PublicKey, crate::Error> {
+    PublicKey::from_sec1_bytes(bytes).map_err(|_| crate::Error::InvalidPoint)
+}
+
+// NUT-11: P2PK data carries compressed keys, but Schnorr signatures are checked against the
+// x-only key, which drops the parity of Y
+pub fn to_xonly(pk: &PublicKey) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(&compressed_bytes(pk)[1..]);
+    bytes
+}
+
+// The key with this x coordinate and an even Y, as BIP-340 defines it
+pub fn from_xonly(xonly: &[u8; 32]) -> Result<PublicKey, crate::Error> {
+    let mut bytes = [0x02; 33];
+    bytes[1..].copy_from_slice(xonly);
+    parse_point(&bytes)
+}
+
+// NUT-12: SHA256 over the concatenated hex of the uncompressed points
+fn hash_e(points: &[&PublicKey]) -> Scalar {
+    let mut data = String::new();
+    for point in points {
+        data.push_str(&hex::encode(point.to_encoded_point(false).as_bytes()));
+    }
+    let hash = Sha256::digest(data.as_bytes());
+    <Scalar as Reduce<U256>>::reduce_bytes(&hash)
+}
+
+fn scalar_from_bytes(data: &[u8]) -> Result<Scalar, crate::Error> {
+    let bytes: [u8; 32] = data.try_into().map_err(|_| k256::elliptic_curve::Error)?;
+    Option::from(Scalar::from_repr(bytes.into())).ok_or(k256::elliptic_curve::Error.into())
+}
+
+fn compressed_bytes(key: &PublicKey) -> [u8; 33] {
+    let mut bytes = [0; 33];
+    bytes.copy_from_slice(key.to_encoded_point(true).as_bytes());
+    bytes
 }
 
 #[cfg(test)]
@@ -91,6 +418,117 @@ mod tests {
         assert_eq!(secret.as_ref().len(), 20);
     }
 
+    // Yields each of its blocks in turn as the bytes of a draw
+    struct RiggedRng(Vec<[u8; 32]>);
+
+    impl rand::RngCore for RiggedRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.copy_from_slice(&self.0.remove(0));
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl rand::CryptoRng for RiggedRng {}
+
+    #[test]
+    fn test_random_blinding_factor() {
+        let order: [u8; 32] =
+            hex::decode("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        let mut rng = RiggedRng(vec![[0; 32], order, [1; 32], [2; 32]]);
+        let r = random_blinding_factor(&mut rng);
+        assert_eq!(r.to_bytes()[..], [1; 32]);
+        assert_eq!(rng.0, vec![[2; 32]]);
+    }
+
+    #[test]
+    fn test_secret_bytes() {
+        let secret = Secret::random();
+        let copy = Secret::from_bytes(secret.expose_bytes().to_vec()).unwrap();
+        assert_eq!(copy, secret);
+        assert_eq!(copy.expose_bytes(), secret.as_ref());
+
+        assert!(Secret::from_bytes(vec![0; MAX_SECRET_LENGTH]).is_ok());
+        for length in [0, MAX_SECRET_LENGTH + 1] {
+            assert!(matches!(
+                Secret::from_bytes(vec![0; length]),
+                Err(crate::Error::InvalidSecretLength(n)) if n == length
+            ));
+        }
+
+        // Every constructor applies the same limits
+        assert!(matches!(
+            Secret::from_hex(""),
+            Err(crate::Error::InvalidSecretLength(0))
+        ));
+        assert!(Secret::from_hex(&"00".repeat(MAX_SECRET_LENGTH)).is_ok());
+        assert!(matches!(
+            Secret::from_hex(&"00".repeat(MAX_SECRET_LENGTH + 1)),
+            Err(crate::Error::InvalidSecretLength(n)) if n == MAX_SECRET_LENGTH + 1
+        ));
+        assert!(matches!(
+            Secret::from_utf8(""),
+            Err(crate::Error::InvalidSecretLength(0))
+        ));
+        assert!(matches!(
+            Secret::from_utf8(&"x".repeat(MAX_SECRET_LENGTH + 1)),
+            Err(crate::Error::InvalidSecretLength(n)) if n == MAX_SECRET_LENGTH + 1
+        ));
+        assert!("".parse::<Secret>().is_err());
+        assert!("x".repeat(MAX_SECRET_LENGTH + 1).parse::<Secret>().is_err());
+    }
+
+    #[test]
+    fn test_secret_debug() {
+        let secret = Secret::from_utf8("407915bc212be61a77e3e6d2aeb4c727").unwrap();
+        assert_eq!(format!("{secret:?}"), "Secret(<redacted>, 32)");
+    }
+
+    #[test]
+    fn test_secret_as_str() {
+        let secret = Secret(vec![0xde, 0xad, 0xbe, 0xef, 0xff]);
+        assert_eq!(secret.as_str(), "deadbeefff");
+
+        let secret =
+            Secret::from_utf8("407915bc212be61a77e3e6d2aeb4c727980bda51cd06a6afc29e2861768a7837")
+                .unwrap();
+        assert_eq!(
+            secret.as_str(),
+            "407915bc212be61a77e3e6d2aeb4c727980bda51cd06a6afc29e2861768a7837"
+        );
+
+        let well_known = r#"["P2PK",{"nonce":"859d4935c4907062a6297cf4e663e2835d90d97ecdd510745d32f6816323a41f","data":"0249098aa8b9d2fbec49ff8598feb17b592b986e62319a4fa488a3dc36387157a7","tags":[["sigflag","SIG_INPUTS"]]}]"#;
+        let secret = Secret::from_utf8(well_known).unwrap();
+        assert!(matches!(secret.as_str(), Cow::Borrowed(s) if s == well_known));
+    }
+
+    #[test]
+    fn test_secret_from_str() {
+        let secret: Secret = "0000000000000000000000000000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        assert_eq!(secret.as_ref().len(), 32);
+        assert_eq!(secret.as_ref()[31], 1);
+
+        let secret: Secret = "test_message".parse().unwrap();
+        assert_eq!(secret.as_ref(), b"test_message");
+        assert_eq!(secret, Secret::from_utf8("test_message").unwrap());
+    }
+
     // Hash to curve vectors: https://github.com/cashubtc/nuts/blob/main/test-vectors/00-tests.md
     #[test]
     fn test_hash_to_curve() {
@@ -110,12 +548,125 @@ mod tests {
         ];
         for (message, point) in vectors {
             let message = hex::decode(message).unwrap();
-            let secret = Secret::from(message);
+            let secret = Secret(message);
             let pk = PublicKey::from_sec1_bytes(&hex::decode(point).unwrap()).unwrap();
             assert_eq!(secret.hash_to_curve(), pk);
         }
     }
 
+    // Domain-separated vectors from the same file
+    #[test]
+    fn test_hash_to_curve_v2() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "024cce997d3b518f739663b757deaec95bcd9473c30a14ac2fd04023a739d1a725",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "022e7158e11c9506f1aa4248bf531298daa7febd6194f003edcd9b93ade6253acf",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "026cdbe15362df59cd1dd3c9c11de8aedac2106eca69236ecd9fbe117af897be4f",
+            ),
+        ];
+        for (message, point) in vectors {
+            let secret = Secret::from_hex(message).unwrap();
+            let pk = PublicKey::from_sec1_bytes(&hex::decode(point).unwrap()).unwrap();
+            assert_eq!(secret.hash_to_curve_v2(), pk);
+        }
+    }
+
+    // The all-zeros secret lands on the curve at counter 0, the other vectors only after the
+    // counter is incremented
+    #[test]
+    fn test_hash_to_curve_v2_counter() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "024cce997d3b518f739663b757deaec95bcd9473c30a14ac2fd04023a739d1a725",
+                0,
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "022e7158e11c9506f1aa4248bf531298daa7febd6194f003edcd9b93ade6253acf",
+                3,
+            ),
+            (
+                "000000000000000000000000000000000000000000000000000000000000000e",
+                "0208b4fc008956a75f97b56391ac9b08ff7aa9bb056450f914a4bda7bf0863b7d0",
+                7,
+            ),
+        ];
+        for (message, point, counter) in vectors {
+            let secret = Secret::from_hex(message).unwrap();
+            let pk = PublicKey::from_sec1_bytes(&hex::decode(point).unwrap()).unwrap();
+            assert_eq!(
+                crate::bdhke::hash_to_curve_v2_with_counter(secret.as_ref()),
+                (pk, counter)
+            );
+            assert_eq!(secret.hash_to_curve_v2(), pk);
+        }
+    }
+
+    #[test]
+    fn test_hash_to_curve_any_length() {
+        let secrets = [
+            Secret(vec![]),
+            Secret(vec![0x61]),
+            Secret::from_utf8(&"x".repeat(100)).unwrap(),
+        ];
+        for secret in &secrets {
+            for point in [secret.hash_to_curve(), secret.hash_to_curve_v2()] {
+                assert_eq!(compressed_bytes(&point)[0], 0x02);
+                assert_eq!(
+                    PublicKey::from_sec1_bytes(&compressed_bytes(&point)),
+                    Ok(point)
+                );
+            }
+            assert_ne!(secret.hash_to_curve(), secret.hash_to_curve_v2());
+        }
+
+        // Every byte counts, however long the secret
+        let longer = Secret::from_utf8(&"x".repeat(101)).unwrap();
+        assert_ne!(longer.hash_to_curve(), secrets[2].hash_to_curve());
+        assert_ne!(longer.hash_to_curve_v2(), secrets[2].hash_to_curve_v2());
+    }
+
+    #[test]
+    fn test_blind_point() {
+        let secret = Secret::from_utf8("test_message").unwrap();
+        let r = SecretKey::random(&mut rand::thread_rng());
+        assert_eq!(
+            blind_point(&secret.hash_to_curve(), &r).unwrap(),
+            secret.blinded_message(&r).unwrap()
+        );
+
+        // With Y = r*G, blinding by -r gives the point at infinity
+        let y = r.public_key();
+        let negated = SecretKey::from(-r.to_nonzero_scalar());
+        assert!(matches!(
+            blind_point(&y, &negated),
+            Err(crate::Error::EccArithmetic(_))
+        ));
+    }
+
+    #[test]
+    fn test_hash_to_curve_cache() {
+        let cache = HashToCurveCache::new(1);
+        let secret = Secret(b"test_message".to_vec());
+        let other = Secret(b"hello".to_vec());
+
+        assert_eq!(cache.hash_to_curve(&secret), secret.hash_to_curve());
+        assert_eq!(cache.hash_to_curve(&secret), secret.hash_to_curve());
+        assert_eq!(cache.len(), 1);
+
+        // Past capacity, points are still computed but no longer stored
+        assert_eq!(cache.hash_to_curve(&other), other.hash_to_curve());
+        assert_eq!(cache.len(), 1);
+    }
+
     #[test]
     fn test_blinding_message() {
         let vectors = [
@@ -132,7 +683,7 @@ mod tests {
         ];
 
         for (message, blinding_factor, point) in vectors {
-            let secret = Secret::from(message.bytes().collect::<Vec<_>>());
+            let secret = Secret(message.bytes().collect::<Vec<_>>());
             let sk = SecretKey::from_slice(&hex::decode(blinding_factor).unwrap()).unwrap();
             let result = secret.blinded_message(&sk);
             let expected: BlindedMessage = PublicKey::from_sec1_bytes(&hex::decode(point).unwrap())
@@ -178,4 +729,222 @@ mod tests {
 
         assert_eq!(bk, expected);
     }
+
+    #[test]
+    fn test_from_hex_errors() {
+        let err = BlindedKey::from_hex("02zz").unwrap_err();
+        assert!(matches!(err, crate::Error::HexConversion(_)));
+
+        // Valid hex, but x = 0 isn't on the curve
+        let err = BlindedKey::from_hex(
+            "020000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap_err();
+        assert!(matches!(err, crate::Error::InvalidPoint));
+        assert_eq!(err.to_string(), "Not a valid curve point");
+    }
+
+    #[test]
+    fn test_unblinding_key() {
+        // https://github.com/cashubtc/nuts/blob/main/test-vectors/00-tests.md
+        let bk = BlindedKey::from_hex(
+            "02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2",
+        )
+        .unwrap();
+        let keydata =
+            hex::decode("0000000000000000000000000000000000000000000000000000000000000001")
+                .unwrap();
+        let r = SecretKey::from_slice(&keydata).unwrap();
+        let mint_key = PublicKey::from_sec1_bytes(
+            &hex::decode("020000000000000000000000000000000000000000000000000000000000000001")
+                .unwrap(),
+        )
+        .unwrap();
+        let uk = bk.unblind(&r, &mint_key).unwrap();
+        let expected = UnblindedKey::from_hex(
+            "03c724d7e6a5443b39ac8acf11f40420adc4f99a02e7cc1b57703d9391f6d129cd",
+        )
+        .unwrap();
+
+        assert_eq!(uk, expected);
+    }
+
+    #[test]
+    fn test_compressed_bytes_round_trip() {
+        let hex = "02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2";
+
+        let bm = BlindedMessage::from_hex(hex).unwrap();
+        let bytes = bm.to_compressed_bytes();
+        assert_eq!(hex::encode(bytes), hex);
+        assert_eq!(BlindedMessage::from_compressed_bytes(&bytes).unwrap(), bm);
+
+        let bk = BlindedKey::from_hex(hex).unwrap();
+        let bytes = bk.to_compressed_bytes();
+        assert_eq!(BlindedKey::from_compressed_bytes(&bytes).unwrap(), bk);
+
+        let uk = UnblindedKey::from_hex(hex).unwrap();
+        let bytes = uk.to_compressed_bytes();
+        assert_eq!(UnblindedKey::from_compressed_bytes(&bytes).unwrap(), uk);
+
+        // Well-formed, but x = 0 isn't on the curve
+        let mut bytes = [0; 33];
+        bytes[0] = 0x02;
+        assert!(UnblindedKey::from_compressed_bytes(&bytes).is_err());
+    }
+
+    fn pubkey(data: &str) -> PublicKey {
+        PublicKey::from_sec1_bytes(&hex::decode(data).unwrap()).unwrap()
+    }
+
+    // hash_e vector: https://github.com/cashubtc/nuts/blob/main/test-vectors/12-tests.md
+    #[test]
+    fn test_hash_e() {
+        let r1 = pubkey("020000000000000000000000000000000000000000000000000000000000000001");
+        let r2 = pubkey("020000000000000000000000000000000000000000000000000000000000000001");
+        let k = pubkey("020000000000000000000000000000000000000000000000000000000000000001");
+        let c_ = pubkey("02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2");
+        let e = hash_e(&[&r1, &r2, &k, &c_]);
+        assert_eq!(
+            hex::encode(e.to_bytes()),
+            "a4dc034b74338c28c6bc3ea49731f2a24440fc7c4affc08b31a93fc9fbe6401e"
+        );
+    }
+
+    #[test]
+    fn test_dleq_round_trip() {
+        let sk = SecretKey::random(&mut rand::thread_rng());
+        let secret = Secret::random();
+        let r = SecretKey::random(&mut rand::thread_rng());
+        let bm = secret.blinded_message(&r).unwrap();
+        let (bk, dleq) = bm.blinded_key_with_dleq(&sk).unwrap();
+        assert!(dleq.verify(&sk.public_key(), &bm, &bk));
+
+        let uk = bk.unblind(&r, &sk.public_key()).unwrap();
+        assert!(dleq.verify_unblinded(&sk.public_key(), &secret, &uk, &r));
+
+        // A different mint key doesn't verify
+        let other = SecretKey::random(&mut rand::thread_rng());
+        assert!(!dleq.verify(&other.public_key(), &bm, &bk));
+
+        // Nor does a tampered proof
+        let mut s = dleq.s();
+        s[31] ^= 1;
+        let tampered = Dleq::from_bytes(&dleq.e(), &s).unwrap();
+        assert!(!tampered.verify(&sk.public_key(), &bm, &bk));
+    }
+
+    #[test]
+    fn test_xonly_round_trip() {
+        let even = PublicKey::from_sec1_bytes(
+            &hex::decode("02c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4")
+                .unwrap(),
+        )
+        .unwrap();
+        let xonly = to_xonly(&even);
+        assert_eq!(
+            hex::encode(xonly),
+            "c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4"
+        );
+        assert_eq!(from_xonly(&xonly).unwrap(), even);
+
+        // An odd key comes back as its even negation, with the same x
+        let odd = PublicKey::try_from(-even.to_projective()).unwrap();
+        assert_eq!(compressed_bytes(&odd)[0], 0x03);
+        assert_eq!(to_xonly(&odd), xonly);
+        assert_eq!(from_xonly(&to_xonly(&odd)).unwrap(), even);
+
+        assert!(matches!(
+            from_xonly(&[0; 32]),
+            Err(crate::Error::InvalidPoint)
+        ));
+    }
+
+    #[test]
+    fn test_mint_key_verify_batch() {
+        let key = MintKey::from(SecretKey::from_slice(&[7; 32]).unwrap());
+        let other = MintKey::from(SecretKey::from_slice(&[8; 32]).unwrap());
+        let sign = |key: &MintKey, secret: &Secret| {
+            let r = SecretKey::from_slice(&[1; 32]).unwrap();
+            secret
+                .blinded_message(&r)
+                .unwrap()
+                .blinded_key(key.0.clone())
+                .unwrap()
+                .unblind(&r, &key.public_key())
+                .unwrap()
+        };
+
+        let items: Vec<(Secret, UnblindedKey)> = (0..4)
+            .map(|i| {
+                let secret = Secret::from_utf8(&format!("secret {i}")).unwrap();
+                // Every other item is signed by the wrong key
+                let c = if i % 2 == 0 {
+                    sign(&key, &secret)
+                } else {
+                    sign(&other, &secret)
+                };
+                (secret, c)
+            })
+            .collect();
+        assert_eq!(key.verify_batch(&items), vec![true, false, true, false]);
+        let single: Vec<bool> = items.iter().map(|(s, c)| key.verify(s, c)).collect();
+        assert_eq!(single, key.verify_batch(&items));
+        assert!(key.verify_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_mint_key_sign_outputs() {
+        let key = MintKey::from(SecretKey::from_slice(&[7; 32]).unwrap());
+        let keys = std::collections::BTreeMap::from([(4, key.public_key())]);
+        let keyset = crate::Keyset {
+            id: crate::Keyset::derive_id(&keys).parse().unwrap(),
+            keys,
+        };
+        let secrets = [
+            Secret::from_utf8("first").unwrap(),
+            Secret::from_utf8("second").unwrap(),
+        ];
+        let factors: Vec<SecretKey> = (0..2)
+            .map(|_| SecretKey::random(&mut rand::thread_rng()))
+            .collect();
+        let outputs: Vec<crate::BlindedMessage> = secrets
+            .iter()
+            .zip(&factors)
+            .map(|(secret, r)| crate::BlindedMessage {
+                amount: 4,
+                id: Some(keyset.id.to_string()),
+                blinded_message: secret
+                    .blinded_message(r)
+                    .unwrap()
+                    .to_compressed_bytes()
+                    .to_vec(),
+            })
+            .collect();
+
+        let signatures = key.sign_outputs(&outputs, &keyset).unwrap();
+        assert_eq!(signatures.len(), 2);
+        for ((signature, output), (secret, r)) in signatures
+            .iter()
+            .zip(&outputs)
+            .zip(secrets.iter().zip(&factors))
+        {
+            assert_eq!(signature.id.as_ref(), Some(&keyset.id));
+            assert_eq!(signature.amount, 4);
+            assert!(signature.verify_dleq_received(output, &key.public_key()));
+            let unblinded = BlindedKey::from_hex(&hex::encode(&signature.blinded_key))
+                .unwrap()
+                .unblind(r, &key.public_key())
+                .unwrap();
+            assert!(key.verify(secret, &unblinded));
+        }
+
+        let outputs = [crate::BlindedMessage {
+            amount: 8,
+            ..outputs[0].clone()
+        }];
+        assert!(matches!(
+            key.sign_outputs(&outputs, &keyset),
+            Err(crate::Error::UnknownAmount(8))
+        ));
+    }
 }
@@ -0,0 +1,190 @@
+use bip32::{ChildNumber, XPrv};
+use k256::SecretKey;
+
+use crate::crypto::Secret;
+use crate::BlindedMessage;
+
+// NUT-13 derivation path: m/129372'/0'/{keyset_id}'/{counter}'/{0 = secret, 1 = r}
+const PURPOSE: u32 = 129372;
+const COIN_TYPE: u32 = 0;
+
+// Blinded messages along with the secrets and blinding factors needed to unblind their signatures
+pub type Outputs = (Vec<BlindedMessage>, Vec<Secret>, Vec<SecretKey>);
+
+#[derive(Clone)]
+pub struct DeterministicSecrets {
+    master: XPrv,
+}
+
+impl DeterministicSecrets {
+    pub fn new(seed: &[u8]) -> Result<DeterministicSecrets, crate::Error> {
+        Ok(DeterministicSecrets {
+            master: XPrv::new(seed)?,
+        })
+    }
+
+    // From a BIP-39 phrase, checking its words and checksum
+    pub fn from_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+    ) -> Result<DeterministicSecrets, crate::Error> {
+        let mnemonic = bip39::Mnemonic::parse(phrase)?;
+        DeterministicSecrets::new(&mnemonic.to_seed(passphrase))
+    }
+
+    pub fn secret(&self, keyset_id: &str, counter: u32) -> Result<Secret, crate::Error> {
+        let key = self.derive(keyset_id, counter, 0)?;
+        Secret::from_utf8(&hex::encode(key.to_bytes()))
+    }
+
+    pub fn blinding_factor(
+        &self,
+        keyset_id: &str,
+        counter: u32,
+    ) -> Result<SecretKey, crate::Error> {
+        self.derive(keyset_id, counter, 1)
+    }
+
+    // Derive the outputs for counters `start..start + count`. The blinded messages carry a
+    // placeholder amount of zero, since the amount is irrelevant when restoring.
+    pub fn outputs_for_range(
+        &self,
+        keyset_id: &str,
+        start: u32,
+        count: u32,
+    ) -> Result<Outputs, crate::Error> {
+        let mut outputs = Vec::with_capacity(count as usize);
+        let mut secrets = Vec::with_capacity(count as usize);
+        let mut factors = Vec::with_capacity(count as usize);
+        for counter in start..start + count {
+            let secret = self.secret(keyset_id, counter)?;
+            let r = self.blinding_factor(keyset_id, counter)?;
+            let bm = secret.blinded_message(&r)?;
+            outputs.push(BlindedMessage {
+                amount: 0,
+                id: Some(keyset_id.to_string()),
+                blinded_message: bm.as_ref().to_sec1_bytes().to_vec(),
+            });
+            secrets.push(secret);
+            factors.push(r);
+        }
+        Ok((outputs, secrets, factors))
+    }
+
+    fn derive(&self, keyset_id: &str, counter: u32, leaf: u32) -> Result<SecretKey, crate::Error> {
+        let path = [
+            ChildNumber::new(PURPOSE, true)?,
+            ChildNumber::new(COIN_TYPE, true)?,
+            ChildNumber::new(keyset_id_int(keyset_id)?, true)?,
+            ChildNumber::new(counter, true)?,
+            ChildNumber::new(leaf, false)?,
+        ];
+        let mut key = self.master.clone();
+        for child in path {
+            key = key.derive_child(child)?;
+        }
+        Ok(SecretKey::from_bytes(&key.to_bytes().into())?)
+    }
+}
+
+pub fn restore_outputs_for_range(
+    seed: &[u8],
+    keyset_id: &str,
+    start: u32,
+    count: u32,
+) -> Result<Outputs, crate::Error> {
+    DeterministicSecrets::new(seed)?.outputs_for_range(keyset_id, start, count)
+}
+
+// The keyset id, read as a big-endian integer, modulo 2^31 - 1
+fn keyset_id_int(keyset_id: &str) -> Result<u32, crate::Error> {
+    const MODULUS: u64 = (1 << 31) - 1;
+    let bytes = hex::decode(keyset_id)?;
+    let int = bytes
+        .iter()
+        .fold(0u64, |acc, b| (acc * 256 + *b as u64) % MODULUS);
+    Ok(int as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u8; 64] = [7; 64];
+    const KEYSET_ID: &str = "009a1f293253e41e";
+
+    #[test]
+    fn test_keyset_id_int() {
+        assert_eq!(keyset_id_int(KEYSET_ID).unwrap(), 864559728);
+    }
+
+    #[test]
+    fn test_restore_outputs_reproducible() {
+        let (outputs, secrets, factors) =
+            restore_outputs_for_range(&SEED, KEYSET_ID, 0, 10).unwrap();
+        let (outputs2, secrets2, factors2) =
+            restore_outputs_for_range(&SEED, KEYSET_ID, 0, 10).unwrap();
+        assert_eq!(outputs.len(), 10);
+        assert_eq!(outputs, outputs2);
+        assert_eq!(secrets, secrets2);
+        assert_eq!(factors, factors2);
+
+        // A sub-range reproduces the matching slice of the larger range
+        let (tail, _, _) = restore_outputs_for_range(&SEED, KEYSET_ID, 5, 5).unwrap();
+        assert_eq!(tail, outputs[5..]);
+    }
+
+    // https://github.com/cashubtc/nuts/blob/main/test-vectors/13-tests.md
+    const MNEMONIC: &str =
+        "half depart obvious quality work element tank gorilla view sugar picture humble";
+
+    #[test]
+    fn test_from_mnemonic() {
+        let secrets = DeterministicSecrets::from_mnemonic(MNEMONIC, "").unwrap();
+        let seed = hex::decode("dd44ee516b0647e80b488e8dcc56d736a148f15276bef588b37057476d4b2b25780d3688a32b37353d6995997842c0fd8b412475c891c16310471fbc86dcbda8").unwrap();
+        let from_seed = DeterministicSecrets::new(&seed).unwrap();
+        for counter in 0..2 {
+            assert_eq!(
+                secrets.secret(KEYSET_ID, counter).unwrap(),
+                from_seed.secret(KEYSET_ID, counter).unwrap()
+            );
+        }
+        assert_eq!(
+            secrets.secret(KEYSET_ID, 0).unwrap().as_ref(),
+            b"485875df74771877439ac06339e284c3acfcd9be7abf3bc20b516faeadfe77ae"
+        );
+        assert_eq!(
+            hex::encode(secrets.blinding_factor(KEYSET_ID, 1).unwrap().to_bytes()),
+            "967d5232515e10b81ff226ecf5a9e2e2aff92d66ebc3edf0987eb56357fd6248"
+        );
+
+        // The passphrase is part of the seed
+        let other = DeterministicSecrets::from_mnemonic(MNEMONIC, "passphrase").unwrap();
+        assert_ne!(
+            other.secret(KEYSET_ID, 0).unwrap(),
+            secrets.secret(KEYSET_ID, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_mnemonic() {
+        let bad_checksum = MNEMONIC.replace("humble", "picture");
+        for phrase in [
+            bad_checksum.as_str(),
+            "half depart obvious",
+            "not a real mnemonic at all",
+        ] {
+            assert!(matches!(
+                DeterministicSecrets::from_mnemonic(phrase, ""),
+                Err(crate::Error::Mnemonic(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_restore_outputs_differ_by_keyset() {
+        let (outputs, _, _) = restore_outputs_for_range(&SEED, KEYSET_ID, 0, 3).unwrap();
+        let (other, _, _) = restore_outputs_for_range(&SEED, "00ad268c4d1f5826", 0, 3).unwrap();
+        assert_ne!(outputs, other);
+    }
+}
@@ -0,0 +1,191 @@
+use k256::{PublicKey, SecretKey};
+
+use crate::crypto::{self, BlindedKey, Dleq, Secret, UnblindedKey};
+use crate::{BlindedMessage, BlindedSignature, KeysetCache, MintConnector, Proof, Token};
+
+// NUT-12 has two checks on the same e and s. A signature received from the mint is checked against
+// the B_ the wallet sent and the C_ that came back, which are all public points. Once unblinded,
+// only Y and C remain, so B_ and C_ have to be rebuilt from the blinding factor r: the wallet that
+// blinded the output keeps r, and passes it along in the token when sending. A proof without r
+// can't be checked at all.
+impl BlindedSignature {
+    // False when the signature carries no DLEQ proof or it doesn't verify for this output
+    pub fn verify_dleq_received(&self, output: &BlindedMessage, mint_key: &PublicKey) -> bool {
+        let Some(dleq) = &self.dleq else {
+            return false;
+        };
+        let (Ok(proof), Ok(b), Ok(c)) = (
+            Dleq::from_bytes(&dleq.e, &dleq.s),
+            PublicKey::from_sec1_bytes(&output.blinded_message),
+            PublicKey::from_sec1_bytes(&self.blinded_key),
+        ) else {
+            return false;
+        };
+        proof.verify(
+            mint_key,
+            &crypto::BlindedMessage::from(b),
+            &BlindedKey::from(c),
+        )
+    }
+}
+
+impl Proof {
+    // False when the proof carries no DLEQ proof or it doesn't verify against the mint key.
+    // The proof is checked by reblinding the secret and C with r, so an r that doesn't belong
+    // to this proof yields a different B_ and C_ than the mint's challenge commits to and fails.
+    pub fn verify_dleq_own(&self, mint_key: &PublicKey) -> bool {
+        let Some(dleq) = &self.dleq else {
+            return false;
+        };
+        let (Ok(proof), Ok(r), Ok(c), Ok(secret)) = (
+            Dleq::from_bytes(&dleq.e, &dleq.s),
+            SecretKey::from_slice(&dleq.r),
+            PublicKey::from_sec1_bytes(&self.unblinded_key),
+            Secret::from_utf8(&self.secret),
+        ) else {
+            return false;
+        };
+        proof.verify_unblinded(mint_key, &secret, &UnblindedKey::from(c), &r)
+    }
+}
+
+impl Token {
+    // Verify every proof's DLEQ against its keyset, stopping at the first failing proof. Proofs
+    // are indexed in order across all of the token's mints.
+    pub fn verify_all_dleq<C: MintConnector>(
+        &self,
+        cache: &KeysetCache<C>,
+    ) -> Result<(), crate::Error> {
+        let proofs = self.token.iter().flat_map(|t| t.proofs.as_ref());
+        for (index, proof) in proofs.enumerate() {
+            let id = proof
+                .id
+                .as_deref()
+                .ok_or(crate::Error::MissingKeysetId(vec![index]))?;
+            if proof.dleq.is_none() {
+                return Err(crate::Error::MissingDleq(index));
+            }
+            let mint_key = cache.mint_key(id, proof.amount)?;
+            if !proof.verify_dleq_own(&mint_key) {
+                return Err(crate::Error::InvalidDleq(index));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMint;
+    use crate::MintToken;
+
+    fn token(mint: &MockMint) -> Token {
+        Token {
+            token: vec![MintToken {
+                mint: "https://8333.space:3338".parse().unwrap(),
+                proofs: mint.mint_proofs(&[1, 4, 8]),
+                unit: None,
+                memo: None,
+            }],
+            memo: None,
+            unit: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_all_dleq() {
+        let mint = MockMint::new("mint");
+        let token = token(&mint);
+        let cache = KeysetCache::new(&mint);
+        assert!(token.verify_all_dleq(&cache).is_ok());
+    }
+
+    #[test]
+    fn test_verify_all_dleq_invalid() {
+        let mint = MockMint::new("mint");
+        let mut token = token(&mint);
+        let proofs = token.token[0].proofs.as_mut();
+        proofs[1].dleq.as_mut().unwrap().s[31] ^= 1;
+        let cache = KeysetCache::new(&mint);
+        assert!(matches!(
+            token.verify_all_dleq(&cache),
+            Err(crate::Error::InvalidDleq(1))
+        ));
+    }
+
+    #[test]
+    fn test_verify_all_dleq_missing() {
+        let mint = MockMint::new("mint");
+        let mut token = token(&mint);
+        token.token[0].proofs.as_mut()[2].dleq = None;
+        let cache = KeysetCache::new(&mint);
+        assert!(matches!(
+            token.verify_all_dleq(&cache),
+            Err(crate::Error::MissingDleq(2))
+        ));
+    }
+
+    #[test]
+    fn test_verify_all_dleq_wrong_mint() {
+        let mint = MockMint::new("mint");
+        let other = MockMint::new("other");
+        let token = token(&mint);
+        let cache = KeysetCache::new(&other);
+        assert!(matches!(
+            token.verify_all_dleq(&cache),
+            Err(crate::Error::UnknownKeyset(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_dleq_r() {
+        let mint = MockMint::new("mint");
+        let mut proofs = mint.mint_proofs(&[4, 4]);
+        let key = mint.keyset.keys[&4];
+        let proofs = proofs.as_mut();
+        assert!(proofs.iter().all(|p| p.verify_dleq_own(&key)));
+
+        // Each proof's r belongs to the other
+        let r0 = proofs[0].dleq.as_ref().unwrap().r.clone();
+        let r1 = std::mem::replace(&mut proofs[1].dleq.as_mut().unwrap().r, r0);
+        proofs[0].dleq.as_mut().unwrap().r = r1;
+        assert!(!proofs[0].verify_dleq_own(&key));
+        assert!(!proofs[1].verify_dleq_own(&key));
+
+        proofs[0].dleq.as_mut().unwrap().r = vec![0; 32];
+        assert!(!proofs[0].verify_dleq_own(&key));
+    }
+
+    #[test]
+    fn test_verify_dleq_received() {
+        let mint = MockMint::new("mint");
+        let key = mint.keyset.keys[&8];
+        let (mut outputs, _, _) = crate::blank_outputs(2, &mint.keyset.id).unwrap();
+        for output in &mut outputs {
+            output.amount = 8;
+        }
+        let signature = mint.sign(&outputs[0]);
+        assert!(signature.verify_dleq_received(&outputs[0], &key));
+        assert!(!signature.verify_dleq_received(&outputs[1], &key));
+        assert!(!signature.verify_dleq_received(&outputs[0], &mint.keyset.keys[&4]));
+
+        let mut signature = signature;
+        signature.dleq = None;
+        assert!(!signature.verify_dleq_received(&outputs[0], &key));
+    }
+
+    #[test]
+    fn test_verify_dleq_own() {
+        let mint = MockMint::new("mint");
+        let mut proof = mint.mint_proofs(&[8]).as_ref()[0].clone();
+        let key = mint.keyset.keys[&8];
+        assert!(proof.verify_dleq_own(&key));
+
+        // Without r, e and s alone say nothing about the unblinded proof
+        let dleq = proof.dleq.take().unwrap();
+        assert!(!proof.verify_dleq_own(&key));
+        proof.dleq = Some(crate::ProofDleq { r: vec![], ..dleq });
+        assert!(!proof.verify_dleq_own(&key));
+    }
+}
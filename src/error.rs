@@ -2,18 +2,251 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Token V3 format")]
-    TokenV3(Option<Box<dyn std::error::Error>>),
+    #[error("Token V3 format{}", cause(.0))]
+    TokenV3(#[source] Option<Box<dyn std::error::Error>>),
+
+    #[error("Token V4 format{}", cause(.0))]
+    TokenV4(#[source] Option<Box<dyn std::error::Error>>),
+
+    #[error("Payment request format{}", cause(.0))]
+    PaymentRequest(#[source] Option<Box<dyn std::error::Error>>),
+
+    #[error("Invalid mint URL {0}: must be http or https with a host")]
+    InvalidMintUrl(String),
+
+    #[error("Token is {length} bytes, over the limit of {max}")]
+    TokenTooLarge { length: usize, max: usize },
+
+    #[error("Mint {0} is not allowed")]
+    MintNotAllowed(url::Url),
+
+    #[error("Token V4 requires exactly one mint, found {0}")]
+    TokenV4MintCount(usize),
+
+    #[error("Token V4 requires a keyset id on every proof, missing on proofs {0:?}")]
+    MissingKeysetId(Vec<usize>),
+
+    #[error("Invalid bolt11 invoice: {0}")]
+    Bolt11(#[from] lightning_invoice::ParseOrSemanticError),
+
+    #[error("Invalid bolt12 offer")]
+    InvalidOffer,
+
+    #[error("Mint doesn't support melting with {0}")]
+    UnsupportedMeltMethod(crate::MeltMethod),
+
+    #[error("Invoice has no amount")]
+    AmountlessInvoice,
+
+    #[error("Offer has no amount in bitcoin, either leaving it to the payer or pricing in fiat")]
+    AmountlessOffer,
+
+    #[error("Secret of {0} bytes is empty or too long")]
+    InvalidSecretLength(usize),
+
+    #[error("Amount {amount} is outside the mint's limits of {min:?} to {max:?}")]
+    AmountOutOfRange {
+        amount: u64,
+        min: Option<u64>,
+        max: Option<u64>,
+    },
+
+    #[error("Fee reserve of {reserve} is too high for a payment of {amount}")]
+    FeeReserveTooHigh { reserve: u64, amount: u64 },
+
+    #[error("Request already names an amount")]
+    RequestHasAmount,
+
+    #[error("Insufficient funds: need {needed}, have {available}")]
+    InsufficientFunds { needed: u64, available: u64 },
+
+    #[error("Not a valid curve point")]
+    InvalidPoint,
 
     #[error("ECC arithmetic error: {0}")]
     EccArithmetic(#[from] k256::elliptic_curve::Error),
 
     #[error("Hex conversion")]
     HexConversion(#[from] hex::FromHexError),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Mint error {code}: {detail}")]
+    Mint { code: u64, detail: String },
+
+    #[cfg(feature = "client")]
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Rate limited by the mint, retry after {retry_after:?}")]
+    RateLimited {
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("Invalid HTTP header {0}")]
+    InvalidHeader(String),
+
+    #[error("HTTP status {0}")]
+    HttpStatus(u16),
+
+    #[error("Quote {quote} is {state:?}, not paid")]
+    QuoteNotPaid {
+        quote: String,
+        state: crate::QuoteState,
+    },
+
+    #[error("Melt for quote {0} failed, the payment wasn't made")]
+    MeltFailed(String),
+
+    #[error("Melt for quote {0} was reported paid with a preimage that doesn't match the invoice")]
+    InvalidPreimage(String),
+
+    #[error("Quote {quote} expired at {expiry}")]
+    QuoteExpired { quote: String, expiry: u64 },
+
+    #[error("Invalid keyset {id}: {reason}")]
+    InvalidKeyset { id: String, reason: String },
+
+    #[error("Invalid keyset id {0:?}")]
+    InvalidKeysetId(String),
+
+    #[error("Invalid response from mint: {0}")]
+    InvalidResponse(String),
+
+    #[error("Unknown keyset {0}")]
+    UnknownKeyset(String),
+
+    #[error("Unit of keyset {0:?} is unknown")]
+    UnknownUnit(String),
+
+    #[error("Expected a signature from keyset {expected}, got one from {found}")]
+    UnexpectedKeyset { expected: String, found: String },
+
+    #[error("Keyset {0} is listed more than once")]
+    DuplicateKeyset(String),
+
+    #[error("No active keyset for unit {0}")]
+    NoActiveKeyset(crate::Unit),
+
+    #[error("More than one active keyset for unit {0}")]
+    AmbiguousActiveKeyset(crate::Unit),
+
+    #[error("Output {0} duplicates an earlier output")]
+    DuplicateOutput(usize),
+
+    #[error("Token contains the same secret more than once")]
+    DuplicateSecret,
+
+    #[error("Swap has no outputs")]
+    NoOutputs,
+
+    #[error("Output {index} has amount {amount}, which isn't a power of two")]
+    InvalidOutputAmount { index: usize, amount: u64 },
+
+    #[error("Output {index} has amount {amount}, more than the keyset's largest of 2^{max_order}")]
+    AmountExceedsMaxOrder {
+        index: usize,
+        amount: u64,
+        max_order: u32,
+    },
+
+    #[error("Amount needs {count} outputs, more than the limit of {max}")]
+    TooManyOutputs { count: u64, max: usize },
+
+    #[error("Proofs for {mint} mix units {units:?}")]
+    MixedUnits {
+        mint: url::Url,
+        units: Vec<crate::Unit>,
+    },
+
+    #[error("Proof {0} has no DLEQ proof")]
+    MissingDleq(usize),
+
+    #[error("Proof {0} has an invalid DLEQ proof")]
+    InvalidDleq(usize),
+
+    #[error("Proof is locked but has no witness")]
+    MissingWitness,
+
+    #[error("Proof's witness doesn't satisfy its spending conditions")]
+    InvalidWitness,
+
+    #[error("{0:?} is not a hex-encoded 64-byte Schnorr signature")]
+    InvalidSignature(String),
+
+    #[error("Proof {0} is locked to conditions the wallet can't satisfy")]
+    CannotUnlock(usize),
+
+    #[error("Proof {0} is already spent")]
+    ProofSpent(usize),
+
+    #[error("Keyset has no key for amount {0}")]
+    UnknownAmount(u64),
+
+    #[error("Key derivation error: {0}")]
+    KeyDerivation(#[from] bip32::Error),
+
+    #[error("Invalid mnemonic: {0}")]
+    Mnemonic(#[from] bip39::Error),
+}
+
+// ": cause" for errors wrapping an optional inner error
+fn cause(err: &Option<Box<dyn std::error::Error>>) -> String {
+    err.as_ref().map(|e| format!(": {e}")).unwrap_or_default()
 }
 
 impl Error {
     pub(crate) fn map_tokenv3(err: impl std::error::Error + 'static) -> Error {
         Error::TokenV3(Some(Box::new(err)))
     }
+
+    pub(crate) fn map_tokenv4(err: impl std::error::Error + 'static) -> Error {
+        Error::TokenV4(Some(Box::new(err)))
+    }
+
+    pub(crate) fn map_payment_request(err: impl std::error::Error + 'static) -> Error {
+        Error::PaymentRequest(Some(Box::new(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn test_wrapped_source() {
+        let inner = hex::FromHexError::OddLength;
+        let err = Error::map_tokenv3(inner);
+        assert!(err.source().is_some());
+        assert_eq!(err.to_string(), format!("Token V3 format: {inner}"));
+
+        let err = Error::TokenV3(None);
+        assert!(err.source().is_none());
+        assert_eq!(err.to_string(), "Token V3 format");
+
+        let err = Error::map_tokenv4(inner);
+        assert_eq!(err.source().unwrap().to_string(), inner.to_string());
+    }
+
+    #[test]
+    fn test_from_conversions() {
+        fn parse(json: &str) -> Result<crate::Proof, Error> {
+            Ok(serde_json::from_str(json)?)
+        }
+        fn decode(data: &str) -> Result<Vec<u8>, Error> {
+            use base64::Engine;
+            Ok(base64::engine::general_purpose::URL_SAFE.decode(data)?)
+        }
+
+        let err = parse(r#"{"amount":"#).unwrap_err();
+        assert!(matches!(err, Error::Json(_)));
+        assert!(err.source().is_some());
+        assert!(matches!(decode("not base64!"), Err(Error::Base64(_))));
+    }
 }
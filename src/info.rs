@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Unit;
+
+// NUT-06: what a mint says about itself at /v1/info. Only the parts the wallet uses are parsed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MintInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contact: Vec<Contact>,
+
+    #[serde(default)]
+    pub nuts: Nuts,
+}
+
+// A way to reach the mint's operator, e.g. method "email" with info "contact@me.com". Older mints
+// send each contact as a [method, info] pair rather than an object, which reads the same.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "ContactRepr")]
+pub struct Contact {
+    pub method: String,
+    pub info: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ContactRepr {
+    Object { method: String, info: String },
+    Pair(String, String),
+}
+
+impl From<ContactRepr> for Contact {
+    fn from(contact: ContactRepr) -> Contact {
+        match contact {
+            ContactRepr::Object { method, info } | ContactRepr::Pair(method, info) => {
+                Contact { method, info }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Nuts {
+    #[serde(rename = "4", default, skip_serializing_if = "Option::is_none")]
+    pub nut04: Option<MethodSettings>,
+
+    #[serde(rename = "5", default, skip_serializing_if = "Option::is_none")]
+    pub nut05: Option<MethodSettings>,
+}
+
+// The payment methods a mint accepts for minting (NUT-04) or melting (NUT-05)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MethodSettings {
+    #[serde(default)]
+    pub methods: Vec<MethodSetting>,
+
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MethodSetting {
+    pub method: String,
+    pub unit: Unit,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_amount: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_amount: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<MethodOptions>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MethodOptions {
+    // Whether the mint pays amountless requests for an amount the wallet gives (NUT-05)
+    #[serde(default)]
+    pub amountless: bool,
+}
+
+// The bounds a mint puts on the amount of one quote, in the method's unit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AmountLimits {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl AmountLimits {
+    pub fn check(&self, amount: u64) -> Result<(), crate::Error> {
+        if self.min.is_some_and(|min| amount < min) || self.max.is_some_and(|max| amount > max) {
+            return Err(crate::Error::AmountOutOfRange {
+                amount,
+                min: self.min,
+                max: self.max,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl MethodSettings {
+    pub fn limits(&self, method: &str, unit: &Unit) -> Option<AmountLimits> {
+        self.methods
+            .iter()
+            .find(|m| m.method == method && &m.unit == unit)
+            .map(|m| AmountLimits {
+                min: m.min_amount,
+                max: m.max_amount,
+            })
+    }
+
+    pub fn supports(&self, method: &str, unit: &Unit) -> bool {
+        !self.disabled
+            && self
+                .methods
+                .iter()
+                .any(|m| m.method == method && &m.unit == unit)
+    }
+}
+
+impl MintInfo {
+    pub fn supports_mint_method(&self, method: &str, unit: &Unit) -> bool {
+        self.nuts
+            .nut04
+            .as_ref()
+            .is_some_and(|s| s.supports(method, unit))
+    }
+
+    pub fn supports_melt_method(&self, method: &str, unit: &Unit) -> bool {
+        self.nuts
+            .nut05
+            .as_ref()
+            .is_some_and(|s| s.supports(method, unit))
+    }
+
+    // None when the mint doesn't list the method for the unit
+    pub fn mint_limits(&self, method: &str, unit: &Unit) -> Option<AmountLimits> {
+        self.nuts.nut04.as_ref()?.limits(method, unit)
+    }
+
+    pub fn melt_limits(&self, method: &str, unit: &Unit) -> Option<AmountLimits> {
+        self.nuts.nut05.as_ref()?.limits(method, unit)
+    }
+
+    pub fn supports_amountless_melt(&self, method: &str, unit: &Unit) -> bool {
+        self.nuts.nut05.as_ref().is_some_and(|s| {
+            !s.disabled
+                && s.methods.iter().any(|m| {
+                    m.method == method
+                        && &m.unit == unit
+                        && m.options.as_ref().is_some_and(|o| o.amountless)
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO: &str = r#"{
+        "name": "Bob's Cashu mint",
+        "pubkey": "0283bf290884eed3a7ca2663fc0260de2e2064d6b355ea13f98dec004b7a7ead99",
+        "version": "Nutshell/0.15.0",
+        "description": "The short mint description",
+        "contact": [{"method": "email", "info": "contact@me.com"}],
+        "nuts": {
+            "4": {
+                "methods": [{"method": "bolt11", "unit": "sat", "min_amount": 0, "max_amount": 10000}],
+                "disabled": false
+            },
+            "5": {
+                "methods": [
+                    {"method": "bolt11", "unit": "sat", "min_amount": 100, "max_amount": 10000},
+                    {"method": "bolt11", "unit": "usd"}
+                ]
+            },
+            "7": {"supported": true}
+        }
+    }"#;
+
+    #[test]
+    fn test_supports_method() {
+        let info: MintInfo = serde_json::from_str(INFO).unwrap();
+        assert_eq!(info.name.as_deref(), Some("Bob's Cashu mint"));
+        assert!(info.supports_mint_method("bolt11", &Unit::Sat));
+        assert!(!info.supports_mint_method("bolt11", &Unit::Usd));
+        assert!(!info.supports_mint_method("bolt12", &Unit::Sat));
+        assert!(info.supports_melt_method("bolt11", &Unit::Sat));
+        assert!(info.supports_melt_method("bolt11", &Unit::Usd));
+        assert!(!info.supports_amountless_melt("bolt11", &Unit::Sat));
+
+        let amountless = r#"{"nuts": {"5": {"methods": [{"method": "bolt11", "unit": "sat", "options": {"amountless": true}}]}}}"#;
+        let info: MintInfo = serde_json::from_str(amountless).unwrap();
+        assert!(info.supports_amountless_melt("bolt11", &Unit::Sat));
+        assert!(!info.supports_amountless_melt("bolt12", &Unit::Sat));
+    }
+
+    #[test]
+    fn test_limits() {
+        let info: MintInfo = serde_json::from_str(INFO).unwrap();
+        let limits = info.mint_limits("bolt11", &Unit::Sat).unwrap();
+        assert_eq!(
+            limits,
+            AmountLimits {
+                min: Some(0),
+                max: Some(10000)
+            }
+        );
+        assert!(limits.check(10000).is_ok());
+        assert!(matches!(
+            limits.check(10001),
+            Err(crate::Error::AmountOutOfRange {
+                amount: 10001,
+                max: Some(10000),
+                ..
+            })
+        ));
+        assert_eq!(info.mint_limits("bolt11", &Unit::Usd), None);
+
+        let limits = info.melt_limits("bolt11", &Unit::Sat).unwrap();
+        assert!(limits.check(99).is_err());
+        assert!(limits.check(100).is_ok());
+        let unbounded = info.melt_limits("bolt11", &Unit::Usd).unwrap();
+        assert_eq!(unbounded, AmountLimits::default());
+        assert!(unbounded.check(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_contact() {
+        let email = Contact {
+            method: "email".to_string(),
+            info: "contact@me.com".to_string(),
+        };
+        let info: MintInfo = serde_json::from_str(INFO).unwrap();
+        assert_eq!(info.contact, vec![email.clone()]);
+
+        let legacy = r#"{"contact": [["email", "contact@me.com"], ["nostr", "npub1abc"]]}"#;
+        let info: MintInfo = serde_json::from_str(legacy).unwrap();
+        assert_eq!(info.contact[0], email);
+        assert_eq!(info.contact[1].method, "nostr");
+        assert_eq!(info.contact[1].info, "npub1abc");
+
+        // Always written in the object form
+        assert_eq!(
+            serde_json::to_value(&info.contact[0]).unwrap(),
+            serde_json::json!({"method": "email", "info": "contact@me.com"})
+        );
+        assert!(serde_json::from_str::<MintInfo>(r#"{"contact": [["email"]]}"#).is_err());
+    }
+
+    #[test]
+    fn test_supports_method_disabled() {
+        let mut info: MintInfo = serde_json::from_str(INFO).unwrap();
+        info.nuts.nut04.as_mut().unwrap().disabled = true;
+        assert!(!info.supports_mint_method("bolt11", &Unit::Sat));
+
+        let info: MintInfo = serde_json::from_str("{}").unwrap();
+        assert!(!info.supports_mint_method("bolt11", &Unit::Sat));
+        assert!(!info.supports_melt_method("bolt11", &Unit::Sat));
+    }
+}
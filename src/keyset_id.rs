@@ -0,0 +1,188 @@
+use std::{fmt, ops::Deref, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeysetIdVersion {
+    // 12 base64 characters, still found on old tokens
+    V0,
+
+    // "00" and 14 hex characters
+    V1,
+}
+
+// The version of a well-formed keyset id, or None for anything else
+pub fn keyset_id_version(id: &str) -> Option<KeysetIdVersion> {
+    if id.len() == 16 && id.starts_with("00") && id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Some(KeysetIdVersion::V1)
+    } else if id.len() == 12
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+    {
+        Some(KeysetIdVersion::V0)
+    } else {
+        None
+    }
+}
+
+// A keyset id of a known version. Version 00 ids are kept in lowercase, so ids read from hex and
+// from the bytes in a V4 token compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeysetId(String);
+
+impl KeysetId {
+    pub fn version(&self) -> KeysetIdVersion {
+        keyset_id_version(&self.0).unwrap_or(KeysetIdVersion::V0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // The form V4 tokens carry, which only version 00 ids have
+    pub fn to_bytes(&self) -> Result<Vec<u8>, crate::Error> {
+        match self.version() {
+            KeysetIdVersion::V1 => Ok(hex::decode(&self.0)?),
+            KeysetIdVersion::V0 => Err(crate::Error::InvalidKeysetId(self.0.clone())),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<KeysetId, crate::Error> {
+        hex::encode(bytes).parse()
+    }
+}
+
+impl FromStr for KeysetId {
+    type Err = crate::Error;
+
+    fn from_str(id: &str) -> Result<KeysetId, crate::Error> {
+        match keyset_id_version(id) {
+            Some(KeysetIdVersion::V1) => Ok(KeysetId(id.to_ascii_lowercase())),
+            Some(KeysetIdVersion::V0) => Ok(KeysetId(id.to_string())),
+            None => Err(crate::Error::InvalidKeysetId(id.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for KeysetId {
+    type Error = crate::Error;
+
+    fn try_from(id: String) -> Result<KeysetId, crate::Error> {
+        id.parse()
+    }
+}
+
+impl From<KeysetId> for String {
+    fn from(id: KeysetId) -> String {
+        id.0
+    }
+}
+
+impl fmt::Display for KeysetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Deref for KeysetId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for KeysetId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for KeysetId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<String> for KeysetId {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<KeysetId> for String {
+    fn eq(&self, other: &KeysetId) -> bool {
+        self == &other.0
+    }
+}
+
+impl PartialEq<KeysetId> for str {
+    fn eq(&self, other: &KeysetId) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<KeysetId> for &str {
+    fn eq(&self, other: &KeysetId) -> bool {
+        *self == other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let id: KeysetId = "009a1f293253e41e".parse().unwrap();
+        assert_eq!(id.version(), KeysetIdVersion::V1);
+        assert_eq!(id.to_string(), "009a1f293253e41e");
+        assert_eq!(
+            id.to_bytes().unwrap(),
+            hex::decode("009a1f293253e41e").unwrap()
+        );
+        assert_eq!(KeysetId::from_bytes(&id.to_bytes().unwrap()).unwrap(), id);
+
+        // Hex case doesn't make a different id
+        assert_eq!("009A1F293253E41E".parse::<KeysetId>().unwrap(), id);
+
+        let legacy: KeysetId = "I2yN+iRYfkzT".parse().unwrap();
+        assert_eq!(legacy.version(), KeysetIdVersion::V0);
+        assert_eq!(legacy, "I2yN+iRYfkzT");
+        assert!(matches!(
+            legacy.to_bytes(),
+            Err(crate::Error::InvalidKeysetId(_))
+        ));
+    }
+
+    #[test]
+    fn test_serde() {
+        let id: KeysetId = serde_json::from_str(r#""009a1f293253e41e""#).unwrap();
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""009a1f293253e41e""#);
+    }
+
+    #[test]
+    fn test_malformed() {
+        for id in [
+            "",
+            "abcd",
+            "009a1f293253e41",
+            "009a1f293253e41e00",
+            "019a1f293253e41e",
+            "009a1f293253e4zz",
+            "I2yN+iRYfk=T",
+        ] {
+            assert!(
+                matches!(
+                    id.parse::<KeysetId>(),
+                    Err(crate::Error::InvalidKeysetId(_))
+                ),
+                "{id}"
+            );
+            assert!(serde_json::from_str::<KeysetId>(&format!("{id:?}")).is_err());
+        }
+        assert!(KeysetId::from_bytes(&[0, 1, 2]).is_err());
+    }
+}
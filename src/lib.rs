@@ -1,11 +1,89 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 
+pub mod bdhke;
+
+#[cfg(feature = "std")]
+mod amount;
+#[cfg(feature = "std")]
+mod bolt11;
+#[cfg(feature = "std")]
+mod bolt12;
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "std")]
+mod connector;
+#[cfg(feature = "std")]
 pub mod crypto;
+#[cfg(feature = "std")]
+mod deterministic;
+#[cfg(feature = "std")]
+mod dleq;
+#[cfg(feature = "std")]
 mod error;
+#[cfg(feature = "std")]
+mod info;
+#[cfg(feature = "std")]
+mod keyset_id;
+#[cfg(all(test, feature = "std"))]
+mod mock;
+#[cfg(feature = "std")]
 mod models;
+#[cfg(feature = "std")]
+mod nut10;
+#[cfg(feature = "std")]
+mod nut11;
+#[cfg(feature = "std")]
+mod outputs;
+#[cfg(feature = "std")]
+mod payment_request;
+#[cfg(feature = "std")]
+mod signing;
+#[cfg(feature = "std")]
+mod store;
+#[cfg(feature = "std")]
+mod token_builder;
+#[cfg(feature = "std")]
+mod token_v4;
+#[cfg(feature = "std")]
+mod wallet;
 
+#[cfg(feature = "std")]
+pub use amount::*;
+#[cfg(feature = "std")]
+pub use bolt11::*;
+#[cfg(feature = "std")]
+pub use bolt12::*;
+#[cfg(feature = "client")]
+pub use client::*;
+#[cfg(feature = "std")]
+pub use connector::*;
+#[cfg(feature = "std")]
+pub use deterministic::*;
+#[cfg(feature = "std")]
 pub use error::*;
+#[cfg(feature = "std")]
+pub use info::*;
+#[cfg(feature = "std")]
+pub use keyset_id::*;
+#[cfg(feature = "std")]
 pub use models::*;
+#[cfg(feature = "std")]
+pub use nut10::*;
+#[cfg(feature = "std")]
+pub use nut11::*;
+#[cfg(feature = "std")]
+pub use outputs::*;
+#[cfg(feature = "std")]
+pub use payment_request::*;
+#[cfg(feature = "std")]
+pub use signing::*;
+#[cfg(feature = "std")]
+pub use store::*;
+#[cfg(feature = "std")]
+pub use token_builder::*;
+#[cfg(feature = "std")]
+pub use wallet::*;
 
 pub fn add(left: usize, right: usize) -> usize {
     left + right
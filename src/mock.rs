@@ -0,0 +1,614 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use k256::{PublicKey, SecretKey};
+use sha2::{Digest, Sha256};
+
+use crate::crypto;
+use crate::{
+    BlindedMessage, BlindedSignature, BlindedSignatureDleq, CheckStateRequest, CheckStateResponse,
+    KeysResponse, Keyset, KeysetId, KeysetInfo, KeysetResponse, KeysetsResponse, MeltMethod,
+    MeltQuote, MeltQuoteRequest, MeltRequest, MethodOptions, MethodSetting, MethodSettings,
+    MintConnector, MintInfo, MintQuote, MintQuoteRequest, MintRequest, MintResponse, Nuts, Proof,
+    ProofDleq, ProofState, Proofs, QuoteState, RestoreRequest, RestoreResponse, SigFlag, State,
+    SwapRequest, SwapResponse, Unit, WellKnownSecret,
+};
+
+// An in-memory mint for exercising wallet flows without a network
+pub(crate) struct MockMint {
+    pub keyset: Keyset,
+    keys: BTreeMap<u64, SecretKey>,
+
+    // Keysets rotated out, which still verify but no longer sign
+    inactive: Vec<(Keyset, BTreeMap<u64, SecretKey>)>,
+
+    signed: RefCell<Vec<(BlindedMessage, BlindedSignature)>>,
+    states: RefCell<HashMap<String, State>>,
+
+    // Witnesses of spent proofs, by Y
+    witnesses: RefCell<HashMap<String, String>>,
+
+    // Swap requests as they were received
+    pub swaps: RefCell<Vec<SwapRequest>>,
+
+    // Quotes and their amounts. Quotes are paid as soon as they're issued.
+    quotes: RefCell<HashMap<String, (u64, MintQuote)>>,
+    melt_quotes: RefCell<HashMap<String, MeltQuote>>,
+
+    pub input_fee_ppk: u64,
+
+    // The unit its keysets are listed in
+    pub unit: Unit,
+
+    // Whether melting to bolt12 offers is advertised
+    pub bolt12: bool,
+
+    // Whether paying amountless bolt11 invoices is advertised
+    pub amountless: bool,
+
+    // The largest amount advertised for minting and melting
+    pub max_amount: Option<u64>,
+
+    // Whether info requests fail, as on mints without NUT-06
+    pub no_info: bool,
+
+    // A keyset id to put on signatures in place of the real one
+    pub signature_id: Option<KeysetId>,
+
+    // Whether melts are left pending, as for a payment that hasn't settled. Melts that complete
+    // pay no Lightning fee, so all of the fee reserve comes back as change.
+    pub melt_pending: bool,
+
+    // Whether melt quotes carry no fee reserve, as from a mint paying over internal routes
+    pub fee_free: bool,
+
+    // The preimage paid melts report, PREIMAGE unless set otherwise
+    pub preimage: [u8; 32],
+
+    // Whether melt quotes leave out the request, as from older mints
+    pub omit_request: bool,
+
+    // Signatures left off the end of mint, swap and restore responses, as from a broken mint
+    pub missing_signatures: usize,
+
+    // The most outputs a mint or swap request may carry
+    pub max_outputs: Option<usize>,
+}
+
+impl MockMint {
+    pub fn new(name: &str) -> MockMint {
+        let (keyset, keys) = generate_keyset(name);
+        MockMint {
+            keyset,
+            keys,
+            inactive: Vec::new(),
+            signed: RefCell::new(Vec::new()),
+            states: RefCell::new(HashMap::new()),
+            witnesses: RefCell::new(HashMap::new()),
+            swaps: RefCell::new(Vec::new()),
+            quotes: RefCell::new(HashMap::new()),
+            melt_quotes: RefCell::new(HashMap::new()),
+            input_fee_ppk: 0,
+            unit: Unit::Sat,
+            bolt12: false,
+            amountless: false,
+            max_amount: None,
+            no_info: false,
+            signature_id: None,
+            melt_pending: false,
+            fee_free: false,
+            preimage: PREIMAGE,
+            omit_request: false,
+            missing_signatures: 0,
+            max_outputs: None,
+        }
+    }
+
+    // What get_info advertises, which the mock also checks requests against
+    fn info(&self) -> MintInfo {
+        let bolt11 = MethodSettings {
+            methods: vec![MethodSetting {
+                method: "bolt11".to_string(),
+                unit: self.unit.clone(),
+                min_amount: None,
+                max_amount: self.max_amount,
+                options: None,
+            }],
+            disabled: false,
+        };
+        let mut melt = bolt11.clone();
+        if self.amountless {
+            melt.methods[0].options = Some(MethodOptions { amountless: true });
+        }
+        if self.bolt12 {
+            melt.methods.push(MethodSetting {
+                method: "bolt12".to_string(),
+                ..bolt11.methods[0].clone()
+            });
+        }
+        MintInfo {
+            name: Some("mock".to_string()),
+            nuts: Nuts {
+                nut04: Some(bolt11),
+                nut05: Some(melt),
+            },
+            ..MintInfo::default()
+        }
+    }
+
+    // Sign with a new keyset from now on, keeping the current one to verify with
+    pub fn rotate_keyset(&mut self, name: &str) {
+        let (keyset, keys) = generate_keyset(name);
+        let old_keyset = std::mem::replace(&mut self.keyset, keyset);
+        let old_keys = std::mem::replace(&mut self.keys, keys);
+        self.inactive.push((old_keyset, old_keys));
+    }
+
+    // Keysets by id, active first. Proofs without an id are taken to be from the active one.
+    fn keyset_keys(&self, id: Option<&str>) -> Option<&BTreeMap<u64, SecretKey>> {
+        match id {
+            None => Some(&self.keys),
+            Some(id) if id == self.keyset.id => Some(&self.keys),
+            Some(id) => self
+                .inactive
+                .iter()
+                .find(|(keyset, _)| keyset.id == id)
+                .map(|(_, keys)| keys),
+        }
+    }
+
+    fn check_output_count(&self, outputs: &[BlindedMessage]) -> Result<(), crate::Error> {
+        match self.max_outputs {
+            Some(max) if outputs.len() > max => Err(mint_error(11000, "Too many outputs")),
+            _ => Ok(()),
+        }
+    }
+
+    fn sign_all(&self, outputs: &[BlindedMessage]) -> Vec<BlindedSignature> {
+        let count = outputs.len().saturating_sub(self.missing_signatures);
+        outputs[..count].iter().map(|o| self.sign(o)).collect()
+    }
+
+    pub fn sign(&self, output: &BlindedMessage) -> BlindedSignature {
+        let key = self.keys[&output.amount].clone();
+        let bm = crypto::BlindedMessage::from(
+            PublicKey::from_sec1_bytes(&output.blinded_message).unwrap(),
+        );
+        let (bk, dleq) = bm.blinded_key_with_dleq(&key).unwrap();
+        let signature = BlindedSignature {
+            id: Some(
+                self.signature_id
+                    .clone()
+                    .unwrap_or_else(|| self.keyset.id.clone()),
+            ),
+            amount: output.amount,
+            blinded_key: bk.as_ref().to_sec1_bytes().to_vec(),
+            dleq: Some(BlindedSignatureDleq {
+                e: dleq.e().to_vec(),
+                s: dleq.s().to_vec(),
+            }),
+        };
+        self.signed
+            .borrow_mut()
+            .push((output.clone(), signature.clone()));
+        signature
+    }
+
+    // Proofs with random secrets, signed by this mint
+    pub fn mint_proofs(&self, amounts: &[u64]) -> Proofs {
+        let proofs: Vec<Proof> = amounts
+            .iter()
+            .map(|amount| self.mint_proof(*amount, hex::encode(rand::random::<[u8; 32]>())))
+            .collect();
+        Proofs::from(proofs)
+    }
+
+    pub fn mint_proof(&self, amount: u64, secret: String) -> Proof {
+        let r = SecretKey::random(&mut rand::thread_rng());
+        let bm = crypto::Secret::from_utf8(&secret)
+            .unwrap()
+            .blinded_message(&r)
+            .unwrap();
+        let signature = self.sign(&BlindedMessage {
+            amount,
+            id: Some(self.keyset.id.to_string()),
+            blinded_message: bm.as_ref().to_sec1_bytes().to_vec(),
+        });
+        let bk =
+            crypto::BlindedKey::from(PublicKey::from_sec1_bytes(&signature.blinded_key).unwrap());
+        let uk = bk.unblind(&r, &self.keyset.keys[&amount]).unwrap();
+        let dleq = signature.dleq.unwrap();
+        Proof {
+            id: signature.id,
+            amount,
+            secret,
+            unblinded_key: uk.as_ref().to_sec1_bytes().to_vec(),
+            dleq: Some(ProofDleq {
+                e: dleq.e,
+                s: dleq.s,
+                r: r.to_bytes().to_vec(),
+            }),
+            witness: None,
+        }
+    }
+
+    // Check C == k * Y for each input, that none have been spent, and that P2PK inputs are
+    // signed by their locking key
+    fn verify_inputs(
+        &self,
+        inputs: &Proofs,
+        outputs: &[BlindedMessage],
+    ) -> Result<(), crate::Error> {
+        let states = self.states.borrow();
+        for proof in inputs.as_ref() {
+            let key = self
+                .keyset_keys(proof.id.as_deref())
+                .ok_or_else(|| mint_error(12001, "Unknown keyset"))?
+                .get(&proof.amount)
+                .ok_or(crate::Error::UnknownAmount(proof.amount))?;
+            let y = proof.y();
+            let expected = (y.to_projective() * *key.to_nonzero_scalar()).to_affine();
+            let c = PublicKey::from_sec1_bytes(&proof.unblinded_key)?;
+            if c.as_affine() != &expected {
+                return Err(mint_error(10003, "Proof could not be verified"));
+            }
+            if states.get(&hex::encode(y.to_sec1_bytes())) == Some(&State::Spent) {
+                return Err(mint_error(11001, "Token already spent"));
+            }
+            if proof
+                .verify_witness(&crate::p2pk_signing_message(proof, inputs, outputs))
+                .is_err()
+            {
+                return Err(mint_error(10003, "Witness is missing or invalid"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_state(&self, y: &PublicKey, state: State) {
+        self.states
+            .borrow_mut()
+            .insert(hex::encode(y.to_sec1_bytes()), state);
+    }
+
+    fn spend(&self, proof: &Proof) {
+        self.set_state(&proof.y(), State::Spent);
+        if let Some(witness) = &proof.witness {
+            self.witnesses
+                .borrow_mut()
+                .insert(hex::encode(proof.y().to_sec1_bytes()), witness.clone());
+        }
+    }
+}
+
+impl MintConnector for MockMint {
+    fn get_info(&self) -> Result<MintInfo, crate::Error> {
+        if self.no_info {
+            return Err(crate::Error::HttpStatus(404));
+        }
+        Ok(self.info())
+    }
+
+    fn get_keyset(&self, id: &str) -> Result<KeysResponse, crate::Error> {
+        if id == self.keyset.id {
+            return self.get_keys();
+        }
+        let (keyset, _) = self
+            .inactive
+            .iter()
+            .find(|(keyset, _)| keyset.id == id)
+            .ok_or_else(|| crate::Error::UnknownKeyset(id.to_string()))?;
+        Ok(KeysResponse {
+            keysets: vec![keyset_response(keyset)],
+        })
+    }
+
+    fn get_keysets(&self) -> Result<KeysetsResponse, crate::Error> {
+        let info = |keyset: &Keyset, active| KeysetInfo {
+            id: keyset.id.to_string(),
+            unit: self.unit.clone(),
+            active,
+            input_fee_ppk: self.input_fee_ppk,
+            max_order: None,
+        };
+        let mut keysets = vec![info(&self.keyset, true)];
+        keysets.extend(self.inactive.iter().map(|(keyset, _)| info(keyset, false)));
+        Ok(KeysetsResponse { keysets })
+    }
+
+    fn get_keys(&self) -> Result<KeysResponse, crate::Error> {
+        Ok(KeysResponse {
+            keysets: vec![keyset_response(&self.keyset)],
+        })
+    }
+
+    fn restore(&self, request: &RestoreRequest) -> Result<RestoreResponse, crate::Error> {
+        let signed = self.signed.borrow();
+        let (outputs, signatures) = request
+            .outputs
+            .iter()
+            .filter_map(|o| {
+                signed
+                    .iter()
+                    .find(|(s, _)| s.blinded_message == o.blinded_message)
+                    .cloned()
+            })
+            .unzip();
+        let mut signatures: Vec<BlindedSignature> = signatures;
+        signatures.truncate(signatures.len().saturating_sub(self.missing_signatures));
+        Ok(RestoreResponse {
+            outputs,
+            signatures,
+        })
+    }
+
+    fn check_state(&self, request: &CheckStateRequest) -> Result<CheckStateResponse, crate::Error> {
+        let states = self.states.borrow();
+        let witnesses = self.witnesses.borrow();
+        Ok(CheckStateResponse {
+            states: request
+                .ys
+                .iter()
+                .map(|y| ProofState {
+                    y: y.clone(),
+                    state: states.get(y).copied().unwrap_or(State::Unspent),
+                    witness: witnesses.get(y).cloned(),
+                })
+                .collect(),
+        })
+    }
+
+    fn mint_quote(&self, request: &MintQuoteRequest) -> Result<MintQuote, crate::Error> {
+        if Unit::from(request.unit.as_str()) != self.unit {
+            return Err(mint_error(11005, "Unsupported unit"));
+        }
+        let mut quotes = self.quotes.borrow_mut();
+        let quote = MintQuote {
+            quote: format!("quote-{}", quotes.len()),
+            request: invoice(Some(request.amount * 1000), rand::random()),
+            expiry: None,
+            state: Some(QuoteState::Paid),
+            paid: None,
+        };
+        quotes.insert(quote.quote.clone(), (request.amount, quote.clone()));
+        Ok(quote)
+    }
+
+    fn check_mint_quote(&self, quote: &str) -> Result<MintQuote, crate::Error> {
+        self.quotes
+            .borrow()
+            .get(quote)
+            .map(|(_, quote)| quote.clone())
+            .ok_or_else(|| mint_error(20007, "Quote not found"))
+    }
+
+    fn mint(&self, request: &MintRequest) -> Result<MintResponse, crate::Error> {
+        let mut quotes = self.quotes.borrow_mut();
+        let (amount, quote) = quotes
+            .get_mut(&request.quote)
+            .ok_or_else(|| mint_error(20007, "Quote not found"))?;
+        if quote.state() == QuoteState::Issued {
+            return Err(mint_error(20002, "Quote already issued"));
+        }
+        self.check_output_count(&request.outputs)?;
+        let total: u64 = request.outputs.iter().map(|o| o.amount).sum();
+        if total != *amount {
+            return Err(mint_error(11002, "Outputs don't match the quote amount"));
+        }
+        quote.state = Some(QuoteState::Issued);
+        Ok(MintResponse {
+            signatures: self.sign_all(&request.outputs),
+        })
+    }
+
+    fn swap(&self, request: &SwapRequest) -> Result<SwapResponse, crate::Error> {
+        self.swaps.borrow_mut().push(request.clone());
+        self.verify_inputs(&request.inputs, &request.outputs)?;
+        self.check_output_count(&request.outputs)?;
+        let total: u64 = request.outputs.iter().map(|o| o.amount).sum();
+        let fee_rates = self
+            .get_keysets()?
+            .keysets
+            .into_iter()
+            .map(|k| (k.id, k.input_fee_ppk))
+            .collect();
+        if total + request.inputs.input_fee(&fee_rates) != request.inputs.total() {
+            return Err(mint_error(11002, "Inputs and outputs are unbalanced"));
+        }
+        for proof in request.inputs.as_ref() {
+            self.spend(proof);
+        }
+        Ok(SwapResponse {
+            signatures: self.sign_all(&request.outputs),
+        })
+    }
+
+    fn melt_quote(
+        &self,
+        method: MeltMethod,
+        request: &MeltQuoteRequest,
+    ) -> Result<MeltQuote, crate::Error> {
+        if !self
+            .info()
+            .supports_melt_method(method.as_str(), &Unit::from(request.unit.as_str()))
+        {
+            return Err(mint_error(11000, "Unsupported method"));
+        }
+        let mut quote = melt_quote_for(method, request)?;
+        if self.fee_free {
+            quote.fee_reserve = 0;
+        }
+        self.melt_quotes.borrow_mut().insert(
+            quote.quote.clone(),
+            MeltQuote {
+                method: Some(method),
+                ..quote.clone()
+            },
+        );
+        if self.omit_request {
+            quote.request = None;
+        }
+        Ok(quote)
+    }
+
+    fn melt(&self, method: MeltMethod, request: &MeltRequest) -> Result<MeltQuote, crate::Error> {
+        let mut quotes = self.melt_quotes.borrow_mut();
+        let quote = quotes
+            .get_mut(&request.quote)
+            .filter(|quote| quote.method == Some(method))
+            .ok_or_else(|| mint_error(20007, "Quote not found"))?;
+        if !quote.can_melt() {
+            return Err(mint_error(20005, "Quote is pending or already paid"));
+        }
+        let outputs = request.outputs.as_deref().unwrap_or_default();
+        self.verify_inputs(&request.inputs, outputs)?;
+        let fee_rates = self
+            .get_keysets()?
+            .keysets
+            .into_iter()
+            .map(|k| (k.id, k.input_fee_ppk))
+            .collect();
+        let available = request.inputs.total() - request.inputs.input_fee(&fee_rates);
+        if available < quote.total_amount() {
+            return Err(mint_error(
+                11002,
+                "Inputs don't cover the amount and fee reserve",
+            ));
+        }
+
+        if self.melt_pending {
+            for proof in request.inputs.as_ref() {
+                self.set_state(&proof.y(), State::Pending);
+            }
+            quote.state = Some(QuoteState::Pending);
+            return Ok(quote.clone());
+        }
+        for proof in request.inputs.as_ref() {
+            self.spend(proof);
+        }
+        let change = crate::split_amount(available - quote.amount)
+            .into_iter()
+            .zip(outputs)
+            .map(|(amount, output)| {
+                self.sign(&BlindedMessage {
+                    amount,
+                    ..output.clone()
+                })
+            })
+            .collect();
+        quote.state = Some(QuoteState::Paid);
+        Ok(MeltQuote {
+            payment_preimage: Some(hex::encode(self.preimage)),
+            change: Some(change),
+            ..quote.clone()
+        })
+    }
+}
+
+// Melt quotes carry a fee reserve of 1%, and at least 1. Amountless requests are paid the amount
+// given in the options.
+fn melt_quote_for(
+    method: MeltMethod,
+    request: &MeltQuoteRequest,
+) -> Result<MeltQuote, crate::Error> {
+    let amount_msat = match method {
+        MeltMethod::Bolt11 => {
+            let invoice: lightning_invoice::Bolt11Invoice = request.request.parse()?;
+            invoice.amount_milli_satoshis()
+        }
+        MeltMethod::Bolt12 => crate::offer_amount_msat(&request.request)?,
+    }
+    .or_else(|| {
+        let options = request.options.as_ref()?;
+        Some(options.amountless.as_ref()?.amount_msat)
+    })
+    .ok_or_else(|| mint_error(11000, "Amountless requests aren't supported"))?;
+    let fee_reserve_msat = (amount_msat / 100).max(1000);
+    Ok(MeltQuote {
+        quote: format!("melt-{}", hex::encode(rand::random::<[u8; 8]>())),
+        request: Some(request.request.clone()),
+        ..MeltQuote::from_msat(String::new(), amount_msat, fee_reserve_msat)
+    })
+}
+
+fn keyset_response(keyset: &Keyset) -> KeysetResponse {
+    KeysetResponse {
+        id: keyset.id.to_string(),
+        unit: "sat".to_string(),
+        keys: keyset
+            .keys
+            .iter()
+            .map(|(a, k)| (a.to_string(), hex::encode(k.to_sec1_bytes())))
+            .collect(),
+    }
+}
+
+fn generate_keyset(name: &str) -> (Keyset, BTreeMap<u64, SecretKey>) {
+    let keys: BTreeMap<u64, SecretKey> = (0..16)
+        .map(|order| {
+            let amount = 1u64 << order;
+            let seed = Sha256::digest(format!("{name}/{amount}"));
+            (amount, SecretKey::from_slice(&seed).unwrap())
+        })
+        .collect();
+    let public_keys = keys.iter().map(|(a, k)| (*a, k.public_key())).collect();
+    let keyset = Keyset {
+        id: Keyset::derive_id(&public_keys).parse().unwrap(),
+        keys: public_keys,
+    };
+    (keyset, keys)
+}
+
+fn mint_error(code: u64, detail: &str) -> crate::Error {
+    crate::Error::Mint {
+        code,
+        detail: detail.to_string(),
+    }
+}
+
+pub(crate) const PREIMAGE: [u8; 32] = [7; 32];
+
+// An invoice paid by PREIMAGE, which the mock reports for the melts it pays
+pub(crate) fn payable_invoice(amount_msat: u64) -> String {
+    invoice(Some(amount_msat), Sha256::digest(PREIMAGE).into())
+}
+
+// A signed bolt11 invoice for the given payment hash
+pub(crate) fn invoice(amount_msat: Option<u64>, payment_hash: [u8; 32]) -> String {
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+
+    let key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+    let builder = InvoiceBuilder::new(Currency::Bitcoin)
+        .description("test".to_string())
+        .payment_hash(sha256::Hash::from_byte_array(payment_hash))
+        .payment_secret(PaymentSecret([0x11; 32]))
+        .duration_since_epoch(std::time::Duration::from_secs(1_700_000_000))
+        .min_final_cltv_expiry_delta(144);
+    let builder = match amount_msat {
+        Some(amount) => builder.amount_milli_satoshis(amount),
+        None => builder,
+    };
+    builder
+        .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key))
+        .unwrap()
+        .to_string()
+}
+
+// A P2PK secret locked to the key, with the given sigflag
+pub(crate) fn p2pk_secret(key: &SecretKey, sig_flag: SigFlag) -> String {
+    let flag = match sig_flag {
+        SigFlag::SigInputs => "SIG_INPUTS",
+        SigFlag::SigAll => "SIG_ALL",
+    };
+    serde_json::to_string(&WellKnownSecret {
+        kind: "P2PK".to_string(),
+        data: crate::SecretData {
+            nonce: hex::encode(rand::random::<[u8; 16]>()),
+            data: hex::encode(key.public_key().to_sec1_bytes()),
+            tags: vec![vec!["sigflag".to_string(), flag.to_string()]],
+        },
+    })
+    .unwrap()
+}
@@ -1,56 +1,1052 @@
-use std::str::from_utf8;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{Read, Write},
+    str::{from_utf8, FromStr},
+};
 
-use base64::{engine::general_purpose::URL_SAFE, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE},
+    Engine,
+};
 use derive_more::*;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use k256::PublicKey;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use url::Url;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+use crate::{keyset_id_version, KeysetId, KeysetIdVersion};
+
+// B_ and C_ on the wire must be compressed curve points, so corrupt data fails on parsing rather
+// than when it's first used
+mod point {
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    pub use super::compressed::serialize;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&hex).map_err(Error::custom)?;
+        if bytes.len() != 33 || k256::PublicKey::from_sec1_bytes(&bytes).is_err() {
+            return Err(Error::custom(format!(
+                "{hex} is not a compressed curve point"
+            )));
+        }
+        Ok(bytes)
+    }
+}
+
+// Points are written in compressed form whatever encoding they were read in, so equal points
+// serialize to equal bytes. Bytes that aren't a point are written as they are.
+mod compressed {
+    use serde::Serializer;
+
+    pub use hex::serde::deserialize;
+
+    pub fn serialize<S: Serializer>(point: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match k256::PublicKey::from_sec1_bytes(point) {
+            Ok(key) => hex::serde::serialize(key.to_sec1_bytes(), serializer),
+            Err(_) => hex::serde::serialize(point, serializer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct BlindedMessage {
-    amount: u64,
+    pub amount: u64,
 
-    #[serde(rename = "B_", with = "hex::serde")]
-    blinded_message: Vec<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "B_", with = "point")]
+    pub blinded_message: Vec<u8>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct BlindedSignature {
-    id: Option<String>,
+    pub id: Option<KeysetId>,
 
-    amount: u64,
+    pub amount: u64,
 
-    #[serde(rename = "C_", with = "hex::serde")]
-    blinded_key: Vec<u8>,
+    #[serde(rename = "C_", with = "point")]
+    pub blinded_key: Vec<u8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dleq: Option<BlindedSignatureDleq>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlindedSignatureDleq {
+    #[serde(with = "hex::serde")]
+    pub e: Vec<u8>,
+
+    #[serde(with = "hex::serde")]
+    pub s: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Proof {
-    id: Option<String>,
+    pub id: Option<KeysetId>,
+
+    pub amount: u64,
+
+    pub secret: String,
+
+    #[serde(rename = "C", with = "compressed")]
+    pub unblinded_key: Vec<u8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dleq: Option<ProofDleq>,
+
+    // Data unlocking a NUT-10 secret's spending conditions, as a JSON string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProofDleq {
+    #[serde(with = "hex::serde")]
+    pub e: Vec<u8>,
+
+    #[serde(with = "hex::serde")]
+    pub s: Vec<u8>,
+
+    #[serde(with = "hex::serde")]
+    pub r: Vec<u8>,
+}
 
-    amount: u64,
+impl Proof {
+    pub fn y(&self) -> PublicKey {
+        crate::bdhke::hash_to_curve(self.secret.as_bytes())
+    }
 
-    secret: String,
+    pub fn y_cached(&self, cache: &crate::crypto::HashToCurveCache) -> PublicKey {
+        cache.hash_bytes(self.secret.as_bytes())
+    }
 
-    #[serde(rename = "C", with = "hex::serde")]
-    unblinded_key: Vec<u8>,
+    // Unblind a signature on the output made from the secret and blinding factor. The signature
+    // must come from the keyset the output was made for. Otherwise the mint signed with a key
+    // the wallet didn't ask for, and the proof can't be unblinded with this keyset's key.
+    pub fn from_signature(
+        signature: &BlindedSignature,
+        secret: &crate::crypto::Secret,
+        r: &k256::SecretKey,
+        keyset: &Keyset,
+    ) -> Result<Proof, crate::Error> {
+        if let Some(id) = signature.id.as_ref().filter(|id| **id != keyset.id) {
+            return Err(crate::Error::UnexpectedKeyset {
+                expected: keyset.id.to_string(),
+                found: id.to_string(),
+            });
+        }
+        let mint_key = keyset
+            .keys
+            .get(&signature.amount)
+            .ok_or(crate::Error::UnknownAmount(signature.amount))?;
+        let blinded_key =
+            crate::crypto::BlindedKey::from(crate::crypto::parse_point(&signature.blinded_key)?);
+        let unblinded_key = blinded_key.unblind(r, mint_key)?;
+        Ok(Proof {
+            id: signature.id.clone(),
+            amount: signature.amount,
+            secret: secret.as_str().into_owned(),
+            unblinded_key: unblinded_key.as_ref().to_sec1_bytes().to_vec(),
+            dleq: signature.dleq.as_ref().map(|dleq| ProofDleq {
+                e: dleq.e.clone(),
+                s: dleq.s.clone(),
+                r: r.to_bytes().to_vec(),
+            }),
+            witness: None,
+        })
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, AsRef, Into, From, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, Default, Serialize, Deserialize, AsRef, AsMut, Into, From, PartialEq, Eq,
+)]
 pub struct Proofs(Vec<Proof>);
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+impl Proofs {
+    pub fn total(&self) -> u64 {
+        self.0.iter().map(|p| p.amount).sum()
+    }
+
+    pub fn append(&mut self, mut other: Proofs) {
+        self.0.append(&mut other.0);
+    }
+
+    // Proofs are matched by secret and C, so differing metadata doesn't prevent removal
+    pub fn remove(&mut self, to_remove: &Proofs) {
+        self.0.retain(|p| {
+            !to_remove
+                .0
+                .iter()
+                .any(|r| r.secret == p.secret && r.unblinded_key == p.unblinded_key)
+        });
+    }
+
+    // NUT-02: the sum of each proof's keyset fee, rounded up to a whole unit. Fee rates are
+    // in thousandths of the unit, keyed by keyset id, and proofs from unlisted keysets are free.
+    pub fn input_fee(&self, fee_rates: &HashMap<String, u64>) -> u64 {
+        let ppk: u64 = self
+            .0
+            .iter()
+            .filter_map(|p| fee_rates.get(p.id.as_deref()?))
+            .sum();
+        ppk.div_ceil(1000)
+    }
+
+    // How many proofs there are of each denomination
+    pub fn denomination_histogram(&self) -> BTreeMap<u64, usize> {
+        let mut histogram = BTreeMap::new();
+        for proof in &self.0 {
+            *histogram.entry(proof.amount).or_default() += 1;
+        }
+        histogram
+    }
+
+    // The largest proofs until they cover the amount, and the rest. Taking largest first covers
+    // it with as few proofs as possible. If the proofs don't cover it, they all go in the first
+    // part.
+    pub fn split_at_amount(self, amount: u64) -> (Proofs, Proofs) {
+        let mut proofs = self.0;
+        proofs.sort_by_key(|p| std::cmp::Reverse(p.amount));
+        let mut total = 0;
+        let taken = proofs
+            .iter()
+            .take_while(|p| {
+                let taking = total < amount;
+                total += p.amount;
+                taking
+            })
+            .count();
+        let rest = proofs.split_off(taken);
+        (Proofs(proofs), Proofs(rest))
+    }
+
+    // Ascending by amount, then by secret bytes. Secrets are unique among spendable proofs, so
+    // any two orderings of the same proofs sort the same.
+    pub fn sort_canonical(&mut self) {
+        self.0
+            .sort_by(|a, b| (a.amount, a.secret.as_bytes()).cmp(&(b.amount, b.secret.as_bytes())));
+    }
+}
+
+impl Extend<Proof> for Proofs {
+    fn extend<T: IntoIterator<Item = Proof>>(&mut self, iter: T) {
+        self.0.extend(iter);
+    }
+}
+
+// Serialized tokens longer than this are rejected before they're decoded, so pasted garbage
+// can't make decoding allocate without bound
+pub const MAX_TOKEN_LENGTH: usize = 1 << 20;
+
+const COMPRESSED_PREFIX: &str = "cashuZ";
+
+// Limits on the subset-sum search in Token::extract_exact, past which it gives up
+const MAX_EXACT_PROOFS: usize = 128;
+const MAX_EXACT_SUMS: usize = 10_000;
+
+pub(crate) fn check_token_length(token: &str, max: usize) -> Result<(), crate::Error> {
+    if token.len() > max {
+        return Err(crate::Error::TokenTooLarge {
+            length: token.len(),
+            max,
+        });
+    }
+    Ok(())
+}
+
+// Parse each of a batch of serialized tokens on its own, V4 or V3 by prefix, so one bad token
+// only fails its own entry
+pub fn parse_tokens(inputs: &[&str]) -> Vec<Result<Token, crate::Error>> {
+    inputs
+        .iter()
+        .map(|input| {
+            let input = input.trim();
+            if input.starts_with("cashuB") {
+                Token::deserialize_v4(input)
+            } else {
+                Token::deserialize(input)
+            }
+        })
+        .collect()
+}
+
+// Mints are reached over http or https, so anything else a token claims as its mint is rejected
+pub fn parse_mint_url(url: &str) -> Result<Url, crate::Error> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => {
+            Ok(parsed)
+        }
+        _ => Err(crate::Error::InvalidMintUrl(url.to_string())),
+    }
+}
+
+// A URL checked by parse_mint_url
+#[derive(Debug, Clone, PartialEq, Eq, Hash, AsRef, Deref, Display, Into)]
+pub struct MintUrl(Url);
+
+impl TryFrom<&str> for MintUrl {
+    type Error = crate::Error;
+
+    fn try_from(url: &str) -> Result<MintUrl, crate::Error> {
+        parse_mint_url(url).map(MintUrl)
+    }
+}
+
+impl FromStr for MintUrl {
+    type Err = crate::Error;
+
+    fn from_str(url: &str) -> Result<MintUrl, crate::Error> {
+        MintUrl::try_from(url)
+    }
+}
+
+// A mint's URL for comparison. Parsing already lowercases the scheme and host and drops default
+// ports, so this only trims trailing slashes, which mints are inconsistent about.
+pub fn normalize_mint_url(url: &Url) -> String {
+    url.as_str().trim_end_matches('/').to_string()
+}
+
+fn deserialize_mint_url<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Url, D::Error> {
+    let url = String::deserialize(deserializer)?;
+    parse_mint_url(&url).map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct MintToken {
-    mint: Url,
-    proofs: Proofs,
+    #[serde(deserialize_with = "deserialize_mint_url")]
+    pub mint: Url,
+    pub proofs: Proofs,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    // Not part of the standard format, so other wallets only see the token's top-level memo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Token {
-    token: Vec<MintToken>,
-    memo: Option<String>,
+    pub token: Vec<MintToken>,
+    pub memo: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+// One proof of a token, as a wallet would show it. Proofs in tokens that don't give a unit are
+// taken to be in sats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofDescription {
+    pub mint: Url,
+    pub amount: u64,
+    pub unit: Unit,
+    pub keyset_id: Option<KeysetId>,
+    pub kind: crate::SecretKind,
+}
+
+impl ProofDescription {
+    // Locked to a key or hash lock, rather than spendable by whoever holds the secret
+    pub fn is_locked(&self) -> bool {
+        self.kind != crate::SecretKind::Plain
+    }
+}
+
+impl std::fmt::Display for ProofDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} from {}", self.amount, self.unit, self.mint)?;
+        if let Some(id) = &self.keyset_id {
+            write!(f, ", keyset {id}")?;
+        }
+        match self.kind {
+            crate::SecretKind::Plain => Ok(()),
+            crate::SecretKind::P2PK => f.write_str(", locked to a key"),
+            crate::SecretKind::Htlc => f.write_str(", locked to a hash"),
+        }
+    }
+}
+
+// Deserializes from a NUT-01 keyset, checked as by TryFrom<KeysetResponse>
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "KeysetResponse")]
+pub struct Keyset {
+    pub id: KeysetId,
+    pub keys: BTreeMap<u64, PublicKey>,
+}
+
+impl Keyset {
+    // The largest amount there's a key for is 2^max_order
+    pub fn max_order(&self) -> Option<u32> {
+        self.keys
+            .keys()
+            .next_back()
+            .and_then(|amount| amount.checked_ilog2())
+    }
+
+    // NUT-02: "00" followed by the first 7 bytes of the hash of the keys, sorted by amount
+    pub fn derive_id(keys: &BTreeMap<u64, PublicKey>) -> String {
+        let mut hasher = Sha256::new();
+        for key in keys.values() {
+            hasher.update(key.to_sec1_bytes());
+        }
+        format!("00{}", hex::encode(&hasher.finalize()[..7]))
+    }
+
+    // Before version 00: the first 12 characters of the base64 hash of the keys' hex, sorted by
+    // amount
+    pub fn derive_id_v0(keys: &BTreeMap<u64, PublicKey>) -> String {
+        let mut hasher = Sha256::new();
+        for key in keys.values() {
+            hasher.update(hex::encode(key.to_sec1_bytes()));
+        }
+        STANDARD.encode(hasher.finalize())[..12].to_string()
+    }
+}
+
+// A cheap check, before unblinding, that a signature claims to come from this keyset and is for
+// an amount the keyset has a key for
+pub fn signature_keyset_consistent(sig: &BlindedSignature, keyset: &Keyset) -> bool {
+    sig.id.as_deref() == Some(keyset.id.as_str()) && keyset.keys.contains_key(&sig.amount)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeysResponse {
+    pub keysets: Vec<KeysetResponse>,
+}
+
+impl KeysResponse {
+    // Parse each keyset independently, so a corrupt keyset doesn't hide the valid ones
+    pub fn into_keysets(self) -> Vec<Result<Keyset, crate::Error>> {
+        self.keysets.into_iter().map(Keyset::try_from).collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeysetResponse {
+    pub id: String,
+    pub unit: String,
+    pub keys: BTreeMap<String, String>,
+}
+
+impl TryFrom<KeysetResponse> for Keyset {
+    type Error = crate::Error;
+
+    fn try_from(response: KeysetResponse) -> Result<Keyset, crate::Error> {
+        let invalid = |reason: String| crate::Error::InvalidKeyset {
+            id: response.id.clone(),
+            reason,
+        };
+        let mut keys = BTreeMap::new();
+        for (amount, key) in &response.keys {
+            let amount: u64 = amount
+                .parse()
+                .map_err(|_| invalid(format!("amount {amount:?} is not an integer")))?;
+            let key =
+                hex::decode(key).map_err(|e| invalid(format!("key for amount {amount}: {e}")))?;
+            let key = PublicKey::from_sec1_bytes(&key)
+                .ok()
+                .filter(|_| key.len() == 33)
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "key for amount {amount} is not a valid compressed point"
+                    ))
+                })?;
+            keys.insert(amount, key);
+        }
+        let derived = match keyset_id_version(&response.id) {
+            Some(KeysetIdVersion::V0) => Keyset::derive_id_v0(&keys),
+            _ => Keyset::derive_id(&keys),
+        };
+        if derived != response.id {
+            return Err(invalid(format!("id does not match derived id {derived}")));
+        }
+        Ok(Keyset {
+            id: response.id.parse()?,
+            keys,
+        })
+    }
+}
+
+// The unit a keyset's amounts are denominated in. Units the crate doesn't know are kept as-is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Unit {
+    Sat,
+    Msat,
+    Usd,
+    Eur,
+    Custom(String),
+}
+
+impl From<String> for Unit {
+    fn from(unit: String) -> Unit {
+        match unit.as_str() {
+            "sat" => Unit::Sat,
+            "msat" => Unit::Msat,
+            "usd" => Unit::Usd,
+            "eur" => Unit::Eur,
+            _ => Unit::Custom(unit),
+        }
+    }
+}
+
+impl From<&str> for Unit {
+    fn from(unit: &str) -> Unit {
+        Unit::from(unit.to_string())
+    }
+}
+
+impl From<Unit> for String {
+    fn from(unit: Unit) -> String {
+        unit.to_string()
+    }
+}
+
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Unit::Sat => "sat",
+            Unit::Msat => "msat",
+            Unit::Usd => "usd",
+            Unit::Eur => "eur",
+            Unit::Custom(unit) => unit,
+        })
+    }
+}
+
+// An entry from /v1/keysets, which lists every keyset without its keys
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeysetInfo {
+    pub id: String,
+    pub unit: Unit,
+    pub active: bool,
+
+    // NUT-02: the fee for spending a proof from this keyset, in thousandths of the unit
+    #[serde(default)]
+    pub input_fee_ppk: u64,
+
+    // The largest amount the keyset signs is 2^max_order, if the mint says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_order: Option<u32>,
+}
+
+impl KeysetInfo {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeysetsResponse {
+    pub keysets: Vec<KeysetInfo>,
+}
+
+impl KeysetsResponse {
+    // The keyset new outputs in this unit go to. Inactive keysets are still valid for verifying
+    // proofs, but a mint should only have one active keyset per unit.
+    pub fn active(&self, unit: &Unit) -> Result<&KeysetInfo, crate::Error> {
+        let mut active = self
+            .keysets
+            .iter()
+            .filter(|k| k.is_active() && &k.unit == unit);
+        match (active.next(), active.next()) {
+            (Some(keyset), None) => Ok(keyset),
+            (None, _) => Err(crate::Error::NoActiveKeyset(unit.clone())),
+            (Some(_), Some(_)) => Err(crate::Error::AmbiguousActiveKeyset(unit.clone())),
+        }
+    }
+}
+
+// The mint key K that signed the proof, found by the proof's keyset id and amount
+pub fn keyset_pubkey_for_proof(
+    proof: &Proof,
+    keysets: &[Keyset],
+) -> Result<PublicKey, crate::Error> {
+    let id = proof
+        .id
+        .as_ref()
+        .ok_or(crate::Error::MissingKeysetId(vec![0]))?;
+    let keyset = keysets
+        .iter()
+        .find(|keyset| &keyset.id == id)
+        .ok_or_else(|| crate::Error::UnknownKeyset(id.to_string()))?;
+    keyset
+        .keys
+        .get(&proof.amount)
+        .copied()
+        .ok_or(crate::Error::UnknownAmount(proof.amount))
+}
+
+// Check a mint's keyset list is usable: ids are unique and well-formed, and no unit has more
+// than one active keyset to mint into
+pub fn validate_keysets(keysets: &[KeysetInfo]) -> Result<(), crate::Error> {
+    for (i, keyset) in keysets.iter().enumerate() {
+        if keyset_id_version(&keyset.id).is_none() {
+            return Err(crate::Error::InvalidKeyset {
+                id: keyset.id.clone(),
+                reason: "id is neither a version 00 nor a legacy keyset id".to_string(),
+            });
+        }
+        let earlier = &keysets[..i];
+        if earlier.iter().any(|k| k.id == keyset.id) {
+            return Err(crate::Error::DuplicateKeyset(keyset.id.clone()));
+        }
+        if keyset.active && earlier.iter().any(|k| k.active && k.unit == keyset.unit) {
+            return Err(crate::Error::AmbiguousActiveKeyset(keyset.unit.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MintQuoteRequest {
+    pub amount: u64,
+    pub unit: String,
+}
+
+// How a melt pays out, which picks the melt endpoints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MeltMethod {
+    #[default]
+    Bolt11,
+    Bolt12,
+}
+
+impl MeltMethod {
+    // Bolt12 for offers, bolt11 for anything else
+    pub fn for_request(request: &str) -> MeltMethod {
+        if crate::is_offer(request) {
+            MeltMethod::Bolt12
+        } else {
+            MeltMethod::Bolt11
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MeltMethod::Bolt11 => "bolt11",
+            MeltMethod::Bolt12 => "bolt12",
+        }
+    }
+
+    pub fn quote_path(&self) -> String {
+        format!("melt/quote/{self}")
+    }
+
+    pub fn melt_path(&self) -> String {
+        format!("melt/{self}")
+    }
+}
+
+impl std::fmt::Display for MeltMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// The request is a bolt11 invoice or a bolt12 offer, depending on the method
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MeltQuoteRequest {
+    pub request: String,
+    pub unit: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<MeltOptions>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MeltOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amountless: Option<Amountless>,
+}
+
+// The amount to pay to a request that doesn't name one itself
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Amountless {
+    pub amount_msat: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MintRequest {
+    pub quote: String,
+    pub outputs: Vec<BlindedMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MintResponse {
+    pub signatures: Vec<BlindedSignature>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SwapRequest {
+    pub inputs: Proofs,
+    pub outputs: Vec<BlindedMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SwapResponse {
+    pub signatures: Vec<BlindedSignature>,
+}
+
+// Blank outputs are optional, and without them the overpaid fee reserve isn't returned
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MeltRequest {
+    pub quote: String,
+    pub inputs: Proofs,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<Vec<BlindedMessage>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ErrorResponse {
+    // Some mints leave the code out, so 0 stands in for unknown
+    #[serde(default)]
+    pub code: u64,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RestoreRequest {
+    pub outputs: Vec<BlindedMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RestoreResponse {
+    pub outputs: Vec<BlindedMessage>,
+
+    #[serde(alias = "promises")]
+    pub signatures: Vec<BlindedSignature>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum State {
+    Unspent,
+    Pending,
+    Spent,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CheckStateRequest {
+    #[serde(rename = "Ys")]
+    pub ys: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CheckStateResponse {
+    pub states: Vec<ProofState>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProofState {
+    #[serde(rename = "Y")]
+    pub y: String,
+
+    pub state: State,
+
+    // The witness a spent proof was unlocked with, as a JSON string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness: Option<String>,
+}
+
+// Mint quotes go UNPAID -> PAID -> ISSUED, and melt quotes UNPAID -> PENDING -> PAID
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum QuoteState {
+    Unpaid,
+    Pending,
+    Paid,
+    Issued,
+}
+
+impl QuoteState {
+    // The invoice has been paid, but the ecash not yet issued
+    pub fn can_mint(&self) -> bool {
+        *self == QuoteState::Paid
+    }
+
+    // Nothing has been paid out against the quote yet
+    pub fn can_melt(&self) -> bool {
+        *self == QuoteState::Unpaid
+    }
+
+    // Older mints only report a `paid` flag
+    fn from_legacy(state: Option<QuoteState>, paid: Option<bool>) -> QuoteState {
+        match (state, paid) {
+            (Some(state), _) => state,
+            (None, Some(true)) => QuoteState::Paid,
+            (None, _) => QuoteState::Unpaid,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MintQuote {
+    pub quote: String,
+    pub request: String,
+    pub expiry: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<QuoteState>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paid: Option<bool>,
+}
+
+impl MintQuote {
+    pub fn state(&self) -> QuoteState {
+        QuoteState::from_legacy(self.state, self.paid)
+    }
+
+    pub fn can_mint(&self) -> bool {
+        self.state().can_mint()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        is_expired(self.expiry, now)
+    }
+
+    pub fn ensure_unexpired(&self, now: u64) -> Result<(), crate::Error> {
+        ensure_unexpired(&self.quote, self.expiry, now)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MeltQuote {
+    pub quote: String,
+    pub amount: u64,
+    pub fee_reserve: u64,
+    pub expiry: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<QuoteState>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paid: Option<bool>,
+
+    // The invoice or offer being paid, which newer mints echo back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+
+    // Set once the melt is paid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_preimage: Option<String>,
+
+    // Signatures on the blank outputs sent with the melt, for the overpaid fee reserve (NUT-08)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<Vec<BlindedSignature>>,
+
+    // The method the wallet asked for the quote with, which mints don't report
+    #[serde(skip)]
+    pub method: Option<MeltMethod>,
+}
+
+impl MeltQuote {
+    pub fn state(&self) -> QuoteState {
+        QuoteState::from_legacy(self.state, self.paid)
+    }
+
+    pub fn can_melt(&self) -> bool {
+        self.state().can_melt()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        is_expired(self.expiry, now)
+    }
+
+    pub fn ensure_unexpired(&self, now: u64) -> Result<(), crate::Error> {
+        ensure_unexpired(&self.quote, self.expiry, now)
+    }
+
+    // The amount the wallet must provide in inputs to fund the melt
+    pub fn total_amount(&self) -> u64 {
+        self.amount + self.fee_reserve
+    }
+
+    // Build a sat-denominated quote when the Lightning layer reports msats
+    pub fn from_msat(quote: String, amount_msat: u64, fee_reserve_msat: u64) -> MeltQuote {
+        MeltQuote {
+            quote,
+            amount: crate::msat_to_sat_ceil(amount_msat),
+            fee_reserve: crate::msat_to_sat_ceil(fee_reserve_msat),
+            expiry: None,
+            state: None,
+            paid: None,
+            request: None,
+            payment_preimage: None,
+            change: None,
+            method: None,
+        }
+    }
+}
+
+// Quote expiries are unix timestamps until which the quote is valid
+fn is_expired(expiry: Option<u64>, now: u64) -> bool {
+    expiry.is_some_and(|expiry| now > expiry)
+}
+
+fn ensure_unexpired(quote: &str, expiry: Option<u64>, now: u64) -> Result<(), crate::Error> {
+    match expiry {
+        Some(expiry) if is_expired(Some(expiry), now) => Err(crate::Error::QuoteExpired {
+            quote: quote.to_string(),
+            expiry,
+        }),
+        _ => Ok(()),
+    }
 }
 
 impl Token {
+    // Each mint the token's proofs come from, once, in the order they first appear. URLs are
+    // compared after normalize_mint_url.
+    pub fn mints(&self) -> Vec<&Url> {
+        let mut seen = HashSet::new();
+        self.token
+            .iter()
+            .map(|t| &t.mint)
+            .filter(|mint| seen.insert(normalize_mint_url(mint)))
+            .collect()
+    }
+
+    // True when a secret appears on more than one proof, in any mint entry. Only one of them can
+    // ever be redeemed.
+    pub fn has_duplicate_secrets(&self) -> bool {
+        let mut seen = HashSet::new();
+        !self
+            .token
+            .iter()
+            .flat_map(|t| t.proofs.as_ref())
+            .all(|proof| seen.insert(proof.secret.as_str()))
+    }
+
+    // Fails on the first mint that isn't in the allowlist, compared after normalize_mint_url
+    pub fn check_mints_allowed(&self, allowed: &[Url]) -> Result<(), crate::Error> {
+        let allowed: Vec<String> = allowed.iter().map(normalize_mint_url).collect();
+        match self
+            .mints()
+            .into_iter()
+            .find(|mint| !allowed.contains(&normalize_mint_url(mint)))
+        {
+            Some(mint) => Err(crate::Error::MintNotAllowed(mint.clone())),
+            None => Ok(()),
+        }
+    }
+
+    // Equal when both hold the same proofs from the same mints in the same units, however the
+    // mints and proofs are ordered or split across entries
+    pub fn semantically_eq(&self, other: &Token) -> bool {
+        fn entries(token: &Token) -> Vec<(&Url, Option<&str>, &Proof)> {
+            let mut entries: Vec<_> = token
+                .token
+                .iter()
+                .enumerate()
+                .flat_map(|(i, t)| {
+                    let unit = token.mint_unit(i);
+                    t.proofs.0.iter().map(move |p| (&t.mint, unit, p))
+                })
+                .collect();
+            entries.sort_by(|a, b| {
+                (a.0, a.1, &a.2.secret, &a.2.unblinded_key).cmp(&(
+                    b.0,
+                    b.1,
+                    &b.2.secret,
+                    &b.2.unblinded_key,
+                ))
+            });
+            entries
+        }
+        self.memo == other.memo && entries(self) == entries(other)
+    }
+
+    // The token's JSON with object keys sorted, and the proofs of each mint entry and the
+    // entries themselves sorted by their canonical JSON, without whitespace. serde_json keeps
+    // fields in declaration order, so this doesn't depend on how the token was built or parsed.
+    pub fn to_canonical_json(&self) -> Result<String, crate::Error> {
+        fn canonicalize(value: serde_json::Value) -> serde_json::Value {
+            match value {
+                serde_json::Value::Object(map) => {
+                    let mut entries: Vec<_> = map.into_iter().collect();
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, canonicalize(value)))
+                        .collect()
+                }
+                serde_json::Value::Array(values) => values.into_iter().map(canonicalize).collect(),
+                value => value,
+            }
+        }
+        fn sort(values: &mut [serde_json::Value]) {
+            values.sort_by_cached_key(|value| value.to_string());
+        }
+
+        let mut token = canonicalize(serde_json::to_value(self)?);
+        if let Some(entries) = token["token"].as_array_mut() {
+            for entry in entries.iter_mut() {
+                if let Some(proofs) = entry["proofs"].as_array_mut() {
+                    sort(proofs);
+                }
+            }
+            sort(entries);
+        }
+        Ok(token.to_string())
+    }
+
+    // Some tokens carry the unit per mint and others once for the whole token. The per-mint unit
+    // takes precedence.
+    pub fn mint_unit(&self, index: usize) -> Option<&str> {
+        self.token
+            .get(index)
+            .and_then(|t| t.unit.as_deref())
+            .or(self.unit.as_deref())
+    }
+
+    // Every proof with the mint it's from and its unit (see mint_unit), in token order
+    pub fn into_proofs(self) -> Vec<(Url, Proof, Option<Unit>)> {
+        let units: Vec<Option<Unit>> = (0..self.token.len())
+            .map(|i| self.mint_unit(i).map(Unit::from))
+            .collect();
+        self.token
+            .into_iter()
+            .zip(units)
+            .flat_map(|(mint_token, unit)| {
+                let mint = mint_token.mint;
+                mint_token
+                    .proofs
+                    .0
+                    .into_iter()
+                    .map(move |proof| (mint.clone(), proof, unit.clone()))
+            })
+            .collect()
+    }
+
+    pub fn describe(&self) -> Vec<ProofDescription> {
+        let mut descriptions = Vec::new();
+        for (i, mint_token) in self.token.iter().enumerate() {
+            let unit = Unit::from(self.mint_unit(i).unwrap_or(crate::token_v4::DEFAULT_UNIT));
+            descriptions.extend(
+                mint_token
+                    .proofs
+                    .as_ref()
+                    .iter()
+                    .map(|proof| ProofDescription {
+                        mint: mint_token.mint.clone(),
+                        amount: proof.amount,
+                        unit: unit.clone(),
+                        keyset_id: proof.id.clone(),
+                        kind: proof.secret_kind(),
+                    }),
+            );
+        }
+        descriptions
+    }
+
     pub fn serialize(&self) -> Result<String, crate::error::Error> {
         let token = serde_json::to_string(self).map_err(crate::error::Error::map_tokenv3)?;
         let mut token = URL_SAFE.encode(&token);
@@ -58,7 +1054,30 @@ impl Token {
         Ok(token)
     }
 
+    // A V3 token with its JSON deflated, for shorter strings and smaller QR codes. The "cashuZ"
+    // prefix isn't a NUT-00 version, so wallets that don't know it reject the token rather than
+    // misread it. Use serialize for tokens other wallets must be able to read.
+    pub fn serialize_compressed(&self) -> Result<String, crate::Error> {
+        let token = serde_json::to_vec(self).map_err(crate::Error::map_tokenv3)?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&token)
+            .map_err(crate::Error::map_tokenv3)?;
+        let token = encoder.finish().map_err(crate::Error::map_tokenv3)?;
+        let mut token = URL_SAFE.encode(token);
+        token.insert_str(0, COMPRESSED_PREFIX);
+        Ok(token)
+    }
+
     pub fn deserialize(token: &str) -> Result<Token, crate::Error> {
+        Token::deserialize_with_limit(token, MAX_TOKEN_LENGTH)
+    }
+
+    pub fn deserialize_with_limit(token: &str, max_length: usize) -> Result<Token, crate::Error> {
+        check_token_length(token, max_length)?;
+        if let Some(token) = token.strip_prefix(COMPRESSED_PREFIX) {
+            return Token::deserialize_compressed(token, max_length);
+        }
         let token = token
             .strip_prefix("cashuA")
             .ok_or(crate::Error::TokenV3(None))?;
@@ -69,11 +1088,140 @@ impl Token {
         let token: Token = serde_json::from_str(token).map_err(crate::Error::map_tokenv3)?;
         Ok(token)
     }
+
+    // The JSON may inflate to no more than max_length either, so a small token can't expand
+    // without bound
+    fn deserialize_compressed(token: &str, max_length: usize) -> Result<Token, crate::Error> {
+        let token = URL_SAFE
+            .decode(token)
+            .map_err(|e| crate::Error::TokenV3(Some(Box::new(e))))?;
+        let mut json = Vec::new();
+        DeflateDecoder::new(token.as_slice())
+            .take(max_length as u64 + 1)
+            .read_to_end(&mut json)
+            .map_err(crate::Error::map_tokenv3)?;
+        if json.len() > max_length {
+            return Err(crate::Error::TokenTooLarge {
+                length: json.len(),
+                max: max_length,
+            });
+        }
+        serde_json::from_slice(&json).map_err(crate::Error::map_tokenv3)
+    }
+
+    // Proofs under one mint must share a unit. A proof's unit comes from its keyset, so proofs
+    // from keysets that aren't listed can't be checked.
+    pub fn validate(&self, keysets: &[KeysetInfo]) -> Result<(), crate::Error> {
+        for (i, mint_token) in self.token.iter().enumerate() {
+            let mut units: Vec<Unit> = self.mint_unit(i).map(Unit::from).into_iter().collect();
+            for proof in mint_token.proofs.as_ref() {
+                let keyset = keysets
+                    .iter()
+                    .find(|k| proof.id.as_deref() == Some(k.id.as_str()));
+                if let Some(keyset) = keyset {
+                    if !units.contains(&keyset.unit) {
+                        units.push(keyset.unit.clone());
+                    }
+                }
+            }
+            if units.len() > 1 {
+                return Err(crate::Error::MixedUnits {
+                    mint: mint_token.mint.clone(),
+                    units,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Fill in the unit of mint entries that have none, from the keyset of their first proof
+    // with a known keyset. Entries with no known keyset are left without a unit.
+    pub fn infer_units(&mut self, keysets: &[KeysetInfo]) {
+        for i in 0..self.token.len() {
+            if self.mint_unit(i).is_some() {
+                continue;
+            }
+            let mint_token = &mut self.token[i];
+            mint_token.unit = mint_token.proofs.as_ref().iter().find_map(|proof| {
+                keysets
+                    .iter()
+                    .find(|k| proof.id.as_deref() == Some(k.id.as_str()))
+                    .map(|k| k.unit.to_string())
+            });
+        }
+    }
+
+    // Split off proofs summing to exactly `amount`, as (exact, remainder), without swapping.
+    // Returns None if no subset of the existing denominations adds up, or if the token has too
+    // many proofs or reachable sums to search.
+    pub fn extract_exact(&self, amount: u64) -> Option<(Token, Token)> {
+        let proofs: Vec<(usize, &Proof)> = self
+            .token
+            .iter()
+            .enumerate()
+            .flat_map(|(i, t)| t.proofs.0.iter().map(move |p| (i, p)))
+            .collect();
+        if proofs.len() > MAX_EXACT_PROOFS {
+            return None;
+        }
+
+        // Subset sum over the proofs, remembering how each reachable sum was first reached
+        let mut reached: HashMap<u64, (u64, usize)> = HashMap::new();
+        reached.insert(0, (0, usize::MAX));
+        for (index, (_, proof)) in proofs.iter().enumerate() {
+            let sums: Vec<u64> = reached.keys().copied().collect();
+            for sum in sums {
+                match sum.checked_add(proof.amount) {
+                    Some(next) if next <= amount && !reached.contains_key(&next) => {
+                        reached.insert(next, (sum, index));
+                    }
+                    _ => {}
+                }
+            }
+            if reached.len() > MAX_EXACT_SUMS {
+                return None;
+            }
+        }
+
+        let mut chosen = vec![false; proofs.len()];
+        let mut sum = amount;
+        while sum != 0 {
+            let (prev, index) = reached.get(&sum)?;
+            chosen[*index] = true;
+            sum = *prev;
+        }
+
+        let split = |take: bool| Token {
+            token: self
+                .token
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| {
+                    let proofs: Vec<Proof> = proofs
+                        .iter()
+                        .zip(&chosen)
+                        .filter(|((mint, _), chosen)| *mint == i && **chosen == take)
+                        .map(|((_, proof), _)| (*proof).clone())
+                        .collect();
+                    (!proofs.is_empty()).then(|| MintToken {
+                        mint: t.mint.clone(),
+                        proofs: Proofs(proofs),
+                        unit: t.unit.clone(),
+                        memo: t.memo.clone(),
+                    })
+                })
+                .collect(),
+            memo: self.memo.clone(),
+            unit: self.unit.clone(),
+        };
+        Some((split(true), split(false)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::TokenBuilder;
 
     #[test]
     fn test_token_v3_serialization() {
@@ -91,14 +1239,17 @@ mod tests {
         assert_eq!(proofs[1].amount, 8);
     }
 
+    const POINT: &str = "02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2";
+
     #[test]
     fn test_blind_message_serialization() {
         let bm = BlindedMessage {
             amount: 10,
-            blinded_message: hex::decode("abcd").unwrap(),
+            id: None,
+            blinded_message: hex::decode(POINT).unwrap(),
         };
         let bmser = serde_json::to_string(&bm).unwrap();
-        assert_eq!(bmser, r#"{"amount":10,"B_":"abcd"}"#);
+        assert_eq!(bmser, format!(r#"{{"amount":10,"B_":"{POINT}"}}"#));
 
         let bm2 = serde_json::from_str(&bmser).unwrap();
         assert_eq!(bm, bm2);
@@ -107,32 +1258,1044 @@ mod tests {
     #[test]
     fn test_blinded_signature_serialization() {
         let bs = BlindedSignature {
-            id: Some("abcd".into()),
+            id: Some("009a1f293253e41e".parse().unwrap()),
             amount: 5,
-            blinded_key: hex::decode("abcd").unwrap(),
+            blinded_key: hex::decode(POINT).unwrap(),
+            dleq: None,
         };
         let bsser = serde_json::to_string(&bs).unwrap();
-        assert_eq!(bsser, r#"{"id":"abcd","amount":5,"C_":"abcd"}"#);
+        assert_eq!(
+            bsser,
+            format!(r#"{{"id":"009a1f293253e41e","amount":5,"C_":"{POINT}"}}"#)
+        );
 
         let bs2 = serde_json::from_str(&bsser).unwrap();
         assert_eq!(bs, bs2);
     }
 
+    #[test]
+    fn test_invalid_points() {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+        // Too short, an uncompressed point, and 33 bytes that aren't on the curve
+        let uncompressed = k256::PublicKey::from_sec1_bytes(&hex::decode(POINT).unwrap())
+            .unwrap()
+            .to_encoded_point(false);
+        let invalid = [
+            "abcd".to_string(),
+            hex::encode(uncompressed.as_bytes()),
+            format!("02{}", "ff".repeat(32)),
+        ];
+        for point in invalid {
+            let json = format!(r#"{{"amount":10,"B_":"{point}"}}"#);
+            let err = serde_json::from_str::<BlindedMessage>(&json).unwrap_err();
+            assert!(
+                err.to_string().contains("not a compressed curve point"),
+                "{point}"
+            );
+            let json = format!(r#"{{"id":null,"amount":5,"C_":"{point}"}}"#);
+            assert!(serde_json::from_str::<BlindedSignature>(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn test_serializes_compressed() {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+        let uncompressed = k256::PublicKey::from_sec1_bytes(&hex::decode(POINT).unwrap())
+            .unwrap()
+            .to_encoded_point(false);
+        let uncompressed = hex::encode(uncompressed.as_bytes());
+        let json = format!(
+            r#"{{"id":"009a1f293253e41e","amount":5,"secret":"abcd","C":"{uncompressed}"}}"#
+        );
+        let proof: Proof = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&proof).unwrap(),
+            format!(r#"{{"id":"009a1f293253e41e","amount":5,"secret":"abcd","C":"{POINT}"}}"#)
+        );
+        assert_eq!(
+            crate::crypto::UnblindedKey::from_hex(&uncompressed)
+                .unwrap()
+                .to_hex(),
+            POINT
+        );
+    }
+
     #[test]
     fn test_proof_serialization() {
         let proof = Proof {
-            id: Some("abcd".into()),
+            id: Some("009a1f293253e41e".parse().unwrap()),
             amount: 5,
             secret: "abcd".to_string(),
             unblinded_key: hex::decode("abcd").unwrap(),
+            dleq: None,
+            witness: None,
         };
         let pser = serde_json::to_string(&proof).unwrap();
         assert_eq!(
             pser,
-            r#"{"id":"abcd","amount":5,"secret":"abcd","C":"abcd"}"#
+            r#"{"id":"009a1f293253e41e","amount":5,"secret":"abcd","C":"abcd"}"#
         );
 
         let proof2 = serde_json::from_str(&pser).unwrap();
         assert_eq!(proof, proof2);
     }
+
+    // Unknown fields are ignored, but required ones are still required. The spec gives no other
+    // spellings of the field names, so none are accepted.
+    #[test]
+    fn test_proof_unknown_fields() {
+        let proof: Proof = serde_json::from_str(
+            r#"{"id":"009a1f293253e41e","amount":5,"secret":"abcd","C":"abcd","extra":{"a":[1,2]},"p":null}"#,
+        )
+        .unwrap();
+        assert_eq!(proof.amount, 5);
+        assert!(proof.witness.is_none());
+
+        for json in [
+            r#"{"id":"009a1f293253e41e","secret":"abcd","C":"abcd","extra":1}"#,
+            r#"{"id":"009a1f293253e41e","Amount":5,"secret":"abcd","C":"abcd"}"#,
+            r#"{"id":"abcd","amount":5,"secret":"abcd","C":"abcd"}"#,
+        ] {
+            assert!(serde_json::from_str::<Proof>(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn test_check_state_response_deserialization() {
+        let json = r#"{"states":[{"Y":"02abcd","state":"UNSPENT","witness":null},{"Y":"03abcd","state":"SPENT","witness":null}]}"#;
+        let response: CheckStateResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.states[0].state, State::Unspent);
+        assert_eq!(response.states[1].y, "03abcd");
+        assert_eq!(response.states[1].state, State::Spent);
+        assert_eq!(response.states[1].witness, None);
+
+        let json = r#"{"states":[{"Y":"02abcd","state":"SPENT","witness":"{\"signatures\":[\"60f3c9b766770b46caac1d27e1ae6b77c8866ebaeba0b9489fe6a15a837eaa6fcd6eaa825499c72ac342983983fd3ba3a8a41f56677cc99ffd73da68b59e1383\"]}"}]}"#;
+        let response: CheckStateResponse = serde_json::from_str(json).unwrap();
+        let witness: crate::P2PKWitness =
+            serde_json::from_str(response.states[0].witness.as_deref().unwrap()).unwrap();
+        assert_eq!(witness.signatures.len(), 1);
+    }
+
+    #[test]
+    fn test_melt_quote_sub_sat_fee_reserve() {
+        let quote = MeltQuote::from_msat("q".to_string(), 100_000, 1_500);
+        assert_eq!(quote.amount, 100);
+        assert_eq!(quote.fee_reserve, 2);
+        assert_eq!(quote.total_amount(), 102);
+
+        // Less than a single sat of fee reserve still reserves a whole sat
+        let quote = MeltQuote::from_msat("q".to_string(), 100_000, 1);
+        assert_eq!(quote.fee_reserve, 1);
+    }
+
+    #[test]
+    fn test_proof_y_cached() {
+        let proof = Proof {
+            id: None,
+            amount: 1,
+            secret: "test_message".to_string(),
+            unblinded_key: vec![],
+            dleq: None,
+            witness: None,
+        };
+        let cache = crate::crypto::HashToCurveCache::new(16);
+        assert_eq!(proof.y_cached(&cache), proof.y());
+        assert_eq!(proof.y_cached(&cache), proof.y());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_proof_from_signature() {
+        use crate::crypto::{MintKey, Secret};
+
+        let key = MintKey::from(k256::SecretKey::from_slice(&[7; 32]).unwrap());
+        let keys = BTreeMap::from([(8, key.public_key())]);
+        let keyset = Keyset {
+            id: Keyset::derive_id(&keys).parse().unwrap(),
+            keys,
+        };
+        let secret =
+            Secret::from_utf8("407915bc212be61a77e3e6d2aeb4c727980bda51cd06a6afc29e2861768a7837")
+                .unwrap();
+        let r = k256::SecretKey::random(&mut rand::thread_rng());
+        let output = BlindedMessage {
+            amount: 8,
+            id: Some(keyset.id.to_string()),
+            blinded_message: secret
+                .blinded_message(&r)
+                .unwrap()
+                .to_compressed_bytes()
+                .to_vec(),
+        };
+        let signature = key.sign_outputs(&[output], &keyset).unwrap().remove(0);
+
+        let proof = Proof::from_signature(&signature, &secret, &r, &keyset).unwrap();
+        assert_eq!(proof.id.as_ref(), Some(&keyset.id));
+        assert_eq!(proof.amount, 8);
+        assert_eq!(proof.secret, secret.as_str());
+        let c = crate::crypto::UnblindedKey::from_hex(&hex::encode(&proof.unblinded_key)).unwrap();
+        assert!(key.verify(&secret, &c));
+        assert!(proof.verify_dleq_own(&key.public_key()));
+
+        let wrong_amount = BlindedSignature {
+            amount: 4,
+            ..signature.clone()
+        };
+        assert!(matches!(
+            Proof::from_signature(&wrong_amount, &secret, &r, &keyset),
+            Err(crate::Error::UnknownAmount(4))
+        ));
+        let wrong_keyset = BlindedSignature {
+            id: Some("00ffffffffffffff".parse().unwrap()),
+            ..signature
+        };
+        assert!(matches!(
+            Proof::from_signature(&wrong_keyset, &secret, &r, &keyset),
+            Err(crate::Error::UnexpectedKeyset { .. })
+        ));
+    }
+
+    // Keyset id vector: https://github.com/cashubtc/nuts/blob/main/test-vectors/02-tests.md
+    const KEYSET_JSON: &str = r#"{
+        "id": "00456a94ab4e1c46",
+        "unit": "sat",
+        "keys": {
+            "1": "03a40f20667ed53513075dc51e715ff2046cad64eb68960632269ba7f0210e38bc",
+            "2": "03fd4ce5a16b65576145949e6f99f445f8249fee17c606b688b504a849cdc452de",
+            "4": "02648eccfa4c026960966276fa5a4cae46ce0fd432211a4f449bf84f13aa5f8303",
+            "8": "02fdfd6796bfeac490cbee12f778f867f0a2c68f6508d17c649759ea0dc3547528"
+        }
+    }"#;
+
+    #[test]
+    fn test_keyset_derive_id() {
+        let response: KeysetResponse = serde_json::from_str(KEYSET_JSON).unwrap();
+        let keyset = Keyset::try_from(response).unwrap();
+        assert_eq!(Keyset::derive_id(&keyset.keys), "00456a94ab4e1c46");
+    }
+
+    #[test]
+    fn test_keyset_deserialize() {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+        let keyset: Keyset = serde_json::from_str(KEYSET_JSON).unwrap();
+        assert_eq!(keyset.id, "00456a94ab4e1c46");
+        assert_eq!(
+            keyset.keys.keys().copied().collect::<Vec<_>>(),
+            vec![1, 2, 4, 8]
+        );
+
+        let uncompressed = hex::encode(keyset.keys[&1].to_encoded_point(false).as_bytes());
+        for (from, to) in [
+            (
+                "03a40f20667ed53513075dc51e715ff2046cad64eb68960632269ba7f0210e38bc",
+                "02abcd",
+            ),
+            (
+                "03a40f20667ed53513075dc51e715ff2046cad64eb68960632269ba7f0210e38bc",
+                uncompressed.as_str(),
+            ),
+            (r#""1":"#, r#""one":"#),
+        ] {
+            let json = KEYSET_JSON.replace(from, to);
+            assert!(serde_json::from_str::<Keyset>(&json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn test_keyset_id_version() {
+        assert_eq!(
+            keyset_id_version("00456a94ab4e1c46"),
+            Some(KeysetIdVersion::V1)
+        );
+        assert_eq!(keyset_id_version("I2yN+iRYfkzT"), Some(KeysetIdVersion::V0));
+        assert_eq!(keyset_id_version("00456a94ab4e1c4"), None);
+        assert_eq!(keyset_id_version("I2yN+iRYfk=T"), None);
+
+        // Legacy keysets are checked against their legacy id
+        let mut response: KeysetResponse = serde_json::from_str(KEYSET_JSON).unwrap();
+        let keys = Keyset::try_from(response.clone()).unwrap().keys;
+        let legacy = Keyset::derive_id_v0(&keys);
+        assert_eq!(keyset_id_version(&legacy), Some(KeysetIdVersion::V0));
+        response.id = legacy.clone();
+        assert_eq!(Keyset::try_from(response.clone()).unwrap().id, legacy);
+        response.id = "I2yN+iRYfkzT".to_string();
+        assert!(matches!(
+            Keyset::try_from(response),
+            Err(crate::Error::InvalidKeyset { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keys_response_skips_invalid_keyset() {
+        let valid: KeysetResponse = serde_json::from_str(KEYSET_JSON).unwrap();
+        let mut corrupt = valid.clone();
+        corrupt.id = "00ffffffffffffff".to_string();
+        corrupt.keys.insert("16".to_string(), "02abcd".to_string());
+        let response = KeysResponse {
+            keysets: vec![corrupt, valid],
+        };
+
+        let keysets = response.into_keysets();
+        assert!(matches!(
+            &keysets[0],
+            Err(crate::Error::InvalidKeyset { id, .. }) if id == "00ffffffffffffff"
+        ));
+        assert_eq!(keysets[1].as_ref().unwrap().id, "00456a94ab4e1c46");
+    }
+
+    #[test]
+    fn test_keyset_id_mismatch() {
+        let mut response: KeysetResponse = serde_json::from_str(KEYSET_JSON).unwrap();
+        response.id = "00456a94ab4e1c47".to_string();
+        assert!(matches!(
+            Keyset::try_from(response),
+            Err(crate::Error::InvalidKeyset { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keyset_pubkey_for_proof() {
+        let keyset: Keyset = serde_json::from_str(KEYSET_JSON).unwrap();
+        let keysets = [keyset.clone()];
+        let mut proof = proof("a", 2);
+        proof.id = Some(keyset.id.clone());
+        assert_eq!(
+            keyset_pubkey_for_proof(&proof, &keysets).unwrap(),
+            keyset.keys[&2]
+        );
+
+        proof.amount = 3;
+        assert!(matches!(
+            keyset_pubkey_for_proof(&proof, &keysets),
+            Err(crate::Error::UnknownAmount(3))
+        ));
+
+        proof.id = Some("009a1f293253e41e".parse().unwrap());
+        assert!(matches!(
+            keyset_pubkey_for_proof(&proof, &keysets),
+            Err(crate::Error::UnknownKeyset(id)) if id == "009a1f293253e41e"
+        ));
+
+        proof.id = None;
+        assert!(matches!(
+            keyset_pubkey_for_proof(&proof, &keysets),
+            Err(crate::Error::MissingKeysetId(_))
+        ));
+    }
+
+    fn proof(secret: &str, amount: u64) -> Proof {
+        Proof {
+            id: Some("009a1f293253e41e".parse().unwrap()),
+            amount,
+            secret: secret.to_string(),
+            unblinded_key: hex::decode("abcd").unwrap(),
+            dleq: None,
+            witness: None,
+        }
+    }
+
+    #[test]
+    fn test_proofs_append_and_extend() {
+        let mut proofs = Proofs::from(vec![proof("a", 1)]);
+        proofs.append(Proofs::from(vec![proof("b", 2), proof("c", 4)]));
+        proofs.extend(vec![proof("d", 8)]);
+        assert_eq!(proofs.as_ref().len(), 4);
+        assert_eq!(proofs.total(), 15);
+    }
+
+    #[test]
+    fn test_input_fee() {
+        let fee_rates = HashMap::from([("009a1f293253e41e".to_string(), 100)]);
+        let mut proofs = Proofs::from(vec![proof("a", 1)]);
+        assert_eq!(proofs.input_fee(&fee_rates), 1);
+        proofs.extend((0..9).map(|i| proof(&format!("s{i}"), 1)));
+        assert_eq!(proofs.input_fee(&fee_rates), 1);
+        proofs.extend(std::iter::once(proof("b", 1)));
+        assert_eq!(proofs.input_fee(&fee_rates), 2);
+
+        proofs.as_mut()[0].id = Some("00ad268c4d1f5826".parse().unwrap());
+        assert_eq!(proofs.input_fee(&fee_rates), 1);
+        assert_eq!(proofs.input_fee(&HashMap::new()), 0);
+    }
+
+    #[test]
+    fn test_denomination_histogram() {
+        let proofs = Proofs::from(
+            [8, 1, 2, 8, 1, 1, 64]
+                .iter()
+                .enumerate()
+                .map(|(i, amount)| proof(&format!("s{i}"), *amount))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            proofs.denomination_histogram(),
+            BTreeMap::from([(1, 3), (2, 1), (8, 2), (64, 1)])
+        );
+        assert!(Proofs::default().denomination_histogram().is_empty());
+    }
+
+    #[test]
+    fn test_split_at_amount() {
+        let proofs = Proofs::from(
+            [2, 16, 1, 4, 8, 4]
+                .iter()
+                .enumerate()
+                .map(|(i, amount)| proof(&format!("s{i}"), *amount))
+                .collect::<Vec<_>>(),
+        );
+        let (spend, rest) = proofs.clone().split_at_amount(21);
+        assert_eq!(
+            spend.denomination_histogram(),
+            BTreeMap::from([(8, 1), (16, 1)])
+        );
+        assert_eq!(rest.total(), 11);
+
+        let (spend, rest) = proofs.clone().split_at_amount(28);
+        assert_eq!(spend.total(), 28);
+        assert_eq!(spend.as_ref().len(), 3);
+        assert_eq!(rest.as_ref().len(), 3);
+
+        let (spend, rest) = proofs.clone().split_at_amount(0);
+        assert_eq!(spend.total(), 0);
+        assert_eq!(rest.total(), 35);
+
+        let (spend, rest) = proofs.split_at_amount(36);
+        assert_eq!(spend.total(), 35);
+        assert!(rest.as_ref().is_empty());
+    }
+
+    #[test]
+    fn test_proofs_remove_by_identity() {
+        let mut proofs = Proofs::from(vec![proof("a", 1), proof("b", 2), proof("c", 4)]);
+
+        // Same secret and C, but different metadata, still identifies the proof
+        let mut spent = proof("b", 2);
+        spent.id = None;
+        spent.amount = 64;
+        // Same secret with a different C is a different proof
+        let mut other = proof("c", 4);
+        other.unblinded_key = hex::decode("ef01").unwrap();
+
+        proofs.remove(&Proofs::from(vec![spent, other]));
+        let secrets: Vec<&str> = proofs.as_ref().iter().map(|p| p.secret.as_str()).collect();
+        assert_eq!(secrets, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_quote_expiry() {
+        let quote: MintQuote =
+            serde_json::from_str(r#"{"quote":"q1","request":"lnbc1","expiry":1000}"#).unwrap();
+        assert!(!quote.is_expired(999));
+        assert!(!quote.is_expired(1000));
+        assert!(quote.is_expired(1001));
+        assert!(quote.ensure_unexpired(1000).is_ok());
+        assert!(matches!(
+            quote.ensure_unexpired(1001),
+            Err(crate::Error::QuoteExpired { quote, expiry: 1000 }) if quote == "q1"
+        ));
+
+        // Quotes without an expiry never expire
+        let quote: MeltQuote =
+            serde_json::from_str(r#"{"quote":"q2","amount":10,"fee_reserve":2}"#).unwrap();
+        assert_eq!(quote.expiry, None);
+        assert!(!quote.is_expired(u64::MAX));
+        assert!(quote.ensure_unexpired(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_mint_url() {
+        let mint = r#"{"mint":"https://8333.space:3338","proofs":[]}"#;
+        let mint: MintToken = serde_json::from_str(mint).unwrap();
+        assert_eq!(mint.mint.as_str(), "https://8333.space:3338/");
+        let url = MintUrl::try_from("https://8333.space:3338").unwrap();
+        assert_eq!(Url::from(url.clone()), mint.mint);
+        assert_eq!(url.host_str(), Some("8333.space"));
+        assert_eq!("https://8333.space:3338".parse::<MintUrl>().unwrap(), url);
+
+        for url in [
+            "file:///etc/passwd",
+            "mailto:mint@example.com",
+            "data:text/plain,mint",
+        ] {
+            let json = format!(r#"{{"mint":"{url}","proofs":[]}}"#);
+            let err = serde_json::from_str::<MintToken>(&json).unwrap_err();
+            assert!(err.to_string().contains("Invalid mint URL"), "{url}");
+            assert!(matches!(
+                parse_mint_url(url),
+                Err(crate::Error::InvalidMintUrl(_))
+            ));
+            assert!(MintUrl::try_from(url).is_err());
+        }
+
+        let token = format!(
+            "cashuA{}",
+            URL_SAFE.encode(r#"{"token":[{"mint":"file:///tmp/mint","proofs":[]}]}"#)
+        );
+        assert!(matches!(
+            Token::deserialize(&token),
+            Err(crate::Error::TokenV3(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn test_token_too_large() {
+        let token = Token {
+            token: vec![],
+            memo: Some("memo".to_string()),
+            unit: None,
+        }
+        .serialize()
+        .unwrap();
+        assert!(Token::deserialize_with_limit(&token, token.len()).is_ok());
+        assert!(matches!(
+            Token::deserialize_with_limit(&token, token.len() - 1),
+            Err(crate::Error::TokenTooLarge { length, max }) if length == token.len() && max == length - 1
+        ));
+
+        // Rejected on length alone, without being decoded
+        let oversized = format!("cashuA{}", "!".repeat(MAX_TOKEN_LENGTH));
+        assert!(matches!(
+            Token::deserialize(&oversized),
+            Err(crate::Error::TokenTooLarge { .. })
+        ));
+        let oversized = format!("cashuB{}", "!".repeat(MAX_TOKEN_LENGTH));
+        assert!(matches!(
+            Token::deserialize_v4(&oversized),
+            Err(crate::Error::TokenTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_token_describe() {
+        let key = k256::SecretKey::random(&mut rand::thread_rng());
+        let locked = crate::mock::p2pk_secret(&key, crate::SigFlag::SigInputs);
+        let a = Url::parse("https://a.example.com").unwrap();
+        let b = Url::parse("https://b.example.com").unwrap();
+        let mut token = TokenBuilder::new()
+            .add_proofs(
+                a.clone(),
+                Proofs::from(vec![proof("a", 1), proof(&locked, 4)]),
+            )
+            .add_proofs(b.clone(), Proofs::from(vec![proof("b", 8)]))
+            .build();
+        token.token[1].unit = Some("usd".to_string());
+        token.token[1].proofs.as_mut()[0].id = None;
+
+        let descriptions = token.describe();
+        assert_eq!(
+            descriptions
+                .iter()
+                .map(|d| (&d.mint, d.amount, &d.unit, d.is_locked()))
+                .collect::<Vec<_>>(),
+            vec![
+                (&a, 1, &Unit::Sat, false),
+                (&a, 4, &Unit::Sat, true),
+                (&b, 8, &Unit::Usd, false),
+            ]
+        );
+        assert_eq!(descriptions[1].kind, crate::SecretKind::P2PK);
+        assert_eq!(
+            descriptions[1].to_string(),
+            "4 sat from https://a.example.com/, keyset 009a1f293253e41e, locked to a key"
+        );
+        assert_eq!(
+            descriptions[2].to_string(),
+            "8 usd from https://b.example.com/"
+        );
+    }
+
+    #[test]
+    fn test_token_compressed() {
+        let proofs: Vec<Proof> = (0..20)
+            .map(|i| proof(&format!("secret{i}"), 1 << i))
+            .collect();
+        let token = TokenBuilder::new()
+            .add_proofs(
+                "https://8333.space:3338".parse().unwrap(),
+                Proofs::from(proofs),
+            )
+            .memo("Thank you.")
+            .build();
+        let compressed = token.serialize_compressed().unwrap();
+        assert!(compressed.starts_with("cashuZ"));
+        assert!(compressed.len() < token.serialize().unwrap().len());
+        assert_eq!(Token::deserialize(&compressed).unwrap(), token);
+        assert_eq!(parse_tokens(&[&compressed]).pop().unwrap().unwrap(), token);
+
+        // The uncompressed form is untouched
+        let serialized = token.serialize().unwrap();
+        assert!(serialized.starts_with("cashuA"));
+        let json = URL_SAFE.decode(&serialized["cashuA".len()..]).unwrap();
+        assert_eq!(serde_json::from_slice::<Token>(&json).unwrap(), token);
+
+        // The inflated JSON counts against the length limit too
+        assert!(matches!(
+            Token::deserialize_with_limit(&compressed, compressed.len()),
+            Err(crate::Error::TokenTooLarge { max, .. }) if max == compressed.len()
+        ));
+        assert!(matches!(
+            Token::deserialize("cashuZAAAA"),
+            Err(crate::Error::TokenV3(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn test_token_unit_placement() {
+        let json = r#"{"token":[{"mint":"https://8333.space:3338","proofs":[]},{"mint":"https://example.com","proofs":[],"unit":"sat"}],"memo":null,"unit":"usd"}"#;
+        let token = Token::deserialize(&format!("cashuA{}", URL_SAFE.encode(json))).unwrap();
+        assert_eq!(token.unit.as_deref(), Some("usd"));
+        assert_eq!(token.mint_unit(0), Some("usd"));
+        assert_eq!(token.mint_unit(1), Some("sat"));
+
+        let json = r#"{"token":[{"mint":"https://8333.space:3338","proofs":[]}],"memo":null}"#;
+        let token = Token::deserialize(&format!("cashuA{}", URL_SAFE.encode(json))).unwrap();
+        assert_eq!(token.mint_unit(0), None);
+    }
+
+    #[test]
+    fn test_token_semantically_eq() {
+        let a = Url::parse("https://a.example.com").unwrap();
+        let b = Url::parse("https://b.example.com").unwrap();
+        let token = TokenBuilder::new()
+            .add_proofs(a.clone(), Proofs::from(vec![proof("1", 1), proof("2", 2)]))
+            .add_proofs(b.clone(), Proofs::from(vec![proof("4", 4)]))
+            .build();
+        let reordered = TokenBuilder::new()
+            .add_proofs(b.clone(), Proofs::from(vec![proof("4", 4)]))
+            .add_proofs(a.clone(), Proofs::from(vec![proof("2", 2), proof("1", 1)]))
+            .build();
+        assert_ne!(token, reordered);
+        assert!(token.semantically_eq(&reordered));
+        assert!(reordered.semantically_eq(&token));
+
+        // The same proof under another mint, or a missing proof, differs
+        let moved = TokenBuilder::new()
+            .add_proofs(a.clone(), Proofs::from(vec![proof("1", 1)]))
+            .add_proofs(b.clone(), Proofs::from(vec![proof("2", 2), proof("4", 4)]))
+            .build();
+        assert!(!token.semantically_eq(&moved));
+        let missing = TokenBuilder::new()
+            .add_proofs(a, Proofs::from(vec![proof("1", 1), proof("2", 2)]))
+            .build();
+        assert!(!token.semantically_eq(&missing));
+    }
+
+    #[test]
+    fn test_token_canonical_json() {
+        let a = Url::parse("https://a.example.com").unwrap();
+        let b = Url::parse("https://b.example.com").unwrap();
+        let token = TokenBuilder::new()
+            .add_proofs(a.clone(), Proofs::from(vec![proof("1", 1), proof("2", 2)]))
+            .add_proofs(b.clone(), Proofs::from(vec![proof("4", 4)]))
+            .build();
+        let reordered = TokenBuilder::new()
+            .add_proofs(b.clone(), Proofs::from(vec![proof("4", 4)]))
+            .add_proofs(a.clone(), Proofs::from(vec![proof("2", 2), proof("1", 1)]))
+            .build();
+        let canonical = token.to_canonical_json().unwrap();
+        assert_eq!(canonical, reordered.to_canonical_json().unwrap());
+
+        // The same token parsed from JSON with fields in another order
+        let json = serde_json::to_string(&reordered).unwrap();
+        let parsed: Token = serde_json::from_str(
+            &json.replace(r#""amount":1,"secret":"1""#, r#""secret":"1","amount":1"#),
+        )
+        .unwrap();
+        assert_eq!(parsed.to_canonical_json().unwrap(), canonical);
+
+        assert!(canonical.starts_with(r#"{"memo":null,"token":[{"mint":"https://a.example.com/","proofs":[{"C":"abcd","amount":1,"id":"009a1f293253e41e","#));
+        let other = TokenBuilder::new()
+            .add_proofs(a, Proofs::from(vec![proof("1", 1)]))
+            .build();
+        assert_ne!(other.to_canonical_json().unwrap(), canonical);
+    }
+
+    #[test]
+    fn test_parse_tokens() {
+        let mint = crate::mock::MockMint::new("mint");
+        let token = TokenBuilder::new()
+            .add_proofs(
+                Url::parse("https://8333.space:3338").unwrap(),
+                mint.mint_proofs(&[1, 2]),
+            )
+            .build();
+        let v3 = token.serialize().unwrap();
+        let v4 = format!("{}\n", token.serialize_v4().unwrap());
+        let parsed = parse_tokens(&[&v3, "cashuAnotatoken", &v4, "hello"]);
+
+        assert_eq!(parsed.len(), 4);
+        assert_eq!(parsed[0].as_ref().unwrap(), &token);
+        assert!(matches!(parsed[1], Err(crate::Error::TokenV3(_))));
+        assert_eq!(
+            parsed[2].as_ref().unwrap().token[0].proofs,
+            token.token[0].proofs
+        );
+        assert!(matches!(parsed[3], Err(crate::Error::TokenV3(None))));
+        assert!(parse_tokens(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_token_into_proofs() {
+        let a = Url::parse("https://a.example.com").unwrap();
+        let b = Url::parse("https://b.example.com").unwrap();
+        let mut token = TokenBuilder::new()
+            .add_proofs(a.clone(), Proofs::from(vec![proof("1", 1), proof("2", 2)]))
+            .add_proofs(b.clone(), Proofs::from(vec![proof("4", 4)]))
+            .unit("sat")
+            .build();
+        token.token[1].unit = Some("usd".to_string());
+
+        let flat = token.into_proofs();
+        let tags: Vec<_> = flat
+            .iter()
+            .map(|(mint, proof, unit)| (mint, proof.secret.as_str(), unit.clone()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                (&a, "1", Some(Unit::Sat)),
+                (&a, "2", Some(Unit::Sat)),
+                (&b, "4", Some(Unit::Usd)),
+            ]
+        );
+
+        let token = TokenBuilder::new()
+            .add_proofs(a, Proofs::from(vec![proof("1", 1)]))
+            .build();
+        assert_eq!(token.into_proofs()[0].2, None);
+    }
+
+    #[test]
+    fn test_token_mints() {
+        let json = r#"{"token":[{"mint":"https://8333.space:3338","proofs":[]},{"mint":"https://example.com/cashu","proofs":[]},{"mint":"https://8333.space:3338/","proofs":[]},{"mint":"https://example.com/cashu/","proofs":[]}]}"#;
+        let token = Token::deserialize(&format!("cashuA{}", URL_SAFE.encode(json))).unwrap();
+        let mints: Vec<&str> = token.mints().into_iter().map(Url::as_str).collect();
+        assert_eq!(
+            mints,
+            vec!["https://8333.space:3338/", "https://example.com/cashu"]
+        );
+    }
+
+    #[test]
+    fn test_check_mints_allowed() {
+        let json = r#"{"token":[{"mint":"https://Mint.example.com:443/cashu","proofs":[]},{"mint":"https://other.example.com","proofs":[]}]}"#;
+        let token = Token::deserialize(&format!("cashuA{}", URL_SAFE.encode(json))).unwrap();
+        let trusted = Url::parse("https://mint.example.com/cashu/").unwrap();
+        let other = Url::parse("https://other.example.com").unwrap();
+
+        assert!(token
+            .check_mints_allowed(&[other.clone(), trusted.clone()])
+            .is_ok());
+        assert!(matches!(
+            token.check_mints_allowed(&[trusted]),
+            Err(crate::Error::MintNotAllowed(mint)) if mint == other
+        ));
+        assert!(matches!(
+            token.check_mints_allowed(&[Url::parse("http://mint.example.com/cashu").unwrap()]),
+            Err(crate::Error::MintNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn test_active_keyset() {
+        let response: KeysetsResponse = serde_json::from_str(
+            r#"{"keysets": [
+                {"id": "009a1f293253e41e", "unit": "sat", "active": false},
+                {"id": "00ad268c4d1f5826", "unit": "sat", "active": true},
+                {"id": "00456a94ab4e1c46", "unit": "usd", "active": false},
+                {"id": "0042ade98b2a370a", "unit": "btc", "active": true}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.keysets[3].unit, Unit::Custom("btc".to_string()));
+        assert_eq!(response.active(&Unit::Sat).unwrap().id, "00ad268c4d1f5826");
+        assert!(matches!(
+            response.active(&Unit::Usd),
+            Err(crate::Error::NoActiveKeyset(Unit::Usd))
+        ));
+
+        let mut response = response;
+        response.keysets[0].active = true;
+        assert!(matches!(
+            response.active(&Unit::Sat),
+            Err(crate::Error::AmbiguousActiveKeyset(Unit::Sat))
+        ));
+    }
+
+    #[test]
+    fn test_quote_state() {
+        let states = [
+            ("UNPAID", QuoteState::Unpaid, false, true),
+            ("PENDING", QuoteState::Pending, false, false),
+            ("PAID", QuoteState::Paid, true, false),
+            ("ISSUED", QuoteState::Issued, false, false),
+        ];
+        for (name, state, can_mint, can_melt) in states {
+            let quote: MintQuote = serde_json::from_str(&format!(
+                r#"{{"quote":"q","request":"lnbc1","expiry":null,"state":"{name}"}}"#
+            ))
+            .unwrap();
+            assert_eq!(quote.state(), state);
+            assert_eq!(quote.can_mint(), can_mint);
+            assert_eq!(state.can_melt(), can_melt);
+        }
+    }
+
+    #[test]
+    fn test_quote_state_legacy_paid() {
+        let quote: MintQuote =
+            serde_json::from_str(r#"{"quote":"q","request":"lnbc1","expiry":null,"paid":true}"#)
+                .unwrap();
+        assert_eq!(quote.state(), QuoteState::Paid);
+        assert!(quote.can_mint());
+
+        let quote: MeltQuote =
+            serde_json::from_str(r#"{"quote":"q","amount":1,"fee_reserve":1,"paid":false}"#)
+                .unwrap();
+        assert_eq!(quote.state(), QuoteState::Unpaid);
+        assert!(quote.can_melt());
+
+        // The state wins when a mint sends both
+        let quote: MeltQuote = serde_json::from_str(
+            r#"{"quote":"q","amount":1,"fee_reserve":1,"paid":false,"state":"PENDING"}"#,
+        )
+        .unwrap();
+        assert_eq!(quote.state(), QuoteState::Pending);
+    }
+
+    #[test]
+    fn test_extract_exact() {
+        let mint = crate::mock::MockMint::new("mint");
+        let token = Token {
+            token: vec![
+                MintToken {
+                    mint: Url::parse("https://a.example.com").unwrap(),
+                    proofs: mint.mint_proofs(&[1, 4, 8]),
+                    unit: None,
+                    memo: None,
+                },
+                MintToken {
+                    mint: Url::parse("https://b.example.com").unwrap(),
+                    proofs: mint.mint_proofs(&[2, 16]),
+                    unit: None,
+                    memo: None,
+                },
+            ],
+            memo: Some("memo".to_string()),
+            unit: Some("sat".to_string()),
+        };
+
+        let (exact, rest) = token.extract_exact(11).unwrap();
+        let total = |t: &Token| t.token.iter().map(|t| t.proofs.total()).sum::<u64>();
+        assert_eq!(total(&exact), 11);
+        assert_eq!(total(&rest), 20);
+        assert_eq!(exact.token.len(), 2);
+        assert_eq!(exact.memo, token.memo);
+
+        // Taking everything from one mint leaves it out of the remainder
+        let (exact, rest) = token.extract_exact(13).unwrap();
+        assert_eq!(exact.token.len(), 1);
+        assert_eq!(exact.token[0].mint, token.token[0].mint);
+        assert_eq!(rest.token.len(), 1);
+
+        let (exact, rest) = token.extract_exact(0).unwrap();
+        assert!(exact.token.is_empty());
+        assert_eq!(total(&rest), 31);
+
+        assert!(token.extract_exact(32).is_none());
+        let token = Token {
+            token: vec![MintToken {
+                mint: Url::parse("https://a.example.com").unwrap(),
+                proofs: mint.mint_proofs(&[4, 8]),
+                unit: None,
+                memo: None,
+            }],
+            memo: None,
+            unit: None,
+        };
+        assert!(token.extract_exact(6).is_none());
+
+        // Too many proofs, or too many sums, to search
+        let single = |proofs| Token {
+            token: vec![MintToken {
+                mint: Url::parse("https://a.example.com").unwrap(),
+                proofs,
+                unit: None,
+                memo: None,
+            }],
+            memo: None,
+            unit: None,
+        };
+        let token = single(mint.mint_proofs(&[1; MAX_EXACT_PROOFS + 1]));
+        assert!(token.extract_exact(1).is_none());
+        let amounts: Vec<u64> = (0..15).map(|i| 1 << i).collect();
+        let token = single(mint.mint_proofs(&amounts));
+        assert!(token.extract_exact((1 << 15) - 1).is_none());
+        assert!(token.extract_exact(5).is_some());
+    }
+
+    #[test]
+    fn test_signature_keyset_consistent() {
+        let mint = crate::mock::MockMint::new("mint");
+        let other = crate::mock::MockMint::new("other");
+        let output = BlindedMessage {
+            amount: 8,
+            id: Some(mint.keyset.id.to_string()),
+            blinded_message: crate::crypto::Secret::from_utf8("test_message")
+                .unwrap()
+                .blinded_message(&k256::SecretKey::from_slice(&[1; 32]).unwrap())
+                .unwrap()
+                .to_compressed_bytes()
+                .to_vec(),
+        };
+        let mut signature = mint.sign(&output);
+        assert!(signature_keyset_consistent(&signature, &mint.keyset));
+        assert!(!signature_keyset_consistent(&signature, &other.keyset));
+
+        signature.amount = 3;
+        assert!(!signature_keyset_consistent(&signature, &mint.keyset));
+
+        signature.amount = 8;
+        signature.id = None;
+        assert!(!signature_keyset_consistent(&signature, &mint.keyset));
+    }
+
+    #[test]
+    fn test_validate_mixed_units() {
+        let sat = crate::mock::MockMint::new("sat");
+        let usd = crate::mock::MockMint::new("usd");
+        let keysets = vec![
+            KeysetInfo {
+                id: sat.keyset.id.to_string(),
+                unit: Unit::Sat,
+                active: true,
+                input_fee_ppk: 0,
+                max_order: None,
+            },
+            KeysetInfo {
+                id: usd.keyset.id.to_string(),
+                unit: Unit::Usd,
+                active: true,
+                input_fee_ppk: 0,
+                max_order: None,
+            },
+        ];
+        let mut proofs = sat.mint_proofs(&[1, 2]);
+        let mut token = TokenBuilder::new()
+            .add_proofs(
+                Url::parse("https://a.example.com").unwrap(),
+                sat.mint_proofs(&[4]),
+            )
+            .build();
+        assert!(token.validate(&keysets).is_ok());
+
+        // A declared unit must agree with the keysets
+        token.unit = Some("usd".to_string());
+        assert!(matches!(
+            token.validate(&keysets),
+            Err(crate::Error::MixedUnits { .. })
+        ));
+        token.unit = Some("sat".to_string());
+        assert!(token.validate(&keysets).is_ok());
+
+        proofs.append(usd.mint_proofs(&[1]));
+        token.token[0].proofs.append(proofs);
+        let Err(crate::Error::MixedUnits { mint, units }) = token.validate(&keysets) else {
+            panic!("mixed units accepted");
+        };
+        assert_eq!(mint.as_str(), "https://a.example.com/");
+        assert_eq!(units, vec![Unit::Sat, Unit::Usd]);
+
+        // Proofs from keysets nobody knows about don't count against the token
+        assert!(token.validate(&keysets[..1]).is_ok());
+    }
+
+    #[test]
+    fn test_infer_units() {
+        let usd = crate::mock::MockMint::new("usd");
+        let unknown = crate::mock::MockMint::new("unknown");
+        let keysets = vec![KeysetInfo {
+            id: usd.keyset.id.to_string(),
+            unit: Unit::Usd,
+            active: false,
+            input_fee_ppk: 0,
+            max_order: None,
+        }];
+        let mut proofs = unknown.mint_proofs(&[1]);
+        proofs.append(usd.mint_proofs(&[2]));
+        let mut token = TokenBuilder::new()
+            .add_proofs(Url::parse("https://a.example.com").unwrap(), proofs)
+            .add_proofs(
+                Url::parse("https://b.example.com").unwrap(),
+                unknown.mint_proofs(&[4]),
+            )
+            .build();
+        token.infer_units(&keysets);
+        assert_eq!(token.mint_unit(0), Some("usd"));
+        assert_eq!(token.mint_unit(1), None);
+
+        // A unit the token already states is kept
+        token.token[0].unit = None;
+        token.unit = Some("sat".to_string());
+        token.infer_units(&keysets);
+        assert_eq!(token.token[0].unit, None);
+        assert_eq!(token.mint_unit(0), Some("sat"));
+    }
+
+    #[test]
+    fn test_validate_keysets() {
+        let keyset = |id: &str, unit: Unit, active: bool| KeysetInfo {
+            id: id.to_string(),
+            unit,
+            active,
+            input_fee_ppk: 0,
+            max_order: None,
+        };
+        let mut keysets = vec![
+            keyset("009a1f293253e41e", Unit::Sat, true),
+            keyset("00ad268c4d1f5826", Unit::Sat, false),
+            keyset("00456a94ab4e1c46", Unit::Usd, true),
+        ];
+        assert!(validate_keysets(&keysets).is_ok());
+        assert!(validate_keysets(&[]).is_ok());
+
+        keysets[1].active = true;
+        assert!(matches!(
+            validate_keysets(&keysets),
+            Err(crate::Error::AmbiguousActiveKeyset(Unit::Sat))
+        ));
+
+        keysets[1] = keyset("009a1f293253e41e", Unit::Usd, false);
+        assert!(matches!(
+            validate_keysets(&keysets),
+            Err(crate::Error::DuplicateKeyset(id)) if id == "009a1f293253e41e"
+        ));
+
+        keysets[1] = keyset("I2yN+iRYfkzT", Unit::Sat, false);
+        assert!(validate_keysets(&keysets).is_ok());
+
+        for id in [
+            "009a1f293253e4",
+            "019a1f293253e41e",
+            "00zz1f293253e41e",
+            "I2yN-iRYfkzT",
+            "I2yN+iRYfkz",
+        ] {
+            assert!(matches!(
+                validate_keysets(&[keyset(id, Unit::Sat, true)]),
+                Err(crate::Error::InvalidKeyset { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_error_response_without_code() {
+        let error: ErrorResponse =
+            serde_json::from_str(r#"{"detail": "Lightning payment failed"}"#).unwrap();
+        assert_eq!(error.code, 0);
+        assert_eq!(error.detail, "Lightning payment failed");
+    }
 }
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::Proof;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretKind {
+    Plain,
+    P2PK,
+    Htlc,
+}
+
+// NUT-10: a secret of the form ["kind", {"nonce": ..., "data": ..., "tags": [...]}], spendable
+// only under the conditions the kind describes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "(String, SecretData)", into = "(String, SecretData)")]
+pub struct WellKnownSecret {
+    pub kind: String,
+    pub data: SecretData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretData {
+    pub nonce: String,
+    pub data: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Vec<String>>,
+}
+
+impl WellKnownSecret {
+    // None for anything that isn't a well-formed well-known secret, including JSON that only
+    // resembles one
+    pub fn parse(secret: &str) -> Option<WellKnownSecret> {
+        serde_json::from_str(secret).ok()
+    }
+
+    pub fn secret_kind(&self) -> SecretKind {
+        match self.kind.as_str() {
+            "P2PK" => SecretKind::P2PK,
+            "HTLC" => SecretKind::Htlc,
+            _ => SecretKind::Plain,
+        }
+    }
+
+    // The values of the first tag with this name
+    pub fn tag(&self, name: &str) -> Option<&[String]> {
+        self.data
+            .tags
+            .iter()
+            .find(|t| t.first().is_some_and(|n| n == name))
+            .map(|t| &t[1..])
+    }
+}
+
+impl From<(String, SecretData)> for WellKnownSecret {
+    fn from((kind, data): (String, SecretData)) -> WellKnownSecret {
+        WellKnownSecret { kind, data }
+    }
+}
+
+impl From<WellKnownSecret> for (String, SecretData) {
+    fn from(secret: WellKnownSecret) -> (String, SecretData) {
+        (secret.kind, secret.data)
+    }
+}
+
+// NUT-14: the preimage of an HTLC's hash lock
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HtlcWitness {
+    pub preimage: String,
+
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "crate::nut11::signatures::deserialize"
+    )]
+    pub signatures: Vec<String>,
+}
+
+impl Proof {
+    pub fn secret_kind(&self) -> SecretKind {
+        WellKnownSecret::parse(&self.secret).map_or(SecretKind::Plain, |s| s.secret_kind())
+    }
+
+    // Check the witness unlocks the secret. P2PK signatures must cover `message` (see
+    // p2pk_signing_message), and HTLCs are checked against their hash lock only. Plain secrets
+    // need no witness.
+    pub fn verify_witness(&self, message: &[u8]) -> Result<(), crate::Error> {
+        let Some(secret) = WellKnownSecret::parse(&self.secret) else {
+            return Ok(());
+        };
+        let kind = secret.secret_kind();
+        if kind == SecretKind::Plain {
+            return Ok(());
+        }
+        let witness = self
+            .witness
+            .as_deref()
+            .ok_or(crate::Error::MissingWitness)?;
+        let valid = match kind {
+            SecretKind::P2PK => self.verify_p2pk(message),
+            SecretKind::Htlc => serde_json::from_str::<HtlcWitness>(witness)
+                .ok()
+                .and_then(|w| hex::decode(w.preimage).ok())
+                .is_some_and(|preimage| hex::encode(Sha256::digest(preimage)) == secret.data.data),
+            SecretKind::Plain => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(crate::Error::InvalidWitness)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof(secret: &str) -> Proof {
+        Proof {
+            id: Some("009a1f293253e41e".parse().unwrap()),
+            amount: 1,
+            secret: secret.to_string(),
+            unblinded_key: vec![],
+            dleq: None,
+            witness: None,
+        }
+    }
+
+    // https://github.com/cashubtc/nuts/blob/main/11.md
+    const P2PK: &str = r#"["P2PK",{"nonce":"859d4935c4907062a6297cf4e663e2835d90d97ecdd510745d32f6816323a41f","data":"0249098aa8b9d2fbec49ff8598feb17b592b986e62319a4fa488a3dc36387157a7","tags":[["sigflag","SIG_INPUTS"]]}]"#;
+
+    #[test]
+    fn test_secret_kind() {
+        assert_eq!(
+            proof("407915bc212be61a77e3e6d2aeb4c727980bda51cd06a6afc29e2861768a7837").secret_kind(),
+            SecretKind::Plain
+        );
+        assert_eq!(proof(P2PK).secret_kind(), SecretKind::P2PK);
+        assert_eq!(
+            proof(r#"["HTLC",{"nonce":"da62796403af76c80cd6ce9153ed3746","data":"023192200a0cfd3867e48eb63b03ff599c7e46c8f4e41146b2d281173ca6c50c54"}]"#)
+                .secret_kind(),
+            SecretKind::Htlc
+        );
+
+        let secret = WellKnownSecret::parse(P2PK).unwrap();
+        assert_eq!(secret.tag("sigflag"), Some(&["SIG_INPUTS".to_string()][..]));
+        assert_eq!(serde_json::to_string(&secret).unwrap(), P2PK);
+    }
+
+    #[test]
+    fn test_secret_kind_near_misses() {
+        let near_misses = [
+            // Missing the nonce
+            r#"["P2PK",{"data":"0249098aa8b9d2fbec49ff8598feb17b592b986e62319a4fa488a3dc36387157a7"}]"#,
+            r#"["P2PK"]"#,
+            r#"{"kind":"P2PK","data":"02"}"#,
+            r#"["P2PK",{"nonce":"00","data":"02"},"extra"]"#,
+            r#"["P2PK",{"nonce":"00","data":"02"}"#,
+            // Unknown kinds are spendable as plain secrets
+            r#"["FOO",{"nonce":"00","data":"02"}]"#,
+        ];
+        for secret in near_misses {
+            assert_eq!(proof(secret).secret_kind(), SecretKind::Plain, "{secret}");
+        }
+    }
+
+    #[test]
+    fn test_verify_witness_missing() {
+        let locked = proof(P2PK);
+        assert!(matches!(
+            locked.verify_witness(locked.secret.as_bytes()),
+            Err(crate::Error::MissingWitness)
+        ));
+
+        let mut signed = proof(P2PK);
+        signed.witness = Some(r#"{"signatures":["60f3c9b766770b46caac1d27e1ae6b77c8866ebaeba0b9489fe6a15a837eaa6fcd6eaa825499c72ac342983983fd3ba3a8a41f56677cc99ffd73da68b59e1383"]}"#.to_string());
+        assert!(signed.verify_witness(signed.secret.as_bytes()).is_ok());
+        assert!(matches!(
+            signed.verify_witness(b"other message"),
+            Err(crate::Error::InvalidWitness)
+        ));
+
+        let plain = proof("407915bc212be61a77e3e6d2aeb4c727980bda51cd06a6afc29e2861768a7837");
+        assert!(plain.verify_witness(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_verify_witness_htlc() {
+        let preimage = [7u8; 32];
+        let hash = hex::encode(Sha256::digest(preimage));
+        let mut htlc = proof(&format!(
+            r#"["HTLC",{{"nonce":"da62796403af76c80cd6ce9153ed3746","data":"{hash}"}}]"#
+        ));
+        assert!(matches!(
+            htlc.verify_witness(&[]),
+            Err(crate::Error::MissingWitness)
+        ));
+
+        htlc.witness = Some(format!(r#"{{"preimage":"{}"}}"#, hex::encode([8u8; 32])));
+        assert!(matches!(
+            htlc.verify_witness(&[]),
+            Err(crate::Error::InvalidWitness)
+        ));
+        htlc.witness = Some(format!(r#"{{"preimage":"{}"}}"#, hex::encode(preimage)));
+        assert!(htlc.verify_witness(&[]).is_ok());
+    }
+}
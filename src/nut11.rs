@@ -0,0 +1,342 @@
+use k256::schnorr::signature::{Signer, Verifier};
+use k256::schnorr::{Signature, SigningKey, VerifyingKey};
+use k256::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::to_xonly;
+use crate::{BlindedMessage, Proof, Proofs, SecretKind, WellKnownSecret};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigFlag {
+    SigInputs,
+    SigAll,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct P2PKWitness {
+    // Hex-encoded BIP-340 signatures
+    #[serde(deserialize_with = "signatures::deserialize")]
+    pub signatures: Vec<String>,
+}
+
+// Witness signatures are BIP-340 signatures as 128 lowercase hex characters
+pub fn sig_to_hex(signature: &Signature) -> String {
+    hex::encode(signature.to_bytes())
+}
+
+// k256 panics on slices shorter than a signature, so the length is checked first
+pub fn sig_from_hex(data: &str) -> Result<Signature, crate::Error> {
+    hex::decode(data)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| crate::Error::InvalidSignature(data.to_string()))
+}
+
+// Witnesses with signatures in any other encoding fail to parse, and uppercase hex is read in
+// lowercase
+pub(crate) mod signatures {
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|signature| super::sig_from_hex(signature).map(|s| super::sig_to_hex(&s)))
+            .collect::<Result<_, _>>()
+            .map_err(Error::custom)
+    }
+}
+
+impl WellKnownSecret {
+    // The key a P2PK secret is locked to
+    pub fn p2pk_pubkey(&self) -> Option<PublicKey> {
+        if self.secret_kind() != SecretKind::P2PK {
+            return None;
+        }
+        let bytes = hex::decode(&self.data.data).ok()?;
+        PublicKey::from_sec1_bytes(&bytes).ok()
+    }
+
+    // SIG_INPUTS unless the secret asks for SIG_ALL
+    pub fn sig_flag(&self) -> SigFlag {
+        match self.tag("sigflag") {
+            Some([flag, ..]) if flag == "SIG_ALL" => SigFlag::SigAll,
+            _ => SigFlag::SigInputs,
+        }
+    }
+}
+
+impl Proof {
+    pub fn p2pk_pubkey(&self) -> Option<PublicKey> {
+        WellKnownSecret::parse(&self.secret)?.p2pk_pubkey()
+    }
+
+    pub fn sig_flag(&self) -> SigFlag {
+        WellKnownSecret::parse(&self.secret).map_or(SigFlag::SigInputs, |s| s.sig_flag())
+    }
+
+    // Sign the message and add the signature to the proof's witness
+    pub fn sign_p2pk(&mut self, key: &SecretKey, message: &[u8]) {
+        let signature: Signature = SigningKey::from(key).sign(message);
+        let mut witness = self.p2pk_witness().unwrap_or_default();
+        witness.signatures.push(sig_to_hex(&signature));
+        self.witness = serde_json::to_string(&witness).ok();
+    }
+
+    // True when the witness holds a valid signature over the message by the locking key
+    pub fn verify_p2pk(&self, message: &[u8]) -> bool {
+        let (Some(pubkey), Some(witness)) = (self.p2pk_pubkey(), self.p2pk_witness()) else {
+            return false;
+        };
+        let Ok(key) = VerifyingKey::from_bytes(&to_xonly(&pubkey)) else {
+            return false;
+        };
+        witness.signatures.iter().any(|signature| {
+            sig_from_hex(signature).is_ok_and(|signature| key.verify(message, &signature).is_ok())
+        })
+    }
+
+    fn p2pk_witness(&self) -> Option<P2PKWitness> {
+        serde_json::from_str(self.witness.as_deref()?).ok()
+    }
+}
+
+// Outputs ascending by amount, then by B_ bytes
+pub fn sort_outputs_canonical(outputs: &mut [BlindedMessage]) {
+    outputs.sort_by(cmp_outputs_canonical);
+}
+
+pub fn cmp_outputs_canonical(a: &BlindedMessage, b: &BlindedMessage) -> std::cmp::Ordering {
+    (a.amount, a.blinded_message.as_slice()).cmp(&(b.amount, b.blinded_message.as_slice()))
+}
+
+// NUT-11 SIG_ALL: every input's secret followed by every output's hex-encoded B_, in the order
+// the request carries them. Wallets put both in canonical order (Proofs::sort_canonical,
+// sort_outputs_canonical) before signing, so the request they send is the one signed.
+pub fn sig_all_message(inputs: &Proofs, outputs: &[BlindedMessage]) -> Vec<u8> {
+    let mut message = Vec::new();
+    for input in inputs.as_ref() {
+        message.extend(input.secret.as_bytes());
+    }
+    for output in outputs {
+        message.extend(hex::encode(&output.blinded_message).into_bytes());
+    }
+    message
+}
+
+// What an input's signature covers: its own secret, or the whole transaction when any input
+// in it is SIG_ALL
+pub fn p2pk_signing_message(proof: &Proof, inputs: &Proofs, outputs: &[BlindedMessage]) -> Vec<u8> {
+    if inputs
+        .as_ref()
+        .iter()
+        .any(|p| p.sig_flag() == SigFlag::SigAll)
+    {
+        sig_all_message(inputs, outputs)
+    } else {
+        proof.secret.as_bytes().to_vec()
+    }
+}
+
+// Sign every input locked to the key for a swap into the given outputs
+pub fn sign_p2pk_inputs(inputs: &mut Proofs, outputs: &[BlindedMessage], key: &SecretKey) {
+    let pubkey = key.public_key();
+    let unsigned = inputs.clone();
+    for proof in inputs.as_mut() {
+        if proof.p2pk_pubkey() == Some(pubkey) {
+            let message = p2pk_signing_message(proof, &unsigned, outputs);
+            proof.sign_p2pk(key, &message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::p2pk_secret;
+
+    fn proof(secret: String) -> Proof {
+        Proof {
+            id: Some("009a1f293253e41e".parse().unwrap()),
+            amount: 1,
+            secret,
+            unblinded_key: vec![],
+            dleq: None,
+            witness: None,
+        }
+    }
+
+    // https://github.com/cashubtc/nuts/blob/main/11.md
+    #[test]
+    fn test_verify_p2pk_vector() {
+        let mut proof = proof(r#"["P2PK",{"nonce":"859d4935c4907062a6297cf4e663e2835d90d97ecdd510745d32f6816323a41f","data":"0249098aa8b9d2fbec49ff8598feb17b592b986e62319a4fa488a3dc36387157a7","tags":[["sigflag","SIG_INPUTS"]]}]"#.to_string());
+        proof.witness = Some(r#"{"signatures":["60f3c9b766770b46caac1d27e1ae6b77c8866ebaeba0b9489fe6a15a837eaa6fcd6eaa825499c72ac342983983fd3ba3a8a41f56677cc99ffd73da68b59e1383"]}"#.to_string());
+        assert_eq!(proof.sig_flag(), SigFlag::SigInputs);
+        assert!(proof.verify_p2pk(proof.secret.as_bytes()));
+        assert!(!proof.verify_p2pk(b"other message"));
+    }
+
+    #[test]
+    fn test_witness_serialization() {
+        let key = SigningKey::from(SecretKey::random(&mut rand::thread_rng()));
+        let signatures: Vec<Signature> = vec![key.sign(b"a"), key.sign(b"b")];
+        let witness = P2PKWitness {
+            signatures: signatures.iter().map(sig_to_hex).collect(),
+        };
+        let json = serde_json::to_string(&witness).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"signatures":["{}","{}"]}}"#,
+                hex::encode(signatures[0].to_bytes()),
+                hex::encode(signatures[1].to_bytes())
+            )
+        );
+        let parsed: P2PKWitness = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, witness);
+        let parsed: Vec<Signature> = parsed
+            .signatures
+            .iter()
+            .map(|s| sig_from_hex(s).unwrap())
+            .collect();
+        assert_eq!(parsed, signatures);
+
+        // Read in lowercase, and anything but a 64-byte signature is rejected
+        let upper = witness.signatures[0].to_uppercase();
+        let parsed: P2PKWitness =
+            serde_json::from_str(&format!(r#"{{"signatures":["{upper}"]}}"#)).unwrap();
+        assert_eq!(parsed.signatures, vec![witness.signatures[0].clone()]);
+        for signature in ["", "abcd", &witness.signatures[0][2..], &"zz".repeat(64)] {
+            assert!(matches!(
+                sig_from_hex(signature),
+                Err(crate::Error::InvalidSignature(_))
+            ));
+            let json = format!(r#"{{"signatures":["{signature}"]}}"#);
+            assert!(serde_json::from_str::<P2PKWitness>(&json).is_err());
+        }
+
+        let htlc = crate::HtlcWitness {
+            preimage: hex::encode([7; 32]),
+            signatures: vec![witness.signatures[1].clone()],
+        };
+        let json = serde_json::to_string(&htlc).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"preimage":"{}","signatures":["{}"]}}"#,
+                hex::encode([7; 32]),
+                witness.signatures[1]
+            )
+        );
+        assert_eq!(
+            serde_json::from_str::<crate::HtlcWitness>(&json).unwrap(),
+            htlc
+        );
+        let json = format!(r#"{{"preimage":"{}"}}"#, htlc.preimage);
+        let preimage_only = crate::HtlcWitness {
+            signatures: vec![],
+            ..htlc
+        };
+        assert_eq!(serde_json::to_string(&preimage_only).unwrap(), json);
+        assert_eq!(
+            serde_json::from_str::<crate::HtlcWitness>(&json).unwrap(),
+            preimage_only
+        );
+    }
+
+    #[test]
+    fn test_sign_p2pk() {
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let other = SecretKey::random(&mut rand::thread_rng());
+        let mut inputs = Proofs::from(vec![
+            proof(p2pk_secret(&key, SigFlag::SigInputs)),
+            proof(p2pk_secret(&other, SigFlag::SigInputs)),
+            proof(hex::encode(rand::random::<[u8; 32]>())),
+        ]);
+        sign_p2pk_inputs(&mut inputs, &[], &key);
+
+        let proofs = inputs.as_ref();
+        assert!(proofs[0].verify_p2pk(proofs[0].secret.as_bytes()));
+        assert!(proofs[1].witness.is_none());
+        assert!(proofs[2].witness.is_none());
+        assert!(!proofs[2].verify_p2pk(proofs[2].secret.as_bytes()));
+    }
+
+    #[test]
+    fn test_sign_p2pk_sig_all() {
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let mut inputs = Proofs::from(vec![
+            proof(p2pk_secret(&key, SigFlag::SigAll)),
+            proof(p2pk_secret(&key, SigFlag::SigInputs)),
+        ]);
+        let outputs = [BlindedMessage {
+            amount: 2,
+            id: Some("009a1f293253e41e".to_string()),
+            blinded_message: key.public_key().to_sec1_bytes().to_vec(),
+        }];
+        sign_p2pk_inputs(&mut inputs, &outputs, &key);
+
+        let message = sig_all_message(&inputs, &outputs);
+        let proofs = inputs.as_ref();
+        assert_eq!(
+            message,
+            format!(
+                "{}{}{}",
+                proofs[0].secret,
+                proofs[1].secret,
+                hex::encode(&outputs[0].blinded_message)
+            )
+            .into_bytes()
+        );
+        assert!(proofs.iter().all(|p| p.verify_p2pk(&message)));
+        assert!(!proofs[1].verify_p2pk(proofs[1].secret.as_bytes()));
+    }
+
+    #[test]
+    fn test_sig_all_message_canonical() {
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let mut inputs: Vec<Proof> = [4, 1, 2, 1]
+            .iter()
+            .map(|amount| Proof {
+                amount: *amount,
+                ..proof(p2pk_secret(&key, SigFlag::SigAll))
+            })
+            .collect();
+        let mut outputs: Vec<BlindedMessage> = [1, 2, 4, 1]
+            .iter()
+            .map(|amount| BlindedMessage {
+                amount: *amount,
+                id: Some("009a1f293253e41e".to_string()),
+                blinded_message: SecretKey::random(&mut rand::thread_rng())
+                    .public_key()
+                    .to_sec1_bytes()
+                    .to_vec(),
+            })
+            .collect();
+        let message = sig_all_message(&Proofs::from(inputs.clone()), &outputs);
+
+        // The message follows the request, so reordering it changes what's signed
+        inputs.reverse();
+        inputs.swap(0, 2);
+        outputs.rotate_left(1);
+        assert_ne!(
+            sig_all_message(&Proofs::from(inputs.clone()), &outputs),
+            message
+        );
+
+        let mut sorted = Proofs::from(inputs);
+        sorted.sort_canonical();
+        let amounts: Vec<u64> = sorted.as_ref().iter().map(|p| p.amount).collect();
+        assert_eq!(amounts, vec![1, 1, 2, 4]);
+        let proofs = sorted.as_ref();
+        assert!(proofs[0].secret < proofs[1].secret);
+
+        sort_outputs_canonical(&mut outputs);
+        let amounts: Vec<u64> = outputs.iter().map(|o| o.amount).collect();
+        assert_eq!(amounts, vec![1, 1, 2, 4]);
+        assert!(outputs[0].blinded_message < outputs[1].blinded_message);
+        assert!(sig_all_message(&sorted, &outputs).starts_with(proofs[0].secret.as_bytes()));
+    }
+}
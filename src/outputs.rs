@@ -0,0 +1,438 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::crypto::Secret;
+use crate::{BlindedMessage, Outputs};
+
+// The powers of two that sum to the amount, smallest first
+pub fn split_amount(amount: u64) -> Vec<u64> {
+    (0..u64::BITS)
+        .map(|bit| 1 << bit)
+        .filter(|denomination| amount & denomination != 0)
+        .collect()
+}
+
+// Denominations for the amount, smallest first, taking up to the target count of each
+// denomination, largest first, while they fit. Whatever the target doesn't cover is split as
+// usual. Targets that aren't powers of two are ignored.
+pub fn split_amount_with_target(amount: u64, target: &BTreeMap<u64, usize>) -> Vec<u64> {
+    let (taken, remaining) = take_target(amount, target.iter().map(|(d, c)| (*d, *c)));
+    let mut amounts: Vec<u64> = taken
+        .into_iter()
+        .flat_map(|(denomination, n)| std::iter::repeat_n(denomination, n as usize))
+        .collect();
+    amounts.extend(split_amount(remaining));
+    amounts.sort();
+    amounts
+}
+
+// How many of each target denomination fit the amount, largest first, and what's left over
+fn take_target(
+    amount: u64,
+    target: impl DoubleEndedIterator<Item = (u64, usize)>,
+) -> (Vec<(u64, u64)>, u64) {
+    let mut taken = Vec::new();
+    let mut remaining = amount;
+    for (denomination, count) in target.rev() {
+        if !denomination.is_power_of_two() {
+            continue;
+        }
+        let n = (count as u64).min(remaining / denomination);
+        taken.push((denomination, n));
+        remaining -= n * denomination;
+    }
+    (taken, remaining)
+}
+
+// How many outputs split_amount makes for the amount
+pub fn output_count_for(amount: u64) -> usize {
+    amount.count_ones() as usize
+}
+
+// Splits are refused past this many requests' worth of outputs, or this many outputs when
+// requests aren't capped, so a small max_order can't make one allocate without bound
+const MAX_BATCHES: usize = 100;
+const MAX_UNBATCHED_OUTPUTS: usize = 10_000;
+
+// Caps some mints put on outputs. Left unset, amounts split into any power of two, and a
+// request can carry any number of outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimits {
+    // The largest denomination is 2^max_order
+    pub max_order: Option<u32>,
+
+    // Outputs per request
+    pub max_outputs: Option<usize>,
+}
+
+impl OutputLimits {
+    // Denominations for the amount, smallest first, with anything over the largest allowed
+    // denomination split into repeats of it
+    pub fn split(&self, amount: u64) -> Result<Vec<u64>, crate::Error> {
+        let count = self.output_count(amount);
+        let max = self.max_split_outputs();
+        if count > max as u64 {
+            return Err(crate::Error::TooManyOutputs { count, max });
+        }
+        let Some(largest) = self.largest() else {
+            return Ok(split_amount(amount));
+        };
+        let mut amounts = split_amount(amount % largest);
+        amounts.extend(std::iter::repeat_n(largest, (amount / largest) as usize));
+        Ok(amounts)
+    }
+
+    // Like split_amount_with_target, leaving out target denominations over the largest allowed
+    // and splitting the rest as split does
+    pub fn split_with_target(
+        &self,
+        amount: u64,
+        target: &BTreeMap<u64, usize>,
+    ) -> Result<Vec<u64>, crate::Error> {
+        let allowed = target
+            .iter()
+            .filter(|(d, _)| self.largest().is_none_or(|largest| **d <= largest))
+            .map(|(d, c)| (*d, *c));
+        let (taken, remaining) = take_target(amount, allowed);
+        let count = taken.iter().map(|(_, n)| n).sum::<u64>() + self.output_count(remaining);
+        let max = self.max_split_outputs();
+        if count > max as u64 {
+            return Err(crate::Error::TooManyOutputs { count, max });
+        }
+        let mut amounts: Vec<u64> = taken
+            .into_iter()
+            .flat_map(|(denomination, n)| std::iter::repeat_n(denomination, n as usize))
+            .collect();
+        amounts.extend(self.split(remaining)?);
+        amounts.sort();
+        Ok(amounts)
+    }
+
+    // How many outputs split makes for the amount, without making them
+    pub fn output_count(&self, amount: u64) -> u64 {
+        match self.largest() {
+            Some(largest) => output_count_for(amount % largest) as u64 + amount / largest,
+            None => output_count_for(amount) as u64,
+        }
+    }
+
+    // The split, in batches small enough for one request each
+    pub fn batches(&self, amount: u64) -> Result<Vec<Vec<u64>>, crate::Error> {
+        let amounts = self.split(amount)?;
+        Ok(match self.per_request() {
+            Some(max) => amounts.chunks(max).map(<[u64]>::to_vec).collect(),
+            None => vec![amounts],
+        })
+    }
+
+    // Outputs one request may carry, if that's capped
+    pub fn per_request(&self) -> Option<usize> {
+        self.max_outputs.filter(|max| *max > 0)
+    }
+
+    fn largest(&self) -> Option<u64> {
+        self.max_order
+            .filter(|order| *order < u64::BITS - 1)
+            .map(|order| 1 << order)
+    }
+
+    fn max_split_outputs(&self) -> usize {
+        self.per_request()
+            .map_or(MAX_UNBATCHED_OUTPUTS, |max| max.saturating_mul(MAX_BATCHES))
+    }
+}
+
+// NUT-08: enough blank outputs to return any change up to the fee reserve,
+// max(ceil(log2(fee_reserve)), 1), or none when there is no reserve
+pub fn blank_output_count(fee_reserve: u64) -> usize {
+    match fee_reserve {
+        0 => 0,
+        1 => 1,
+        reserve => (u64::BITS - (reserve - 1).leading_zeros()) as usize,
+    }
+}
+
+// Outputs with random secrets and a placeholder amount of zero, which the mint fills in when
+// it signs them as change
+pub fn blank_outputs(count: usize, keyset_id: &str) -> Result<Outputs, crate::Error> {
+    let mut outputs = Vec::with_capacity(count);
+    let mut secrets = Vec::with_capacity(count);
+    let mut factors = Vec::with_capacity(count);
+    for _ in 0..count {
+        let secret = Secret::from_utf8(&hex::encode(rand::random::<[u8; 32]>()))?;
+        let r = crate::crypto::random_blinding_factor(&mut rand::thread_rng());
+        let bm = secret.blinded_message(&r)?;
+        outputs.push(BlindedMessage {
+            amount: 0,
+            id: Some(keyset_id.to_string()),
+            blinded_message: bm.as_ref().to_sec1_bytes().to_vec(),
+        });
+        secrets.push(secret);
+        factors.push(r);
+    }
+    Ok((outputs, secrets, factors))
+}
+
+// Outputs in one request must not repeat a B_, secret or blinding factor. Repeats come from
+// counter reuse, and a mint either rejects the request or signs the same output twice.
+pub fn ensure_unique_outputs(outputs: &Outputs) -> Result<(), crate::Error> {
+    let (outputs, secrets, factors) = outputs;
+    let mut seen_outputs = HashSet::new();
+    let mut seen_secrets = HashSet::new();
+    let mut seen_factors = HashSet::new();
+    for (i, (output, (secret, r))) in outputs.iter().zip(secrets.iter().zip(factors)).enumerate() {
+        let unique = seen_outputs.insert(output.blinded_message.as_slice())
+            & seen_secrets.insert(secret.as_ref())
+            & seen_factors.insert(r.to_bytes());
+        if !unique {
+            return Err(crate::Error::DuplicateOutput(i));
+        }
+    }
+    Ok(())
+}
+
+// Outputs over 2^max_order can't be signed by the keyset
+pub fn check_max_order(outputs: &[BlindedMessage], max_order: u32) -> Result<(), crate::Error> {
+    let too_large = |amount| {
+        1u64.checked_shl(max_order)
+            .is_some_and(|largest| amount > largest)
+    };
+    match outputs.iter().position(|output| too_large(output.amount)) {
+        Some(index) => Err(crate::Error::AmountExceedsMaxOrder {
+            index,
+            amount: outputs[index].amount,
+            max_order,
+        }),
+        None => Ok(()),
+    }
+}
+
+// A swap needs at least one output, and each must be for a single denomination. Blank outputs
+// with amount 0 only belong in a melt.
+pub fn validate_swap_outputs(outputs: &[BlindedMessage]) -> Result<(), crate::Error> {
+    if outputs.is_empty() {
+        return Err(crate::Error::NoOutputs);
+    }
+    match outputs
+        .iter()
+        .position(|output| !output.amount.is_power_of_two())
+    {
+        Some(index) => Err(crate::Error::InvalidOutputAmount {
+            index,
+            amount: outputs[index].amount,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_amount() {
+        assert_eq!(split_amount(0), Vec::<u64>::new());
+        assert_eq!(split_amount(1), vec![1]);
+        assert_eq!(split_amount(13), vec![1, 4, 8]);
+        assert_eq!(split_amount(64), vec![64]);
+        assert_eq!(split_amount(u64::MAX).len(), 64);
+    }
+
+    #[test]
+    fn test_split_amount_with_target() {
+        let target = BTreeMap::from([(1, 2), (4, 3), (8, 1)]);
+        assert_eq!(
+            split_amount_with_target(30, &target),
+            vec![1, 1, 4, 4, 4, 8, 8]
+        );
+
+        // The target is cut short when the amount runs out
+        assert_eq!(split_amount_with_target(10, &target), vec![1, 1, 8]);
+        assert_eq!(
+            split_amount_with_target(13, &BTreeMap::new()),
+            split_amount(13)
+        );
+        assert_eq!(
+            split_amount_with_target(6, &BTreeMap::from([(3, 2)])),
+            vec![2, 4]
+        );
+    }
+
+    #[test]
+    fn test_split_with_target() {
+        let target = BTreeMap::from([(1, 2), (4, 3), (16, 1)]);
+        let limits = OutputLimits {
+            max_order: Some(3),
+            max_outputs: None,
+        };
+        assert_eq!(
+            limits.split_with_target(30, &target).unwrap(),
+            vec![1, 1, 4, 4, 4, 8, 8]
+        );
+        assert_eq!(
+            OutputLimits::default()
+                .split_with_target(30, &target)
+                .unwrap(),
+            split_amount_with_target(30, &target)
+        );
+
+        // Huge targets are refused before they're made
+        let limits = OutputLimits {
+            max_order: None,
+            max_outputs: Some(10),
+        };
+        assert!(matches!(
+            limits.split_with_target(u64::MAX, &BTreeMap::from([(1, usize::MAX)])),
+            Err(crate::Error::TooManyOutputs { max: 1000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_output_count_for() {
+        for amount in [0, 1, 2, 3, 13, 64, 1000, 2u64.pow(40) - 1, u64::MAX] {
+            assert_eq!(
+                output_count_for(amount),
+                split_amount(amount).len(),
+                "{amount}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_output_limits() {
+        let unlimited = OutputLimits::default();
+        assert_eq!(unlimited.split(13).unwrap(), split_amount(13));
+        assert_eq!(unlimited.batches(13).unwrap(), vec![vec![1, 4, 8]]);
+        assert_eq!(unlimited.output_count(u64::MAX), 64);
+
+        let limits = OutputLimits {
+            max_order: Some(2),
+            max_outputs: Some(3),
+        };
+        assert_eq!(limits.split(13).unwrap(), vec![1, 4, 4, 4]);
+        assert_eq!(limits.batches(13).unwrap(), vec![vec![1, 4, 4], vec![4]]);
+        assert_eq!(limits.output_count(13), 4);
+
+        let amount = 1_000_000;
+        let limits = OutputLimits {
+            max_order: Some(10),
+            max_outputs: Some(100),
+        };
+        let batches = limits.batches(amount).unwrap();
+        assert_eq!(batches.len(), 10);
+        assert!(batches.iter().all(|b| b.len() <= 100));
+        assert!(batches.iter().flatten().all(|a| *a <= 1024));
+        assert_eq!(batches.iter().flatten().sum::<u64>(), amount);
+
+        // Splits needing more outputs than the requests could carry are refused up front
+        assert!(matches!(
+            limits.split(u64::MAX),
+            Err(crate::Error::TooManyOutputs { max: 10_000, .. })
+        ));
+        let limits = OutputLimits {
+            max_order: Some(0),
+            max_outputs: None,
+        };
+        assert_eq!(limits.split(10_000).unwrap().len(), 10_000);
+        assert!(matches!(
+            limits.split(10_001),
+            Err(crate::Error::TooManyOutputs {
+                count: 10_001,
+                max: 10_000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_blank_output_count() {
+        assert_eq!(blank_output_count(0), 0);
+        assert_eq!(blank_output_count(1), 1);
+        assert_eq!(blank_output_count(2), 1);
+        assert_eq!(blank_output_count(3), 2);
+        assert_eq!(blank_output_count(4), 2);
+        assert_eq!(blank_output_count(1000), 10);
+        assert_eq!(blank_output_count(1024), 10);
+        assert_eq!(blank_output_count(1025), 11);
+    }
+
+    #[test]
+    fn test_blank_outputs() {
+        let count = blank_output_count(1000);
+        let (outputs, secrets, factors) = blank_outputs(count, "009a1f293253e41e").unwrap();
+        assert_eq!(outputs.len(), 10);
+        assert_eq!(secrets.len(), 10);
+        assert_eq!(factors.len(), 10);
+        assert!(outputs
+            .iter()
+            .all(|o| o.amount == 0 && o.id.as_deref() == Some("009a1f293253e41e")));
+        assert_ne!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn test_ensure_unique_outputs() {
+        let outputs = blank_outputs(3, "009a1f293253e41e").unwrap();
+        assert!(ensure_unique_outputs(&outputs).is_ok());
+
+        // A repeated B_ along with its secret and r, as counter reuse would produce
+        let (mut outputs, mut secrets, mut factors) = outputs;
+        outputs.push(outputs[1].clone());
+        secrets.push(Secret::from_bytes(secrets[1].expose_bytes().to_vec()).unwrap());
+        factors.push(factors[1].clone());
+        assert!(matches!(
+            ensure_unique_outputs(&(outputs, secrets, factors)),
+            Err(crate::Error::DuplicateOutput(3))
+        ));
+
+        // Only the blinding factor repeats
+        let (outputs, secrets, mut factors) = blank_outputs(2, "009a1f293253e41e").unwrap();
+        factors[1] = factors[0].clone();
+        assert!(matches!(
+            ensure_unique_outputs(&(outputs, secrets, factors)),
+            Err(crate::Error::DuplicateOutput(1))
+        ));
+    }
+
+    #[test]
+    fn test_validate_swap_outputs() {
+        assert!(matches!(
+            validate_swap_outputs(&[]),
+            Err(crate::Error::NoOutputs)
+        ));
+
+        let (mut outputs, _, _) = blank_outputs(2, "009a1f293253e41e").unwrap();
+        outputs[0].amount = 8;
+        assert!(matches!(
+            validate_swap_outputs(&outputs),
+            Err(crate::Error::InvalidOutputAmount {
+                index: 1,
+                amount: 0
+            })
+        ));
+        outputs[1].amount = 3;
+        assert!(matches!(
+            validate_swap_outputs(&outputs),
+            Err(crate::Error::InvalidOutputAmount {
+                index: 1,
+                amount: 3
+            })
+        ));
+        outputs[1].amount = 1;
+        assert!(validate_swap_outputs(&outputs).is_ok());
+    }
+
+    #[test]
+    fn test_check_max_order() {
+        let (mut outputs, _, _) = blank_outputs(2, "009a1f293253e41e").unwrap();
+        outputs[0].amount = 8;
+        outputs[1].amount = 16;
+        assert!(check_max_order(&outputs, 4).is_ok());
+        assert!(matches!(
+            check_max_order(&outputs, 3),
+            Err(crate::Error::AmountExceedsMaxOrder {
+                index: 1,
+                amount: 16,
+                max_order: 3
+            })
+        ));
+        assert!(check_max_order(&outputs, 64).is_ok());
+    }
+}
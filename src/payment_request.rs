@@ -0,0 +1,182 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use serde::{Deserialize, Serialize};
+
+// Requests are padded, but decoding tolerates either
+const URL_SAFE_CREQ: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(true)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// NUT-18: a request for payment, telling the payer what to send and where
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentRequest {
+    #[serde(rename = "i", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+
+    #[serde(rename = "u", default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    #[serde(
+        rename = "s",
+        alias = "r",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub single_use: Option<bool>,
+
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub mints: Option<Vec<String>>,
+
+    #[serde(rename = "d", default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(rename = "t", default, skip_serializing_if = "Vec::is_empty")]
+    pub transports: Vec<Transport>,
+
+    // Spending conditions the payment must be locked to, e.g. P2PK
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nut10: Option<Nut10Option>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Transport {
+    // e.g. "nostr" or "post"
+    #[serde(rename = "t")]
+    pub kind: String,
+
+    #[serde(rename = "a")]
+    pub target: String,
+
+    #[serde(rename = "g", default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Vec<String>>,
+}
+
+impl Transport {
+    // The values of the first tag with this name, e.g. "n" for the NIPs a nostr transport supports
+    pub fn tag(&self, name: &str) -> Option<&[String]> {
+        self.tags
+            .iter()
+            .find(|t| t.first().is_some_and(|n| n == name))
+            .map(|t| &t[1..])
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Nut10Option {
+    #[serde(rename = "k")]
+    pub kind: String,
+
+    #[serde(rename = "d")]
+    pub data: String,
+
+    #[serde(rename = "t", default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Vec<String>>,
+}
+
+impl PaymentRequest {
+    pub fn serialize(&self) -> Result<String, crate::Error> {
+        let mut data = Vec::new();
+        ciborium::into_writer(self, &mut data).map_err(crate::Error::map_payment_request)?;
+        let mut request = URL_SAFE_CREQ.encode(data);
+        request.insert_str(0, "creqA");
+        Ok(request)
+    }
+
+    pub fn deserialize(request: &str) -> Result<PaymentRequest, crate::Error> {
+        let request = request
+            .strip_prefix("creqA")
+            .ok_or(crate::Error::PaymentRequest(None))?;
+        let data = URL_SAFE_CREQ
+            .decode(request)
+            .map_err(crate::Error::map_payment_request)?;
+        ciborium::from_reader(data.as_slice()).map_err(crate::Error::map_payment_request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://github.com/cashubtc/nuts/blob/main/18.md
+    const REQUEST: &str = "creqApWF0gaNhdGVub3N0cmFheKlucHJvZmlsZTFxeTI4d3VtbjhnaGo3dW45ZDNzaGp0bnl2OWtoMnVld2Q5aHN6OW1od2RlbjV0ZTB3ZmprY2N0ZTljdXJ4dmVuOWVlaHFjdHJ2NWhzenJ0aHdkZW41dGUwZGVoaHh0bnZkYWtxcWd5ZGFxeTdjdXJrNDM5eWtwdGt5c3Y3dWRoZGh1NjhzdWNtMjk1YWtxZWZkZWhrZjBkNDk1Y3d1bmw1YWeBgmFuYjE3YWloYjdhOTAxNzZhYQphdWNzYXRhbYF4Imh0dHBzOi8vbm9mZWVzLnRlc3RudXQuY2FzaHUuc3BhY2U=";
+
+    #[test]
+    fn test_deserialize_reference() {
+        let request = PaymentRequest::deserialize(REQUEST).unwrap();
+        assert_eq!(request.id.as_deref(), Some("b7a90176"));
+        assert_eq!(request.amount, Some(10));
+        assert_eq!(request.unit.as_deref(), Some("sat"));
+        assert_eq!(
+            request.mints,
+            Some(vec!["https://nofees.testnut.cashu.space".to_string()])
+        );
+        assert_eq!(request.transports.len(), 1);
+        let transport = &request.transports[0];
+        assert_eq!(transport.kind, "nostr");
+        assert!(transport.target.starts_with("nprofile1"));
+        assert_eq!(transport.tag("n"), Some(&["17".to_string()][..]));
+        assert_eq!(transport.tag("x"), None);
+        assert_eq!(request.nut10, None);
+
+        // Re-encoding preserves everything, even if the key order differs from the reference
+        let encoded = request.serialize().unwrap();
+        assert_eq!(PaymentRequest::deserialize(&encoded).unwrap(), request);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let request = PaymentRequest {
+            id: Some("4840f51e".to_string()),
+            amount: Some(21),
+            unit: Some("sat".to_string()),
+            single_use: Some(true),
+            mints: Some(vec!["https://mint.example.com".to_string()]),
+            description: Some("coffee".to_string()),
+            transports: vec![Transport {
+                kind: "post".to_string(),
+                target: "https://example.com/pay".to_string(),
+                tags: vec![],
+            }],
+            nut10: Some(Nut10Option {
+                kind: "P2PK".to_string(),
+                data: "02c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4"
+                    .to_string(),
+                tags: vec![vec!["locktime".to_string(), "1700000000".to_string()]],
+            }),
+        };
+        let encoded = request.serialize().unwrap();
+        assert!(encoded.starts_with("creqA"));
+        assert_eq!(PaymentRequest::deserialize(&encoded).unwrap(), request);
+
+        // Unpadded requests decode too
+        let unpadded = encoded.trim_end_matches('=');
+        assert_eq!(PaymentRequest::deserialize(unpadded).unwrap(), request);
+
+        let empty = PaymentRequest::default().serialize().unwrap();
+        assert_eq!(
+            PaymentRequest::deserialize(&empty).unwrap(),
+            PaymentRequest::default()
+        );
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        assert!(matches!(
+            PaymentRequest::deserialize("cashuA1234"),
+            Err(crate::Error::PaymentRequest(None))
+        ));
+        assert!(matches!(
+            PaymentRequest::deserialize("creqA!!!"),
+            Err(crate::Error::PaymentRequest(Some(_)))
+        ));
+    }
+}
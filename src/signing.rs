@@ -0,0 +1,135 @@
+use k256::schnorr::signature::{Signer, Verifier};
+use k256::schnorr::{Signature, SigningKey, VerifyingKey};
+use k256::{PublicKey, SecretKey};
+
+use crate::crypto::to_xonly;
+use crate::{BlindedMessage, Token};
+
+// NUT-20: the quote id followed by each output's hex-encoded B_, as UTF-8
+pub fn nut20_signing_message(quote: &str, outputs: &[BlindedMessage]) -> Vec<u8> {
+    let mut message = quote.as_bytes().to_vec();
+    for output in outputs {
+        message.extend(hex::encode(&output.blinded_message).into_bytes());
+    }
+    message
+}
+
+impl Token {
+    // A BIP-340 signature by the sender over token_signing_message
+    pub fn sign(&self, key: &SecretKey) -> Signature {
+        SigningKey::from(key).sign(&token_signing_message(self))
+    }
+
+    pub fn verify_signature(&self, signature: &Signature, pubkey: &PublicKey) -> bool {
+        VerifyingKey::from_bytes(&to_xonly(pubkey))
+            .is_ok_and(|key| key.verify(&token_signing_message(self), signature).is_ok())
+    }
+}
+
+// What a token signature covers: the memo, then for each proof its mint (normalized, see
+// normalize_mint_url), unit ("sat" if unset), keyset id ("" if unset), amount as a decimal
+// string, secret, and hex-encoded C. Proofs are ordered by those fields, so splitting or
+// reordering the token doesn't change the message. Every field is a 4-byte big-endian length
+// followed by its UTF-8 bytes. DLEQ proofs and witnesses aren't covered.
+pub fn token_signing_message(token: &Token) -> Vec<u8> {
+    let mut proofs: Vec<[String; 6]> = Vec::new();
+    for (i, mint_token) in token.token.iter().enumerate() {
+        let mint = crate::normalize_mint_url(&mint_token.mint);
+        let unit = token.mint_unit(i).unwrap_or(crate::token_v4::DEFAULT_UNIT);
+        for proof in mint_token.proofs.as_ref() {
+            proofs.push([
+                mint.clone(),
+                unit.to_string(),
+                proof.id.as_deref().unwrap_or_default().to_string(),
+                proof.amount.to_string(),
+                proof.secret.clone(),
+                hex::encode(&proof.unblinded_key),
+            ]);
+        }
+    }
+    proofs.sort();
+
+    let mut message = Vec::new();
+    let memo = token.memo.as_deref().unwrap_or_default();
+    for field in std::iter::once(memo).chain(proofs.iter().flatten().map(String::as_str)) {
+        message.extend((field.len() as u32).to_be_bytes());
+        message.extend(field.as_bytes());
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://github.com/cashubtc/nuts/blob/main/20.md
+    #[test]
+    fn test_nut20_signing_message() {
+        let outputs: Vec<BlindedMessage> = [
+            "0342e5bcc77f5b2a3c2afb40bb591a1e27da83cddc968abdc0ec4904201a201834",
+            "032fd3c4dc49a2844a89998d5e9d5b0f0b00dde9310063acb8a92e2fdafa4126d4",
+            "033b6fde50b6a0dfe61ad148fff167ad9cf8308ded5f6f6b2fe000a036c464c311",
+        ]
+        .iter()
+        .map(|b| BlindedMessage {
+            amount: 1,
+            id: Some("009a1f293253e41e".to_string()),
+            blinded_message: hex::decode(b).unwrap(),
+        })
+        .collect();
+
+        let message = nut20_signing_message("9d745270-1405-46de-b5c5-e2762b4f5e00", &outputs);
+        assert_eq!(
+            String::from_utf8(message).unwrap(),
+            "9d745270-1405-46de-b5c5-e2762b4f5e00\
+             0342e5bcc77f5b2a3c2afb40bb591a1e27da83cddc968abdc0ec4904201a201834\
+             032fd3c4dc49a2844a89998d5e9d5b0f0b00dde9310063acb8a92e2fdafa4126d4\
+             033b6fde50b6a0dfe61ad148fff167ad9cf8308ded5f6f6b2fe000a036c464c311"
+        );
+
+        // The amount and keyset id aren't part of the message
+        let mut other = outputs.clone();
+        other[0].amount = 8;
+        other[0].id = None;
+        assert_eq!(
+            nut20_signing_message("9d745270-1405-46de-b5c5-e2762b4f5e00", &other),
+            nut20_signing_message("9d745270-1405-46de-b5c5-e2762b4f5e00", &outputs)
+        );
+    }
+
+    #[test]
+    fn test_token_signature() {
+        let mint = crate::mock::MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[1, 2, 4]);
+        let mut token = Token {
+            token: vec![crate::MintToken {
+                mint: "https://mint.example.com".parse().unwrap(),
+                proofs: proofs.clone(),
+                unit: None,
+                memo: None,
+            }],
+            memo: Some("thanks".to_string()),
+            unit: None,
+        };
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let signature = token.sign(&key);
+        assert!(token.verify_signature(&signature, &key.public_key()));
+
+        let other = SecretKey::random(&mut rand::thread_rng());
+        assert!(!token.verify_signature(&signature, &other.public_key()));
+
+        // Reordering proofs and spelling out the default unit and the mint's trailing slash
+        // don't change what's signed
+        let mut equivalent = token.clone();
+        equivalent.token[0].proofs.as_mut().reverse();
+        equivalent.token[0].unit = Some("sat".to_string());
+        equivalent.token[0].mint = "https://mint.example.com/".parse().unwrap();
+        assert!(equivalent.verify_signature(&signature, &key.public_key()));
+
+        let mut tampered = token.clone();
+        tampered.token[0].proofs.as_mut()[0].amount = 8;
+        assert!(!tampered.verify_signature(&signature, &key.public_key()));
+        token.memo = Some("thanks!".to_string());
+        assert!(!token.verify_signature(&signature, &key.public_key()));
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use crate::{Proof, Proofs};
+
+// Where a wallet keeps its proofs. Reserved proofs are committed to an in-flight swap or melt,
+// and are kept out of selection until released or removed.
+pub trait ProofStore {
+    fn insert(&mut self, proofs: Proofs);
+
+    // Proofs are matched by secret and C
+    fn remove(&mut self, proofs: &Proofs);
+
+    // Unreserved proofs covering at least `amount`
+    fn select(&self, amount: u64) -> Result<Proofs, crate::Error> {
+        self.select_matching(amount, &|_| true)
+    }
+
+    // Like select, drawing only on the proofs that match, e.g. those of one unit
+    fn select_matching(
+        &self,
+        amount: u64,
+        matches: &dyn Fn(&Proof) -> bool,
+    ) -> Result<Proofs, crate::Error>;
+
+    fn all(&self) -> Proofs;
+
+    fn reserve(&mut self, proofs: &Proofs);
+
+    fn release(&mut self, proofs: &Proofs);
+
+    fn is_reserved(&self, proof: &Proof) -> bool;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryProofStore {
+    proofs: Proofs,
+
+    // Y of reserved proofs, hex-encoded
+    reserved: HashSet<String>,
+}
+
+impl MemoryProofStore {
+    pub fn new() -> MemoryProofStore {
+        MemoryProofStore::default()
+    }
+}
+
+impl ProofStore for MemoryProofStore {
+    fn insert(&mut self, proofs: Proofs) {
+        self.proofs.append(proofs);
+    }
+
+    fn remove(&mut self, proofs: &Proofs) {
+        self.proofs.remove(proofs);
+        self.release(proofs);
+    }
+
+    // Largest first
+    fn select_matching(
+        &self,
+        amount: u64,
+        matches: &dyn Fn(&Proof) -> bool,
+    ) -> Result<Proofs, crate::Error> {
+        let mut available: Vec<&Proof> = self
+            .proofs
+            .as_ref()
+            .iter()
+            .filter(|p| !self.is_reserved(p) && matches(p))
+            .collect();
+        available.sort_by_key(|p| std::cmp::Reverse(p.amount));
+
+        let mut selected = Vec::new();
+        let mut total = 0;
+        for proof in available {
+            if total >= amount {
+                break;
+            }
+            total += proof.amount;
+            selected.push(proof.clone());
+        }
+        if total < amount {
+            return Err(crate::Error::InsufficientFunds {
+                needed: amount,
+                available: total,
+            });
+        }
+        Ok(Proofs::from(selected))
+    }
+
+    fn all(&self) -> Proofs {
+        self.proofs.clone()
+    }
+
+    fn reserve(&mut self, proofs: &Proofs) {
+        self.reserved.extend(
+            proofs
+                .as_ref()
+                .iter()
+                .map(|p| hex::encode(p.y().to_sec1_bytes())),
+        );
+    }
+
+    fn release(&mut self, proofs: &Proofs) {
+        for proof in proofs.as_ref() {
+            self.reserved
+                .remove(&hex::encode(proof.y().to_sec1_bytes()));
+        }
+    }
+
+    fn is_reserved(&self, proof: &Proof) -> bool {
+        self.reserved
+            .contains(&hex::encode(proof.y().to_sec1_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMint;
+
+    #[test]
+    fn test_insert_remove() {
+        let mint = MockMint::new("mint");
+        let mut store = MemoryProofStore::new();
+        let proofs = mint.mint_proofs(&[1, 2, 4]);
+        store.insert(proofs.clone());
+        store.insert(mint.mint_proofs(&[8]));
+        assert_eq!(store.all().total(), 15);
+
+        store.remove(&Proofs::from(proofs.as_ref()[..2].to_vec()));
+        assert_eq!(store.all().total(), 12);
+        assert!(store.select(13).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let mint = MockMint::new("mint");
+        let mut store = MemoryProofStore::new();
+        store.insert(mint.mint_proofs(&[1, 8, 2, 4]));
+        assert_eq!(store.select(0).unwrap().total(), 0);
+        assert_eq!(store.select(6).unwrap().total(), 8);
+        assert_eq!(store.select(9).unwrap().total(), 12);
+        assert!(matches!(
+            store.select(16),
+            Err(crate::Error::InsufficientFunds {
+                needed: 16,
+                available: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn test_select_matching() {
+        let mint = MockMint::new("mint");
+        let mut store = MemoryProofStore::new();
+        store.insert(mint.mint_proofs(&[1, 8, 2, 4]));
+        let small = |p: &Proof| p.amount < 8;
+        assert_eq!(store.select_matching(5, &small).unwrap().total(), 6);
+        assert!(matches!(
+            store.select_matching(8, &small),
+            Err(crate::Error::InsufficientFunds {
+                needed: 8,
+                available: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mint = MockMint::new("mint");
+        let mut store = MemoryProofStore::new();
+        store.insert(mint.mint_proofs(&[4, 8]));
+
+        let selected = store.select(5).unwrap();
+        store.reserve(&selected);
+        assert!(store.is_reserved(&selected.as_ref()[0]));
+        assert_eq!(store.select(4).unwrap().total(), 4);
+        assert!(store.select(5).is_err());
+
+        // Reserved proofs are still held
+        assert_eq!(store.all().total(), 12);
+
+        store.release(&selected);
+        assert_eq!(store.select(5).unwrap().total(), 8);
+
+        // Removing a reserved proof also drops its reservation
+        store.reserve(&selected);
+        store.remove(&selected);
+        store.insert(selected.clone());
+        assert!(!store.is_reserved(&selected.as_ref()[0]));
+    }
+}
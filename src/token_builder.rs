@@ -0,0 +1,103 @@
+use url::Url;
+
+use crate::{MintToken, Proofs, Token};
+
+// Assembles a token from proofs, grouping them by mint
+#[derive(Debug, Default)]
+pub struct TokenBuilder {
+    token: Vec<MintToken>,
+    memo: Option<String>,
+    unit: Option<String>,
+}
+
+impl TokenBuilder {
+    pub fn new() -> TokenBuilder {
+        TokenBuilder::default()
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> TokenBuilder {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn unit(mut self, unit: impl Into<String>) -> TokenBuilder {
+        self.unit = Some(unit.into());
+        self
+    }
+
+    pub fn add_proofs(mut self, mint: Url, proofs: Proofs) -> TokenBuilder {
+        self.mint_token(mint).proofs.append(proofs);
+        self
+    }
+
+    // Proofs along with a memo for this mint's entry, replacing any memo it already has
+    pub fn add_proofs_with_memo(
+        mut self,
+        mint: Url,
+        proofs: Proofs,
+        memo: impl Into<String>,
+    ) -> TokenBuilder {
+        let mint_token = self.mint_token(mint);
+        mint_token.proofs.append(proofs);
+        mint_token.memo = Some(memo.into());
+        self
+    }
+
+    pub fn build(self) -> Token {
+        Token {
+            token: self.token,
+            memo: self.memo,
+            unit: self.unit,
+        }
+    }
+
+    fn mint_token(&mut self, mint: Url) -> &mut MintToken {
+        let index = match self.token.iter().position(|t| t.mint == mint) {
+            Some(index) => index,
+            None => {
+                self.token.push(MintToken {
+                    mint,
+                    proofs: Proofs::default(),
+                    unit: None,
+                    memo: None,
+                });
+                self.token.len() - 1
+            }
+        };
+        &mut self.token[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMint;
+
+    #[test]
+    fn test_per_mint_memo_round_trip() {
+        let mint = MockMint::new("mint");
+        let a = Url::parse("https://a.example.com").unwrap();
+        let b = Url::parse("https://b.example.com").unwrap();
+        let token = TokenBuilder::new()
+            .memo("for lunch")
+            .unit("sat")
+            .add_proofs_with_memo(a.clone(), mint.mint_proofs(&[1, 2]), "my half")
+            .add_proofs(b.clone(), mint.mint_proofs(&[4]))
+            .add_proofs(a.clone(), mint.mint_proofs(&[8]))
+            .build();
+        assert_eq!(token.token.len(), 2);
+        assert_eq!(token.token[0].proofs.total(), 11);
+
+        let decoded = Token::deserialize(&token.serialize().unwrap()).unwrap();
+        assert_eq!(decoded, token);
+        assert_eq!(decoded.memo.as_deref(), Some("for lunch"));
+        assert_eq!(decoded.token[0].memo.as_deref(), Some("my half"));
+        assert_eq!(decoded.token[1].memo, None);
+
+        // Wallets that don't know per-mint memos find the standard fields where they expect them
+        let json: serde_json::Value = serde_json::to_value(&token).unwrap();
+        assert_eq!(json["memo"], "for lunch");
+        assert_eq!(json["token"][0]["mint"], "https://a.example.com/");
+        assert!(json["token"][1].get("memo").is_none());
+    }
+}
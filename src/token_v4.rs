@@ -0,0 +1,306 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::UnblindedKey;
+use crate::{KeysetId, MintToken, Proof, ProofDleq, Proofs, Token};
+
+// V4 tokens are unpadded, but decoding tolerates padding
+const URL_SAFE_V4: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// V3 tokens without a unit are denominated in sats
+pub(crate) const DEFAULT_UNIT: &str = "sat";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenV4 {
+    m: String,
+
+    u: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    d: Option<String>,
+
+    t: Vec<TokenV4Keyset>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenV4Keyset {
+    #[serde(with = "serde_bytes")]
+    i: Vec<u8>,
+
+    p: Vec<ProofV4>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProofV4 {
+    a: u64,
+
+    s: String,
+
+    #[serde(with = "serde_bytes")]
+    c: Vec<u8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    d: Option<DleqV4>,
+}
+
+// The V3 DLEQ fields, as raw bytes rather than hex
+#[derive(Debug, Serialize, Deserialize)]
+struct DleqV4 {
+    #[serde(with = "serde_bytes")]
+    e: Vec<u8>,
+
+    #[serde(with = "serde_bytes")]
+    s: Vec<u8>,
+
+    #[serde(with = "serde_bytes")]
+    r: Vec<u8>,
+}
+
+impl Token {
+    pub fn serialize_v4(&self) -> Result<String, crate::Error> {
+        let [mint_token] = self.token.as_slice() else {
+            return Err(crate::Error::TokenV4MintCount(self.token.len()));
+        };
+        let proofs = mint_token.proofs.as_ref();
+
+        let missing: Vec<usize> = proofs
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.id.is_none())
+            .map(|(i, _)| i)
+            .collect();
+        if !missing.is_empty() {
+            return Err(crate::Error::MissingKeysetId(missing));
+        }
+
+        // Group proofs by keyset, in order of first appearance
+        let mut keysets: Vec<(&KeysetId, Vec<ProofV4>)> = Vec::new();
+        for proof in proofs {
+            // Proofs without an id were rejected above
+            let Some(id) = &proof.id else { continue };
+            let c = UnblindedKey::from(crate::crypto::parse_point(&proof.unblinded_key)?);
+            let proof = ProofV4 {
+                a: proof.amount,
+                s: proof.secret.clone(),
+                c: c.to_compressed_bytes().to_vec(),
+                d: proof.dleq.as_ref().map(|dleq| DleqV4 {
+                    e: dleq.e.clone(),
+                    s: dleq.s.clone(),
+                    r: dleq.r.clone(),
+                }),
+            };
+            match keysets.iter_mut().find(|(i, _)| *i == id) {
+                Some((_, p)) => p.push(proof),
+                None => keysets.push((id, vec![proof])),
+            }
+        }
+        let t = keysets
+            .into_iter()
+            .map(|(id, p)| {
+                Ok(TokenV4Keyset {
+                    i: id.to_bytes()?,
+                    p,
+                })
+            })
+            .collect::<Result<_, crate::Error>>()?;
+
+        let token = TokenV4 {
+            m: crate::normalize_mint_url(&mint_token.mint),
+            u: self.mint_unit(0).unwrap_or(DEFAULT_UNIT).to_string(),
+            d: self.memo.clone().or_else(|| mint_token.memo.clone()),
+            t,
+        };
+        let mut data = Vec::new();
+        ciborium::into_writer(&token, &mut data).map_err(crate::Error::map_tokenv4)?;
+        let mut token = URL_SAFE_V4.encode(data);
+        token.insert_str(0, "cashuB");
+        Ok(token)
+    }
+
+    pub fn deserialize_v4(token: &str) -> Result<Token, crate::Error> {
+        Token::deserialize_v4_with_limit(token, crate::MAX_TOKEN_LENGTH)
+    }
+
+    pub fn deserialize_v4_with_limit(
+        token: &str,
+        max_length: usize,
+    ) -> Result<Token, crate::Error> {
+        crate::check_token_length(token, max_length)?;
+        let token = token
+            .strip_prefix("cashuB")
+            .ok_or(crate::Error::TokenV4(None))?;
+        let data = URL_SAFE_V4
+            .decode(token)
+            .map_err(crate::Error::map_tokenv4)?;
+        let token: TokenV4 =
+            ciborium::from_reader(data.as_slice()).map_err(crate::Error::map_tokenv4)?;
+
+        let mut proofs = Vec::new();
+        for keyset in token.t {
+            let id = KeysetId::from_bytes(&keyset.i)?;
+            for p in keyset.p {
+                let c: [u8; 33] =
+                    p.c.as_slice()
+                        .try_into()
+                        .map_err(crate::Error::map_tokenv4)?;
+                let c = UnblindedKey::from_compressed_bytes(&c)?;
+                proofs.push(Proof {
+                    id: Some(id.clone()),
+                    amount: p.a,
+                    secret: p.s,
+                    unblinded_key: c.to_compressed_bytes().to_vec(),
+                    dleq: p.d.map(|d| ProofDleq {
+                        e: d.e,
+                        s: d.s,
+                        r: d.r,
+                    }),
+                    witness: None,
+                });
+            }
+        }
+        Ok(Token {
+            token: vec![MintToken {
+                mint: crate::parse_mint_url(&token.m)?,
+                proofs: Proofs::from(proofs),
+                unit: None,
+                memo: None,
+            }],
+            memo: token.d,
+            unit: Some(token.u),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof(id: Option<&str>, amount: u64) -> Proof {
+        Proof {
+            id: id.map(|id| id.parse().unwrap()),
+            amount,
+            secret: format!("secret{amount}"),
+            unblinded_key: hex::decode(
+                "02c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4",
+            )
+            .unwrap(),
+            dleq: None,
+            witness: None,
+        }
+    }
+
+    fn token(proofs: Vec<Proof>) -> Token {
+        Token {
+            token: vec![MintToken {
+                mint: "https://8333.space:3338".parse().unwrap(),
+                proofs: Proofs::from(proofs),
+                unit: None,
+                memo: None,
+            }],
+            memo: Some("Thank you".to_string()),
+            unit: None,
+        }
+    }
+
+    #[test]
+    fn test_token_v4_round_trip() {
+        let token = token(vec![
+            proof(Some("009a1f293253e41e"), 1),
+            proof(Some("00ad268c4d1f5826"), 2),
+            proof(Some("009a1f293253e41e"), 4),
+        ]);
+        let serialized = token.serialize_v4().unwrap();
+        assert!(serialized.starts_with("cashuB"));
+
+        let decoded = Token::deserialize_v4(&serialized).unwrap();
+        assert_eq!(decoded.memo, token.memo);
+        assert_eq!(decoded.unit.as_deref(), Some("sat"));
+        assert_eq!(decoded.token[0].mint, token.token[0].mint);
+
+        // Proofs come back grouped by keyset
+        let amounts: Vec<u64> = decoded.token[0]
+            .proofs
+            .as_ref()
+            .iter()
+            .map(|p| p.amount)
+            .collect();
+        assert_eq!(amounts, vec![1, 4, 2]);
+        assert_eq!(
+            decoded.token[0].proofs.as_ref()[2],
+            token.token[0].proofs.as_ref()[1]
+        );
+    }
+
+    #[test]
+    fn test_token_v4_mint_url() {
+        let mut token = token(vec![proof(Some("009a1f293253e41e"), 1)]);
+        token.unit = Some("sat".to_string());
+        let serialized = token.serialize_v4().unwrap();
+        let data = URL_SAFE_V4
+            .decode(serialized.strip_prefix("cashuB").unwrap())
+            .unwrap();
+        let raw: TokenV4 = ciborium::from_reader(data.as_slice()).unwrap();
+        assert_eq!(raw.m, "https://8333.space:3338");
+
+        // V3 to V4 and back gives the same V3 token
+        let v3 = token.serialize().unwrap();
+        let v4 = Token::deserialize(&v3).unwrap().serialize_v4().unwrap();
+        assert_eq!(v4, serialized);
+        assert_eq!(Token::deserialize_v4(&v4).unwrap().serialize().unwrap(), v3);
+    }
+
+    #[test]
+    fn test_token_v4_dleq() {
+        let mint = crate::mock::MockMint::new("mint");
+        let mut proofs = mint.mint_proofs(&[1, 8]);
+        proofs.as_mut()[1].dleq = None;
+        let token = token(proofs.into());
+
+        let decoded = Token::deserialize_v4(&token.serialize_v4().unwrap()).unwrap();
+        assert_eq!(decoded.token[0].proofs, token.token[0].proofs);
+        let proofs = decoded.token[0].proofs.as_ref();
+        assert!(proofs[0].verify_dleq_own(&mint.keyset.keys[&1]));
+        assert!(proofs[1].dleq.is_none());
+    }
+
+    #[test]
+    fn test_token_v4_requires_keyset_id() {
+        let token = token(vec![
+            proof(Some("009a1f293253e41e"), 1),
+            proof(None, 2),
+            proof(None, 4),
+        ]);
+        assert!(matches!(
+            token.serialize_v4(),
+            Err(crate::Error::MissingKeysetId(ids)) if ids == vec![1, 2]
+        ));
+
+        // V3 keeps the id optional
+        let serialized = token.serialize().unwrap();
+        assert_eq!(Token::deserialize(&serialized).unwrap(), token);
+    }
+
+    #[test]
+    fn test_token_v4_single_mint() {
+        let mut token = token(vec![proof(Some("009a1f293253e41e"), 1)]);
+        token.token.push(MintToken {
+            mint: "https://example.com".parse().unwrap(),
+            proofs: Proofs::from(vec![proof(Some("009a1f293253e41e"), 2)]),
+            unit: None,
+            memo: None,
+        });
+        assert!(matches!(
+            token.serialize_v4(),
+            Err(crate::Error::TokenV4MintCount(2))
+        ));
+    }
+}
@@ -0,0 +1,2767 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use k256::SecretKey;
+use url::Url;
+
+use crate::crypto::Secret;
+use crate::{
+    Amountless, BlindedSignature, CheckStateRequest, CheckStateResponse, DeterministicSecrets,
+    Keyset, KeysetInfo, MeltMethod, MeltOptions, MeltQuote, MeltQuoteRequest, MeltRequest,
+    MemoryProofStore, MintConnector, MintQuote, MintQuoteRequest, MintRequest, OutputLimits,
+    Outputs, Proof, ProofStore, Proofs, QuoteState, RestoreRequest, State, SwapRequest, Token,
+    Unit,
+};
+
+// Swapping proofs for fewer larger ones is considered once the wallet holds more than this many
+pub const CONSOLIDATION_THRESHOLD: usize = 16;
+
+pub struct Wallet<C, S = MemoryProofStore> {
+    client: C,
+    keysets: Vec<Keyset>,
+    secrets: DeterministicSecrets,
+    counters: HashMap<String, u32>,
+    store: S,
+    output_limits: OutputLimits,
+
+    // Input fees in thousandths of the unit, by keyset id
+    fee_rates: HashMap<String, u64>,
+
+    // The largest melt fee reserve accepted, in millionths of the amount
+    max_fee_reserve_ppm: Option<u64>,
+
+    // Units by keyset id
+    units: HashMap<String, Unit>,
+
+    // Largest denominations the mint advertises, as powers of two by keyset id
+    max_orders: HashMap<String, u32>,
+
+    // Mints tokens are accepted from. Any mint is trusted until this is set.
+    trusted_mints: Option<Vec<Url>>,
+
+    // Keys P2PK-locked proofs may be locked to
+    p2pk_keys: Vec<SecretKey>,
+
+    // Ys of proofs the wallet has seen spent, by its own swaps and melts or in the mint's state
+    // checks. Nothing is ever dropped, so this grows with the wallet's history.
+    spent: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeltOutcome {
+    // The change is the amount returned to the wallet from the fee reserve
+    Paid {
+        preimage: Option<String>,
+        change: u64,
+    },
+
+    Pending {
+        quote_id: String,
+    },
+}
+
+impl<C: MintConnector> Wallet<C> {
+    pub fn new(
+        client: C,
+        seed: &[u8],
+        keysets: Vec<Keyset>,
+        keyset_infos: &[KeysetInfo],
+    ) -> Result<Wallet<C>, crate::Error> {
+        Wallet::with_store(client, seed, keysets, keyset_infos, MemoryProofStore::new())
+    }
+}
+
+impl<C: MintConnector, S: ProofStore> Wallet<C, S> {
+    // Keyset units, input fees and largest denominations come from `keyset_infos`, the mint's
+    // keyset list as fetched or stored, so a wallet can be made without reaching the mint
+    pub fn with_store(
+        client: C,
+        seed: &[u8],
+        keysets: Vec<Keyset>,
+        keyset_infos: &[KeysetInfo],
+        store: S,
+    ) -> Result<Wallet<C, S>, crate::Error> {
+        let mut wallet = Wallet {
+            client,
+            keysets,
+            secrets: DeterministicSecrets::new(seed)?,
+            counters: HashMap::new(),
+            store,
+            output_limits: OutputLimits::default(),
+            fee_rates: HashMap::new(),
+            max_fee_reserve_ppm: None,
+            units: HashMap::new(),
+            max_orders: HashMap::new(),
+            trusted_mints: None,
+            p2pk_keys: Vec::new(),
+            spent: HashSet::new(),
+        };
+        wallet.set_keyset_infos(keyset_infos);
+        Ok(wallet)
+    }
+
+    // What the wallet can spend. Proofs locked by NUT-10 conditions are left out until they're
+    // unlocked (see locked_balance).
+    pub fn balance(&self) -> u64 {
+        self.store
+            .all()
+            .as_ref()
+            .iter()
+            .filter(|p| p.secret_kind() == crate::SecretKind::Plain)
+            .map(|p| p.amount)
+            .sum()
+    }
+
+    // What the wallet holds in P2PK- or HTLC-locked proofs
+    pub fn locked_balance(&self) -> u64 {
+        self.store.all().total() - self.balance()
+    }
+
+    pub fn proofs(&self) -> Proofs {
+        self.store.all()
+    }
+
+    // Spendable balances, as for balance
+    pub fn balance_by_unit(&self) -> Result<HashMap<Unit, u64>, crate::Error> {
+        let mut balances = HashMap::new();
+        let proofs = self.store.all();
+        let unlocked = proofs
+            .as_ref()
+            .iter()
+            .filter(|p| p.secret_kind() == crate::SecretKind::Plain);
+        for proof in unlocked {
+            *balances.entry(self.unit_of(proof)?).or_default() += proof.amount;
+        }
+        Ok(balances)
+    }
+
+    // Units are taken from the keyset list the wallet was made with. Proofs without a keyset id,
+    // or from keysets the list didn't include, are in no known unit.
+    pub fn unit_of(&self, proof: &Proof) -> Result<Unit, crate::Error> {
+        let id = proof
+            .id
+            .as_ref()
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        self.units
+            .get(id.as_str())
+            .cloned()
+            .ok_or(crate::Error::UnknownUnit(id))
+    }
+
+    // Check the wallet can cover an invoice before asking the mint for a melt quote. The quote's
+    // fee reserve comes on top of this.
+    pub fn ensure_can_pay(&self, invoice: &str) -> Result<u64, crate::Error> {
+        let amount = crate::invoice_amount_sat(invoice)?.ok_or(crate::Error::AmountlessInvoice)?;
+        if amount > self.balance() {
+            return Err(crate::Error::InsufficientFunds {
+                needed: amount,
+                available: self.balance(),
+            });
+        }
+        Ok(amount)
+    }
+
+    // Keep proofs out of selection while an operation spending them is in flight
+    pub fn reserve(&mut self, proofs: &Proofs) {
+        self.store.reserve(proofs);
+    }
+
+    pub fn release(&mut self, proofs: &Proofs) {
+        self.store.release(proofs);
+    }
+
+    pub fn is_reserved(&self, proof: &Proof) -> bool {
+        self.store.is_reserved(proof)
+    }
+
+    pub fn select_proofs(&self, amount: u64) -> Result<Proofs, crate::Error> {
+        self.store.select(amount)
+    }
+
+    // Split outputs to fit the mint's caps on denominations and on outputs per request
+    pub fn set_output_limits(&mut self, limits: OutputLimits) {
+        self.output_limits = limits;
+    }
+
+    // Take keyset units, input fees and the largest denominations the mint advertises from a
+    // keyset list, on top of what the wallet was made with. Sending, melting and receiving only
+    // use proofs in the unit of the keyset the wallet swaps into. Keysets without an advertised
+    // largest denomination are limited to the largest amount they have a key for.
+    pub fn set_keyset_infos(&mut self, keysets: &[KeysetInfo]) {
+        for keyset in keysets {
+            self.units.insert(keyset.id.clone(), keyset.unit.clone());
+            self.fee_rates
+                .insert(keyset.id.clone(), keyset.input_fee_ppk);
+            match keyset.max_order {
+                Some(max_order) => self.max_orders.insert(keyset.id.clone(), max_order),
+                None => self.max_orders.remove(&keyset.id),
+            };
+        }
+    }
+
+    // Reject melt quotes whose fee reserve is more than this many millionths of the amount. Until
+    // this is set, any reserve is accepted.
+    pub fn set_max_fee_reserve_ppm(&mut self, ppm: Option<u64>) {
+        self.max_fee_reserve_ppm = ppm;
+    }
+
+    pub fn set_trusted_mints(&mut self, mints: Option<Vec<Url>>) {
+        self.trusted_mints = mints;
+    }
+
+    pub fn add_p2pk_key(&mut self, key: SecretKey) {
+        self.p2pk_keys.push(key);
+    }
+
+    pub fn counter(&self, keyset_id: &str) -> u32 {
+        self.counters.get(keyset_id).copied().unwrap_or_default()
+    }
+
+    // A bolt11 quote in the wallet's unit. Quotes outside the limits the mint advertises for it
+    // are refused here.
+    pub fn mint_quote(&self, amount: u64) -> Result<MintQuote, crate::Error> {
+        let unit = self.output_unit()?;
+        // Mints without NUT-06 advertise no limits
+        let limits = self.client.get_info().ok();
+        if let Some(limits) = limits.and_then(|info| info.mint_limits("bolt11", &unit)) {
+            limits.check(amount)?;
+        }
+        self.client.mint_quote(&MintQuoteRequest {
+            amount,
+            unit: unit.to_string(),
+        })
+    }
+
+    // A quote for paying a bolt11 invoice or a bolt12 offer. Offers are only sent to mints that
+    // advertise bolt12 melting, or that don't serve their info at all.
+    pub fn melt_quote(&self, request: &str) -> Result<MeltQuote, crate::Error> {
+        self.request_melt_quote(request, None)
+    }
+
+    // A quote for paying the given amount to an invoice or offer without one, from mints that
+    // advertise amountless melting for the method
+    pub fn melt_quote_amountless(
+        &self,
+        request: &str,
+        amount_msat: u64,
+    ) -> Result<MeltQuote, crate::Error> {
+        self.request_melt_quote(request, Some(amount_msat))
+    }
+
+    fn request_melt_quote(
+        &self,
+        request: &str,
+        amount_msat: Option<u64>,
+    ) -> Result<MeltQuote, crate::Error> {
+        let method = MeltMethod::for_request(request);
+        let amountless = || match method {
+            MeltMethod::Bolt11 => crate::Error::AmountlessInvoice,
+            MeltMethod::Bolt12 => crate::Error::AmountlessOffer,
+        };
+        let amount = match method {
+            MeltMethod::Bolt11 => crate::invoice_amount_sat(request)?,
+            MeltMethod::Bolt12 => crate::offer_amount_msat(request)?.map(crate::msat_to_sat_ceil),
+        };
+        let (amount, options) = match (amount, amount_msat) {
+            (Some(amount), None) => (amount, None),
+            (Some(_), Some(_)) => return Err(crate::Error::RequestHasAmount),
+            (None, None) => return Err(amountless()),
+            (None, Some(amount_msat)) => (
+                crate::msat_to_sat_ceil(amount_msat),
+                Some(MeltOptions {
+                    amountless: Some(Amountless { amount_msat }),
+                }),
+            ),
+        };
+        let unit = self.output_unit()?;
+        // Without the mint's info there is nothing to check up front, and the mint rejects what
+        // it doesn't support
+        let info = self.client.get_info().ok();
+        if let Some(info) = &info {
+            if (method == MeltMethod::Bolt12 || options.is_some())
+                && !info.supports_melt_method(method.as_str(), &unit)
+            {
+                return Err(crate::Error::UnsupportedMeltMethod(method));
+            }
+            if options.is_some() && !info.supports_amountless_melt(method.as_str(), &unit) {
+                return Err(amountless());
+            }
+        }
+        // The request's amount is in sats, so limits in other units are checked against the
+        // quote's amount instead
+        let limits = info.and_then(|info| info.melt_limits(method.as_str(), &unit));
+        if let Some(limits) = limits.as_ref().filter(|_| unit == Unit::Sat) {
+            limits.check(amount)?;
+        }
+        let mut quote = self.client.melt_quote(
+            method,
+            &MeltQuoteRequest {
+                request: request.trim().to_string(),
+                unit: unit.to_string(),
+                options,
+            },
+        )?;
+        if let Some(limits) = limits.as_ref().filter(|_| unit != Unit::Sat) {
+            limits.check(quote.amount)?;
+        }
+        // Melting goes to the same method, and the preimage is checked against the request,
+        // whether or not the mint echoes it
+        quote.method = Some(method);
+        quote
+            .request
+            .get_or_insert_with(|| request.trim().to_string());
+        if let Some(ppm) = self.max_fee_reserve_ppm {
+            if quote.fee_reserve as u128 * 1_000_000 > quote.amount as u128 * ppm as u128 {
+                return Err(crate::Error::FeeReserveTooHigh {
+                    reserve: quote.fee_reserve,
+                    amount: quote.amount,
+                });
+            }
+        }
+        Ok(quote)
+    }
+
+    // Pay a melt quote that hasn't expired from the wallet's proofs. Blank outputs for the
+    // overpaid amount are sent along and come back as change. A payment still in flight leaves
+    // the inputs reserved, so reconcile_pending can settle them once the mint knows the outcome.
+    // The blank outputs are deterministic, so change from a melt settled later can be found with
+    // restore. With no fee reserve, proofs adding up to the amount are spent as they are when the
+    // wallet has them, and no blank outputs are sent. A bolt11 melt reported paid with a
+    // preimage that doesn't match the invoice fails, after any change is kept.
+    pub fn melt(&mut self, quote: &MeltQuote) -> Result<MeltOutcome, crate::Error> {
+        quote.ensure_unexpired(unix_now())?;
+        let exact = match quote.fee_reserve {
+            0 => self.select_exact(quote.amount)?,
+            _ => None,
+        };
+        let (inputs, fee) = match exact {
+            Some(inputs) => (inputs, 0),
+            None => self.select_with_fee(quote.total_amount())?,
+        };
+        let keyset = self.output_keyset()?;
+        let overpaid = inputs.total() - fee - quote.amount;
+        let (outputs, secrets, factors) =
+            self.outputs_for_amounts(&keyset.id, &vec![0; crate::blank_output_count(overpaid)])?;
+        let method = quote.method.unwrap_or_else(|| {
+            MeltMethod::for_request(quote.request.as_deref().unwrap_or_default())
+        });
+
+        self.reserve(&inputs);
+        let request = MeltRequest {
+            quote: quote.quote.clone(),
+            inputs: inputs.clone(),
+            outputs: (!outputs.is_empty()).then_some(outputs),
+        };
+        let response = match self.client.melt(method, &request) {
+            Ok(response) => response,
+            // A rejected melt spent nothing. Any other failure may have reached the mint.
+            Err(e @ crate::Error::Mint { .. }) => {
+                self.release(&inputs);
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        match response.state() {
+            QuoteState::Paid => {
+                self.release(&inputs);
+                self.remove_spent(&inputs);
+                // The mint may sign fewer blank outputs than were sent, but not more
+                let change = response.change.unwrap_or_default();
+                let signed = change.len().min(secrets.len());
+                let change =
+                    unblind_signatures(&keyset, &change, &secrets[..signed], &factors[..signed])?;
+                let amount = change.total();
+                self.store.insert(change);
+                // A bolt11 preimage proves the payment, so one that doesn't is refused
+                if let (MeltMethod::Bolt11, Some(invoice), Some(preimage)) = (
+                    method,
+                    quote.request.as_deref(),
+                    response.payment_preimage.as_deref(),
+                ) {
+                    if !crate::verify_preimage(invoice, preimage)? {
+                        return Err(crate::Error::InvalidPreimage(response.quote));
+                    }
+                }
+                Ok(MeltOutcome::Paid {
+                    preimage: response.payment_preimage,
+                    change: amount,
+                })
+            }
+            QuoteState::Unpaid => {
+                self.release(&inputs);
+                Err(crate::Error::MeltFailed(response.quote))
+            }
+            QuoteState::Pending | QuoteState::Issued => Ok(MeltOutcome::Pending {
+                quote_id: response.quote,
+            }),
+        }
+    }
+
+    // Mint proofs for a paid quote that hasn't expired, returning the amount minted
+    pub fn mint(&mut self, quote: &MintQuote, amount: u64) -> Result<u64, crate::Error> {
+        if !quote.can_mint() {
+            return Err(crate::Error::QuoteNotPaid {
+                quote: quote.quote.clone(),
+                state: quote.state(),
+            });
+        }
+        quote.ensure_unexpired(unix_now())?;
+        let keyset = self.output_keyset()?;
+        let amounts = self.limits_for(&keyset).split(amount)?;
+        // A quote is minted by a single request, so its outputs can't be spread over several
+        if let Some(max) = self.output_limits.per_request() {
+            if amounts.len() > max {
+                return Err(crate::Error::TooManyOutputs {
+                    count: amounts.len() as u64,
+                    max,
+                });
+            }
+        }
+        let (outputs, secrets, factors) = self.outputs_for_amounts(&keyset.id, &amounts)?;
+        self.check_max_order(&keyset, &outputs)?;
+        let response = self.client.mint(&MintRequest {
+            quote: quote.quote.clone(),
+            outputs,
+        })?;
+        let proofs = unblind_signatures(&keyset, &response.signatures, &secrets, &factors)?;
+        let amount = proofs.total();
+        self.store.insert(proofs);
+        Ok(amount)
+    }
+
+    // Swap proofs covering `amount` and the input fee into proofs for exactly that amount, which
+    // are removed from the wallet and returned. Any change stays in the wallet.
+    pub fn send(&mut self, amount: u64) -> Result<Proofs, crate::Error> {
+        self.send_amounts(amount, None)
+    }
+
+    // Like send, but with the sent proofs split to match the target count of each denomination
+    // as far as the amount and the output limits allow (see split_amount_with_target). Sends
+    // that share a denomination distribution are harder to tell apart. A send too large for one
+    // request fails rather than losing the distribution over several.
+    pub fn send_with_privacy(
+        &mut self,
+        amount: u64,
+        target_counts: &BTreeMap<u64, usize>,
+    ) -> Result<Proofs, crate::Error> {
+        self.send_amounts(amount, Some(target_counts))
+    }
+
+    fn send_amounts(
+        &mut self,
+        amount: u64,
+        target_counts: Option<&BTreeMap<u64, usize>>,
+    ) -> Result<Proofs, crate::Error> {
+        let limits = self.output_limits()?;
+        let mut amounts = match target_counts {
+            Some(target) => limits.split_with_target(amount, target)?,
+            None => limits.split(amount)?,
+        };
+        let (inputs, fee) = self.select_with_fee(amount)?;
+        let change = inputs.total() - amount - fee;
+        let split = amounts.len();
+        amounts.extend(limits.split(change)?);
+        if !self.fits_one_request(amounts.len()) {
+            // Batches split their share of the amount as send does
+            if let (Some(_), Some(max)) = (target_counts, limits.per_request()) {
+                return Err(crate::Error::TooManyOutputs {
+                    count: amounts.len() as u64,
+                    max,
+                });
+            }
+            let batches = self.plan_batches(inputs.as_ref(), Some(amount))?;
+            return self.swap_batched(inputs, &batches, &[]);
+        }
+        self.swap_once(inputs, &amounts, split, &[])
+    }
+
+    // Swap the inputs into outputs for the amounts, handing back the first `split` of them.
+    // The rest go into the wallet. Inputs locked to any of the keys are signed for first, with the
+    // request in canonical order so a SIG_ALL signature covers it as sent.
+    fn swap_once(
+        &mut self,
+        mut inputs: Proofs,
+        amounts: &[u64],
+        split: usize,
+        keys: &[SecretKey],
+    ) -> Result<Proofs, crate::Error> {
+        let keyset = self.output_keyset()?;
+        let swap_outputs = self.outputs_for_amounts(&keyset.id, amounts)?;
+        crate::ensure_unique_outputs(&swap_outputs)?;
+        let (outputs, secrets, factors) = swap_outputs;
+        crate::validate_swap_outputs(&outputs)?;
+        self.check_max_order(&keyset, &outputs)?;
+        let mut entries: Vec<_> = outputs
+            .into_iter()
+            .zip(secrets.into_iter().zip(factors))
+            .enumerate()
+            .map(|(i, (output, (secret, r)))| (i < split, output, secret, r))
+            .collect();
+        if !keys.is_empty() {
+            inputs.sort_canonical();
+            entries.sort_by(|a, b| crate::cmp_outputs_canonical(&a.1, &b.1));
+        }
+        let outputs: Vec<_> = entries.iter().map(|e| e.1.clone()).collect();
+        for key in keys {
+            crate::sign_p2pk_inputs(&mut inputs, &outputs, key);
+        }
+
+        self.reserve(&inputs);
+        let response = self.client.swap(&SwapRequest {
+            inputs: inputs.clone(),
+            outputs,
+        });
+        self.release(&inputs);
+        let response = response?;
+        // The mint has spent the inputs, so they go even if the signatures turn out unusable
+        self.remove_spent(&inputs);
+
+        if response.signatures.len() != entries.len() {
+            return Err(crate::Error::InvalidResponse(format!(
+                "{} signatures for {} outputs",
+                response.signatures.len(),
+                entries.len()
+            )));
+        }
+        let mut send = (Vec::new(), Vec::new(), Vec::new());
+        let mut change = (Vec::new(), Vec::new(), Vec::new());
+        for ((sent, _, secret, r), signature) in entries.into_iter().zip(response.signatures) {
+            let (signatures, secrets, factors) = if sent { &mut send } else { &mut change };
+            signatures.push(signature);
+            secrets.push(secret);
+            factors.push(r);
+        }
+        let send_part = unblind_signatures(&keyset, &send.0, &send.1, &send.2)?;
+        self.apply_swap_result(send_part, change.0, change.1, change.2)
+    }
+
+    fn fits_one_request(&self, outputs: usize) -> bool {
+        self.output_limits
+            .per_request()
+            .is_none_or(|max| outputs <= max)
+    }
+
+    // Runs of the inputs whose outputs fit one request each, as the index each run ends at and
+    // how much of `amount` it hands back. Runs hand back all they can until the amount is
+    // covered, or everything without an amount, and split their share as usual.
+    fn plan_batches(
+        &self,
+        inputs: &[Proof],
+        amount: Option<u64>,
+    ) -> Result<Vec<(usize, u64)>, crate::Error> {
+        let limits = self.output_limits()?;
+        let max = limits.per_request().unwrap_or(usize::MAX);
+        let mut batches = Vec::new();
+        let mut remaining = amount.unwrap_or(u64::MAX);
+        let mut fees = 0;
+        let mut start = 0;
+        while start < inputs.len() {
+            let mut batch = None;
+            let mut over = None;
+            for end in start + 1..=inputs.len() {
+                let run = Proofs::from(inputs[start..end].to_vec());
+                let Some(net) = run.total().checked_sub(run.input_fee(&self.fee_rates)) else {
+                    continue;
+                };
+                let take = net.min(remaining);
+                let count = limits.output_count(take) + limits.output_count(net - take);
+                if count > max as u64 {
+                    over = Some(count);
+                    break;
+                }
+                batch = Some((end, take));
+            }
+            let Some((end, take)) = batch else {
+                let run = Proofs::from(inputs[start..].to_vec());
+                return Err(match over {
+                    Some(count) => crate::Error::TooManyOutputs { count, max },
+                    None => crate::Error::InsufficientFunds {
+                        needed: run.input_fee(&self.fee_rates),
+                        available: run.total(),
+                    },
+                });
+            };
+            let run = Proofs::from(inputs[start..end].to_vec());
+            fees += run.input_fee(&self.fee_rates);
+            batches.push((end, take));
+            remaining -= take;
+            start = end;
+        }
+        // Each request pays its own rounded-up fee, which can leave the inputs short
+        match amount {
+            Some(amount) if remaining > 0 => Err(crate::Error::InsufficientFunds {
+                needed: amount + fees,
+                available: inputs.iter().map(|p| p.amount).sum(),
+            }),
+            _ => Ok(batches),
+        }
+    }
+
+    // Swap each planned run of the inputs in its own request, handing back what the runs hand
+    // back. If a request fails, whatever earlier ones handed back goes into the wallet.
+    fn swap_batched(
+        &mut self,
+        inputs: Proofs,
+        batches: &[(usize, u64)],
+        keys: &[SecretKey],
+    ) -> Result<Proofs, crate::Error> {
+        let mut handed_back = Proofs::default();
+        let mut start = 0;
+        for (end, take) in batches {
+            let run = Proofs::from(inputs.as_ref()[start..*end].to_vec());
+            start = *end;
+            let net = run.total() - run.input_fee(&self.fee_rates);
+            let limits = self.output_limits()?;
+            let result = limits.split(*take).and_then(|mut amounts| {
+                let split = amounts.len();
+                amounts.extend(limits.split(net - take)?);
+                self.swap_once(run, &amounts, split, keys)
+            });
+            match result {
+                Ok(proofs) => handed_back.extend(proofs.as_ref().iter().cloned()),
+                Err(e) => {
+                    self.store.insert(handed_back);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(handed_back)
+    }
+
+    // Check a token could be received before swapping it: its mints are trusted, its proofs are
+    // from the wallet's keysets and in its unit, P2PK locks are to one of the wallet's keys, HTLCs already carry their
+    // preimage, and none of the proofs are ones the wallet has seen spent. Locks needing more than
+    // one signature, signatures on an HTLC, or a refund key once their locktime has passed can't
+    // be met by the wallet. Proofs are numbered across the whole token.
+    pub fn can_redeem(&self, token: &Token) -> Result<(), crate::Error> {
+        if let Some(trusted) = &self.trusted_mints {
+            token.check_mints_allowed(trusted)?;
+        }
+        let unit = self.output_unit()?;
+        let now = unix_now();
+        let proofs = token
+            .token
+            .iter()
+            .flat_map(|t| t.proofs.as_ref().iter().map(move |p| (&t.mint, p)));
+        for (index, (mint, proof)) in proofs.enumerate() {
+            let id = proof
+                .id
+                .as_ref()
+                .ok_or(crate::Error::MissingKeysetId(vec![index]))?;
+            if !self.keysets.iter().any(|k| &k.id == id) {
+                return Err(crate::Error::UnknownKeyset(id.to_string()));
+            }
+            let proof_unit = self.unit_of(proof)?;
+            if proof_unit != unit {
+                return Err(crate::Error::MixedUnits {
+                    mint: mint.clone(),
+                    units: vec![unit, proof_unit],
+                });
+            }
+            let unlockable = match crate::WellKnownSecret::parse(&proof.secret) {
+                None => true,
+                Some(secret) => match secret.secret_kind() {
+                    crate::SecretKind::Plain => true,
+                    crate::SecretKind::P2PK => {
+                        single_key_conditions(&secret, now)
+                            && proof.p2pk_pubkey().is_some_and(|pubkey| {
+                                self.p2pk_keys.iter().any(|k| k.public_key() == pubkey)
+                            })
+                    }
+                    crate::SecretKind::Htlc => {
+                        secret.tag("pubkeys").is_none()
+                            && single_key_conditions(&secret, now)
+                            && proof.verify_witness(&[]).is_ok()
+                    }
+                },
+            };
+            if !unlockable {
+                return Err(crate::Error::CannotUnlock(index));
+            }
+            if self.spent.contains(&hex::encode(proof.y().to_sec1_bytes())) {
+                return Err(crate::Error::ProofSpent(index));
+            }
+        }
+        Ok(())
+    }
+
+    // Swap a received token's proofs for new ones, so the sender, who knows their secrets, can't
+    // spend them after handing them over. The token must pass can_redeem, and proofs locked to
+    // the wallet's keys are signed for. Returns the amount received after input fees.
+    pub fn receive(&mut self, token: Token) -> Result<u64, crate::Error> {
+        if token.has_duplicate_secrets() {
+            return Err(crate::Error::DuplicateSecret);
+        }
+        self.can_redeem(&token)?;
+        let mut inputs = Proofs::default();
+        for mint_token in token.token {
+            inputs.append(mint_token.proofs);
+        }
+        let keys = self.p2pk_keys.clone();
+        match self.swap_in_place(inputs.clone(), &keys) {
+            // Find out which were spent, so they're caught without asking next time
+            Err(e @ crate::Error::Mint { code: 11001, .. }) => {
+                let request = CheckStateRequest {
+                    ys: inputs
+                        .as_ref()
+                        .iter()
+                        .map(|p| hex::encode(p.y().to_sec1_bytes()))
+                        .collect(),
+                };
+                self.note_spent(&self.client.check_state(&request)?);
+                Err(e)
+            }
+            result => result,
+        }
+    }
+
+    // Claim the P2PK proofs locked to this key (NUT-11) by swapping them for plain proofs,
+    // returning the amount claimed
+    pub fn unlock_and_swap(&mut self, key: &SecretKey) -> Result<u64, crate::Error> {
+        let pubkey = key.public_key();
+        let unit = self.output_unit()?;
+        let locked: Vec<Proof> = self
+            .store
+            .all()
+            .as_ref()
+            .iter()
+            .filter(|p| {
+                p.p2pk_pubkey() == Some(pubkey)
+                    && !self.is_reserved(p)
+                    && self.unit_of(p).is_ok_and(|u| u == unit)
+            })
+            .cloned()
+            .collect();
+        self.swap_in_place(Proofs::from(locked), std::slice::from_ref(key))
+    }
+
+    // Swap proofs from keysets the mint reports inactive into the one it reports active for the
+    // wallet's unit, one swap per keyset. The active keyset becomes the wallet's output keyset.
+    // The old proofs stay spendable, but change from them would otherwise keep landing in
+    // keysets the mint may eventually retire. Keysets whose proofs are worth no more than the fee
+    // for swapping them are left as they are.
+    pub fn migrate_to_active_keyset(&mut self) -> Result<(), crate::Error> {
+        let unit = self.output_unit()?;
+        let keysets = self.client.get_keysets()?;
+        let active = keysets.active(&unit)?.id.clone();
+        let index = self
+            .keysets
+            .iter()
+            .position(|k| k.id == active)
+            .ok_or_else(|| crate::Error::UnknownKeyset(active.clone()))?;
+        let keyset = self.keysets.remove(index);
+        self.keysets.insert(0, keyset);
+
+        let inactive = keysets
+            .keysets
+            .iter()
+            .filter(|k| !k.is_active() && k.id != active && k.unit == unit);
+        for info in inactive {
+            let proofs: Vec<Proof> = self
+                .store
+                .all()
+                .as_ref()
+                .iter()
+                .filter(|p| {
+                    p.id.as_deref() == Some(info.id.as_str()) && self.is_spendable(p, &unit)
+                })
+                .cloned()
+                .collect();
+            let proofs = Proofs::from(proofs);
+            if proofs.total() <= proofs.input_fee(&self.fee_rates) {
+                continue;
+            }
+            self.swap_in_place(proofs, &[])?;
+        }
+        Ok(())
+    }
+
+    // Once the wallet holds more than CONSOLIDATION_THRESHOLD proofs, swap them for as few as
+    // their value allows. Fees round up per request, so each proof costs at least the whole
+    // rounded-up fee when it ends up spent on its own. Consolidating is only worth it when the
+    // fee for the swap is less than that saving, or there is no fee.
+    pub fn consolidate(&mut self) -> Result<(), crate::Error> {
+        let unit = self.output_unit()?;
+        let proofs: Vec<Proof> = self
+            .store
+            .all()
+            .as_ref()
+            .iter()
+            .filter(|p| self.is_spendable(p, &unit))
+            .cloned()
+            .collect();
+        if proofs.len() <= CONSOLIDATION_THRESHOLD {
+            return Ok(());
+        }
+        let proofs = Proofs::from(proofs);
+        let fee = proofs.input_fee(&self.fee_rates);
+        let Some(amount) = proofs.total().checked_sub(fee) else {
+            return Ok(());
+        };
+
+        let keyset = self.output_keyset()?;
+        let spent_alone = |id: &str| self.fee_rates.get(id).map_or(0, |ppk| ppk.div_ceil(1000));
+        let current: u64 = proofs
+            .as_ref()
+            .iter()
+            .map(|p| p.id.as_deref().map_or(0, spent_alone))
+            .sum();
+        let consolidated = self.limits_for(&keyset).output_count(amount) * spent_alone(&keyset.id);
+        if fee > 0 && fee >= current.saturating_sub(consolidated) {
+            return Ok(());
+        }
+        self.swap_in_place(proofs, &[])?;
+        Ok(())
+    }
+
+    // Proofs covering the amount and the fee for spending them, along with that fee
+    fn select_with_fee(&self, amount: u64) -> Result<(Proofs, u64), crate::Error> {
+        let unit = self.output_unit()?;
+        let mut fee = 0;
+        loop {
+            let inputs = self
+                .store
+                .select_matching(amount + fee, &|p| self.is_spendable(p, &unit))?;
+            let needed = inputs.input_fee(&self.fee_rates);
+            if inputs.total() >= amount + needed {
+                return Ok((inputs, needed));
+            }
+            fee = needed;
+        }
+    }
+
+    // Proofs adding up to exactly the amount with no input fee, if the wallet has them
+    fn select_exact(&self, amount: u64) -> Result<Option<Proofs>, crate::Error> {
+        let unit = self.output_unit()?;
+        let mut available: Vec<Proof> = self
+            .store
+            .all()
+            .as_ref()
+            .iter()
+            .filter(|p| self.is_spendable(p, &unit))
+            .cloned()
+            .collect();
+        available.sort_by_key(|p| std::cmp::Reverse(p.amount));
+
+        let mut remaining = amount;
+        let mut selected = Vec::new();
+        for proof in available {
+            if proof.amount <= remaining {
+                remaining -= proof.amount;
+                selected.push(proof);
+            }
+        }
+        let selected = Proofs::from(selected);
+        Ok((remaining == 0 && selected.input_fee(&self.fee_rates) == 0).then_some(selected))
+    }
+
+    // Unreserved, unlocked and in the unit. Locked proofs would fail the whole swap they're in,
+    // so only unlock_and_swap spends them.
+    fn is_spendable(&self, proof: &Proof, unit: &Unit) -> bool {
+        !self.is_reserved(proof)
+            && self.unit_of(proof).is_ok_and(|u| &u == unit)
+            && proof.secret_kind() == crate::SecretKind::Plain
+    }
+
+    // Swap the inputs for new proofs worth their value less the input fee, which replace them in
+    // the wallet. Inputs locked to any of the keys are signed for first. Returns the amount
+    // swapped into.
+    fn swap_in_place(&mut self, inputs: Proofs, keys: &[SecretKey]) -> Result<u64, crate::Error> {
+        if inputs.total() == 0 {
+            return Ok(0);
+        }
+        let fee = inputs.input_fee(&self.fee_rates);
+        let amount = inputs
+            .total()
+            .checked_sub(fee)
+            .ok_or(crate::Error::InsufficientFunds {
+                needed: fee,
+                available: inputs.total(),
+            })?;
+        let amounts = self.output_limits()?.split(amount)?;
+        let proofs = if self.fits_one_request(amounts.len()) {
+            self.swap_once(inputs, &amounts, amounts.len(), keys)?
+        } else {
+            let batches = self.plan_batches(inputs.as_ref(), None)?;
+            self.swap_batched(inputs, &batches, keys)?
+        };
+        let amount = proofs.total();
+        self.store.insert(proofs);
+        Ok(amount)
+    }
+
+    // Unblind the change from a swap into the wallet, handing back the part being sent. The
+    // counters for the change outputs were already advanced when they were derived, so they're
+    // never reused even if this fails.
+    pub fn apply_swap_result(
+        &mut self,
+        send_part: Proofs,
+        change_signatures: Vec<BlindedSignature>,
+        change_secrets: Vec<Secret>,
+        change_factors: Vec<SecretKey>,
+    ) -> Result<Proofs, crate::Error> {
+        let keyset = self.output_keyset()?;
+        let change = unblind_signatures(
+            &keyset,
+            &change_signatures,
+            &change_secrets,
+            &change_factors,
+        )?;
+        self.store.insert(change);
+        Ok(send_part)
+    }
+
+    // Ys of the proofs the wallet has seen spent, to persist alongside its proofs
+    pub fn spent_ys(&self) -> impl Iterator<Item = &str> {
+        self.spent.iter().map(String::as_str)
+    }
+
+    // Restore Ys saved from spent_ys
+    pub fn mark_spent(&mut self, ys: impl IntoIterator<Item = String>) {
+        self.spent.extend(ys);
+    }
+
+    fn note_spent(&mut self, response: &CheckStateResponse) {
+        self.spent.extend(
+            response
+                .states
+                .iter()
+                .filter(|s| s.state == State::Spent)
+                .map(|s| s.y.clone()),
+        );
+    }
+
+    fn remove_spent(&mut self, proofs: &Proofs) {
+        self.spent.extend(
+            proofs
+                .as_ref()
+                .iter()
+                .map(|p| hex::encode(p.y().to_sec1_bytes())),
+        );
+        self.store.remove(proofs);
+    }
+
+    // New outputs are signed by the first keyset
+    fn output_keyset(&self) -> Result<Keyset, crate::Error> {
+        self.keysets
+            .first()
+            .cloned()
+            .ok_or_else(|| crate::Error::UnknownKeyset(String::new()))
+    }
+
+    // The largest denomination the keyset signs is 2^max_order, as the mint lists it or else as
+    // its keys go
+    fn keyset_max_order(&self, keyset: &Keyset) -> Option<u32> {
+        self.max_orders
+            .get(keyset.id.as_str())
+            .copied()
+            .or_else(|| keyset.max_order())
+    }
+
+    // The configured limits, with denominations capped to what the keyset can sign
+    fn limits_for(&self, keyset: &Keyset) -> OutputLimits {
+        let max_order = match (self.output_limits.max_order, self.keyset_max_order(keyset)) {
+            (Some(configured), Some(keyset)) => Some(configured.min(keyset)),
+            (configured, keyset) => configured.or(keyset),
+        };
+        OutputLimits {
+            max_order,
+            ..self.output_limits
+        }
+    }
+
+    fn output_limits(&self) -> Result<OutputLimits, crate::Error> {
+        Ok(self.limits_for(&self.output_keyset()?))
+    }
+
+    // A backstop for outputs split other than by limits_for
+    fn check_max_order(
+        &self,
+        keyset: &Keyset,
+        outputs: &[crate::BlindedMessage],
+    ) -> Result<(), crate::Error> {
+        match self.keyset_max_order(keyset) {
+            Some(max_order) => crate::check_max_order(outputs, max_order),
+            None => Ok(()),
+        }
+    }
+
+    fn output_unit(&self) -> Result<Unit, crate::Error> {
+        let id = self.output_keyset()?.id;
+        self.units
+            .get(id.as_str())
+            .cloned()
+            .ok_or(crate::Error::UnknownUnit(id.to_string()))
+    }
+
+    // Deterministic outputs for the given amounts, advancing the keyset's counter past them
+    fn outputs_for_amounts(
+        &mut self,
+        keyset_id: &str,
+        amounts: &[u64],
+    ) -> Result<Outputs, crate::Error> {
+        let counter = self.counter(keyset_id);
+        let (mut outputs, secrets, factors) =
+            self.secrets
+                .outputs_for_range(keyset_id, counter, amounts.len() as u32)?;
+        for (output, amount) in outputs.iter_mut().zip(amounts) {
+            output.amount = *amount;
+        }
+        self.counters
+            .insert(keyset_id.to_string(), counter + amounts.len() as u32);
+        Ok((outputs, secrets, factors))
+    }
+
+    // Recheck reserved proofs after a melt or swap failed or timed out. Those the mint reports
+    // unspent become spendable again, spent ones are dropped, and those still pending (or not
+    // reported at all) stay reserved.
+    pub fn reconcile_pending(&mut self) -> Result<(), crate::Error> {
+        let pending: Vec<Proof> = self
+            .store
+            .all()
+            .as_ref()
+            .iter()
+            .filter(|p| self.store.is_reserved(p))
+            .cloned()
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let request = CheckStateRequest {
+            ys: pending
+                .iter()
+                .map(|p| hex::encode(p.y().to_sec1_bytes()))
+                .collect(),
+        };
+        let response = self.client.check_state(&request)?;
+        let state_of = |proof: &Proof| {
+            let y = hex::encode(proof.y().to_sec1_bytes());
+            response.states.iter().find(|s| s.y == y).map(|s| s.state)
+        };
+        let (unspent, spent): (Vec<Proof>, Vec<Proof>) = pending
+            .into_iter()
+            .filter(|p| matches!(state_of(p), Some(State::Unspent | State::Spent)))
+            .partition(|p| state_of(p) == Some(State::Unspent));
+        self.store.release(&Proofs::from(unspent));
+        self.remove_spent(&Proofs::from(spent));
+        self.note_spent(&response);
+        Ok(())
+    }
+
+    // Recover proofs from the wallet seed (NUT-09). Each keyset is scanned in batches of
+    // `gap_limit` outputs until `gap_limit` consecutive outputs come back unsigned. Recovered
+    // proofs that are still unspent are added to the wallet, and the recovered balance is
+    // returned.
+    pub fn restore(&mut self, gap_limit: u32) -> Result<u64, crate::Error> {
+        let mut recovered = Vec::new();
+        for keyset in &self.keysets {
+            let mut counter = 0;
+            let mut next_counter = self.counter(&keyset.id);
+            let mut unsigned = 0;
+            while unsigned < gap_limit {
+                let (outputs, secrets, factors) = self
+                    .secrets
+                    .outputs_for_range(&keyset.id, counter, gap_limit)?;
+                let response = self.client.restore(&RestoreRequest {
+                    outputs: outputs.clone(),
+                })?;
+                if response.outputs.len() != response.signatures.len() {
+                    return Err(crate::Error::InvalidResponse(format!(
+                        "restore returned {} outputs and {} signatures",
+                        response.outputs.len(),
+                        response.signatures.len()
+                    )));
+                }
+
+                for (i, output) in outputs.iter().enumerate() {
+                    let signed = response
+                        .outputs
+                        .iter()
+                        .zip(&response.signatures)
+                        .find(|(o, _)| o.blinded_message == output.blinded_message);
+                    match signed {
+                        Some((_, signature)) => {
+                            recovered.push(Proof::from_signature(
+                                signature,
+                                &secrets[i],
+                                &factors[i],
+                                keyset,
+                            )?);
+                            next_counter = next_counter.max(counter + i as u32 + 1);
+                            unsigned = 0;
+                        }
+                        None => unsigned += 1,
+                    }
+                }
+                counter += gap_limit;
+            }
+            self.counters.insert(keyset.id.to_string(), next_counter);
+        }
+
+        // Only keep what hasn't been spent, and what the wallet doesn't already hold
+        let held = self.store.all();
+        recovered.retain(|p| !held.as_ref().iter().any(|q| q.secret == p.secret));
+        if recovered.is_empty() {
+            return Ok(0);
+        }
+        let request = CheckStateRequest {
+            ys: recovered
+                .iter()
+                .map(|p| hex::encode(p.y().to_sec1_bytes()))
+                .collect(),
+        };
+        let response = self.client.check_state(&request)?;
+        recovered.retain(|proof| {
+            let y = hex::encode(proof.y().to_sec1_bytes());
+            response
+                .states
+                .iter()
+                .any(|s| s.y == y && s.state == State::Unspent)
+        });
+        self.note_spent(&response);
+        let recovered = Proofs::from(recovered);
+        let amount = recovered.total();
+        self.store.insert(recovered);
+        Ok(amount)
+    }
+}
+
+// Everything spending the inputs could cost on top of what's being paid: their input fee, plus
+// the Lightning fee reserve for a melt. The reserve is the most a melt can cost, and any of it
+// left unused comes back as change.
+pub fn estimate_fees(
+    inputs: &Proofs,
+    melt_quote: Option<&MeltQuote>,
+    fee_rates: &HashMap<String, u64>,
+) -> u64 {
+    inputs.input_fee(fee_rates) + melt_quote.map_or(0, |quote| quote.fee_reserve)
+}
+
+// Whether NUT-11 conditions on the secret can be met by one signature from the locking key:
+// at most one signature is needed, and no locktime has passed to hand the proof to a refund key
+fn single_key_conditions(secret: &crate::WellKnownSecret, now: u64) -> bool {
+    let one_signature = secret
+        .tag("n_sigs")
+        .is_none_or(|n| n.first().is_some_and(|n| n == "1"));
+    let before_locktime = secret.tag("locktime").is_none_or(|locktime| {
+        locktime
+            .first()
+            .and_then(|t| t.parse::<u64>().ok())
+            .is_some_and(|t| now < t)
+    });
+    one_signature && before_locktime
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Every output must come back signed, or its value would be lost with the inputs
+fn unblind_signatures(
+    keyset: &Keyset,
+    signatures: &[BlindedSignature],
+    secrets: &[Secret],
+    factors: &[SecretKey],
+) -> Result<Proofs, crate::Error> {
+    if signatures.len() != secrets.len() || signatures.len() != factors.len() {
+        return Err(crate::Error::InvalidResponse(format!(
+            "{} signatures for {} outputs",
+            signatures.len(),
+            secrets.len()
+        )));
+    }
+    signatures
+        .iter()
+        .zip(secrets.iter().zip(factors))
+        .map(|(signature, (secret, r))| Proof::from_signature(signature, secret, r, keyset))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Proofs::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMint;
+
+    const SEED: [u8; 64] = [3; 64];
+
+    fn keyset_infos(mint: &MockMint) -> Vec<KeysetInfo> {
+        mint.get_keysets().unwrap().keysets
+    }
+
+    // Have the mint sign the wallet's deterministic outputs at the given counters
+    fn sign_outputs(mint: &MockMint, signed: &[(u32, u64)]) -> Vec<Proof> {
+        let secrets = DeterministicSecrets::new(&SEED).unwrap();
+        signed
+            .iter()
+            .map(|(counter, amount)| {
+                let (mut outputs, secrets, factors) = secrets
+                    .outputs_for_range(&mint.keyset.id, *counter, 1)
+                    .unwrap();
+                outputs[0].amount = *amount;
+                let signature = mint.sign(&outputs[0]);
+                Proof::from_signature(&signature, &secrets[0], &factors[0], &mint.keyset).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_restore() {
+        let mint = MockMint::new("mint");
+        let keyset_id = mint.keyset.id.clone();
+        let proofs = sign_outputs(&mint, &[(0, 1), (1, 2), (2, 4), (4, 8), (12, 16)]);
+        mint.set_state(&proofs[2].y(), State::Spent);
+
+        let keysets = vec![mint.keyset.clone()];
+        let infos = keyset_infos(&mint);
+        let mut wallet = Wallet::new(mint, &SEED, keysets, &infos).unwrap();
+        let amount = wallet.restore(3).unwrap();
+
+        // Counter 12 is past the gap, and counter 2 was spent
+        assert_eq!(amount, 11);
+        assert_eq!(wallet.balance(), 11);
+        assert_eq!(wallet.proofs().as_ref().len(), 3);
+        assert_eq!(wallet.counter(&keyset_id), 5);
+        let y = hex::encode(proofs[2].y().to_sec1_bytes());
+        assert_eq!(wallet.spent_ys().collect::<Vec<_>>(), vec![y.as_str()]);
+
+        // Restoring again finds nothing new
+        assert_eq!(wallet.restore(3).unwrap(), 0);
+        assert_eq!(wallet.balance(), 11);
+    }
+
+    #[test]
+    fn test_restore_missing_signatures() {
+        let mut mint = MockMint::new("mint");
+        sign_outputs(&mint, &[(0, 1), (1, 2)]);
+        mint.missing_signatures = 1;
+        let keysets = vec![mint.keyset.clone()];
+        let infos = keyset_infos(&mint);
+        let mut wallet = Wallet::new(mint, &SEED, keysets, &infos).unwrap();
+        assert!(matches!(
+            wallet.restore(3),
+            Err(crate::Error::InvalidResponse(_))
+        ));
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_restore_empty() {
+        let mint = MockMint::new("mint");
+        let keyset_id = mint.keyset.id.clone();
+        let keysets = vec![mint.keyset.clone()];
+        let infos = keyset_infos(&mint);
+        let mut wallet = Wallet::new(mint, &SEED, keysets, &infos).unwrap();
+        assert_eq!(wallet.restore(5).unwrap(), 0);
+        assert_eq!(wallet.counter(&keyset_id), 0);
+    }
+
+    #[test]
+    fn test_ensure_can_pay() {
+        let mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[8, 16]);
+        let keysets = vec![mint.keyset.clone()];
+        let infos = keyset_infos(&mint);
+        let mut wallet = Wallet::new(mint, &SEED, keysets, &infos).unwrap();
+        wallet.store.insert(proofs);
+
+        let invoice = crate::mock::invoice(Some(20_000), [1; 32]);
+        assert_eq!(wallet.ensure_can_pay(&invoice).unwrap(), 20);
+
+        let invoice = crate::mock::invoice(Some(25_000), [1; 32]);
+        assert!(matches!(
+            wallet.ensure_can_pay(&invoice),
+            Err(crate::Error::InsufficientFunds {
+                needed: 25,
+                available: 24
+            })
+        ));
+
+        let invoice = crate::mock::invoice(None, [1; 32]);
+        assert!(matches!(
+            wallet.ensure_can_pay(&invoice),
+            Err(crate::Error::AmountlessInvoice)
+        ));
+    }
+
+    #[test]
+    fn test_reserved_proofs_excluded_from_selection() {
+        let mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[4, 8, 16]);
+        let keysets = vec![mint.keyset.clone()];
+        let infos = keyset_infos(&mint);
+        let mut wallet = Wallet::new(mint, &SEED, keysets, &infos).unwrap();
+        wallet.store.insert(proofs);
+
+        let selected = wallet.select_proofs(10).unwrap();
+        assert_eq!(selected.as_ref()[0].amount, 16);
+
+        // The 16 is in flight, so a concurrent send must use the rest
+        wallet.reserve(&selected);
+        let next = wallet.select_proofs(10).unwrap();
+        assert_eq!(next.total(), 12);
+        assert!(next.as_ref().iter().all(|p| !wallet.is_reserved(p)));
+        assert!(matches!(
+            wallet.select_proofs(13),
+            Err(crate::Error::InsufficientFunds { .. })
+        ));
+
+        wallet.release(&selected);
+        assert_eq!(wallet.select_proofs(13).unwrap().total(), 16);
+    }
+
+    #[test]
+    fn test_mint_and_send() {
+        let mint = MockMint::new("mint");
+        let keyset_id = mint.keyset.id.clone();
+        let keysets = vec![mint.keyset.clone()];
+        let mut wallet = Wallet::new(&mint, &SEED, keysets, &keyset_infos(&mint)).unwrap();
+
+        let quote = wallet.mint_quote(100).unwrap();
+        assert_eq!(wallet.mint(&quote, 100).unwrap(), 100);
+        assert_eq!(wallet.balance(), 100);
+        assert_eq!(wallet.counter(&keyset_id), 3);
+
+        // The quote can't be minted twice
+        assert!(matches!(
+            wallet.mint(&quote, 100),
+            Err(crate::Error::Mint { code: 20002, .. })
+        ));
+        let quote = mint.check_mint_quote(&quote.quote).unwrap();
+        assert!(matches!(
+            wallet.mint(&quote, 100),
+            Err(crate::Error::QuoteNotPaid {
+                state: crate::QuoteState::Issued,
+                ..
+            })
+        ));
+
+        let sent = wallet.send(10).unwrap();
+        assert_eq!(sent.total(), 10);
+        assert_eq!(wallet.balance(), 90);
+        assert!(sent
+            .as_ref()
+            .iter()
+            .all(|p| p.verify_dleq_own(&mint.keyset.keys[&p.amount])));
+        assert!(matches!(
+            wallet.send(91),
+            Err(crate::Error::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_swap_result() {
+        let mint = MockMint::new("mint");
+        let keyset_id = mint.keyset.id.clone();
+        let keysets = vec![mint.keyset.clone()];
+        let mut wallet = Wallet::new(&mint, &SEED, keysets, &keyset_infos(&mint)).unwrap();
+        wallet.store.insert(mint.mint_proofs(&[4]));
+
+        let (outputs, secrets, factors) = wallet.outputs_for_amounts(&keyset_id, &[1, 2]).unwrap();
+        let signatures = outputs.iter().map(|o| mint.sign(o)).collect();
+        let send_part = mint.mint_proofs(&[8]);
+        let sent = wallet
+            .apply_swap_result(send_part.clone(), signatures, secrets, factors)
+            .unwrap();
+        assert_eq!(sent, send_part);
+        assert_eq!(wallet.balance(), 7);
+        assert_eq!(wallet.counter(&keyset_id), 2);
+
+        // Change the mint signed can be spent
+        let inputs = Proofs::from(wallet.proofs().as_ref()[1..].to_vec());
+        assert_eq!(inputs.total(), 3);
+        let (outputs, _, _) = wallet.outputs_for_amounts(&keyset_id, &[1, 2]).unwrap();
+        assert!(mint.swap(&SwapRequest { inputs, outputs }).is_ok());
+    }
+
+    #[test]
+    fn test_send_change_balance() {
+        let mint = MockMint::new("mint");
+        let keysets = vec![mint.keyset.clone()];
+        let mut wallet = Wallet::new(&mint, &SEED, keysets, &keyset_infos(&mint)).unwrap();
+        wallet.store.insert(mint.mint_proofs(&[64]));
+
+        let sent = wallet.send(21).unwrap();
+        assert_eq!(sent.total(), 21);
+        assert_eq!(wallet.balance(), 43);
+        let mut amounts: Vec<u64> = wallet.proofs().as_ref().iter().map(|p| p.amount).collect();
+        amounts.sort();
+        assert_eq!(amounts, crate::split_amount(43));
+    }
+
+    fn locked_proofs(mint: &MockMint, key: &SecretKey, sig_flag: crate::SigFlag) -> Proofs {
+        Proofs::from(vec![
+            mint.mint_proof(8, crate::mock::p2pk_secret(key, sig_flag)),
+            mint.mint_proof(4, crate::mock::p2pk_secret(key, sig_flag)),
+        ])
+    }
+
+    #[test]
+    fn test_unlock_and_swap() {
+        let mint = MockMint::new("mint");
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let other = SecretKey::random(&mut rand::thread_rng());
+        let locked = locked_proofs(&mint, &key, crate::SigFlag::SigInputs);
+
+        // The mint won't take locked proofs without a signature
+        let (mut outputs, _, _) = crate::blank_outputs(1, &mint.keyset.id).unwrap();
+        outputs[0].amount = 8;
+        assert!(matches!(
+            mint.swap(&SwapRequest {
+                inputs: Proofs::from(vec![locked.as_ref()[0].clone()]),
+                outputs,
+            }),
+            Err(crate::Error::Mint { code: 10003, .. })
+        ));
+
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(locked.clone());
+        wallet.store.insert(mint.mint_proofs(&[2]));
+        wallet.store.insert(Proofs::from(vec![mint.mint_proof(
+            1,
+            crate::mock::p2pk_secret(&other, crate::SigFlag::SigInputs),
+        )]));
+        assert_eq!(
+            wallet
+                .unlock_and_swap(&SecretKey::random(&mut rand::thread_rng()))
+                .unwrap(),
+            0
+        );
+
+        assert_eq!(wallet.balance(), 2);
+        assert_eq!(wallet.locked_balance(), 13);
+        assert_eq!(wallet.unlock_and_swap(&key).unwrap(), 12);
+        assert_eq!(wallet.balance(), 14);
+        assert_eq!(wallet.locked_balance(), 1);
+        let proofs = wallet.proofs();
+        assert!(proofs
+            .as_ref()
+            .iter()
+            .all(|p| p.p2pk_pubkey() != Some(key.public_key())));
+        assert_eq!(
+            proofs
+                .as_ref()
+                .iter()
+                .filter(|p| p.p2pk_pubkey().is_some())
+                .count(),
+            1
+        );
+
+        let ys = locked
+            .as_ref()
+            .iter()
+            .map(|p| hex::encode(p.y().to_sec1_bytes()))
+            .collect();
+        let states = mint.check_state(&CheckStateRequest { ys }).unwrap();
+        assert!(states.states.iter().all(|s| s.state == State::Spent));
+
+        // The mint shows how they were claimed
+        for state in states.states {
+            let witness: crate::P2PKWitness =
+                serde_json::from_str(&state.witness.unwrap()).unwrap();
+            assert_eq!(witness.signatures.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_locked_proofs_not_selected() {
+        let mint = MockMint::new("mint");
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet
+            .store
+            .insert(locked_proofs(&mint, &key, crate::SigFlag::SigInputs));
+        wallet
+            .store
+            .insert(mint.mint_proofs(&[1; CONSOLIDATION_THRESHOLD + 1]));
+        assert_eq!(wallet.balance(), 17);
+
+        // Neither the swap to consolidate nor the one to send takes the locked proofs
+        wallet.consolidate().unwrap();
+        assert_eq!(wallet.proofs().as_ref().len(), 4);
+        assert_eq!(wallet.send(12).unwrap().total(), 12);
+        assert_eq!(wallet.balance(), 5);
+        assert_eq!(wallet.locked_balance(), 12);
+        assert!(matches!(
+            wallet.send(6),
+            Err(crate::Error::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unlock_and_swap_sig_all() {
+        let mint = MockMint::new("mint");
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet
+            .store
+            .insert(locked_proofs(&mint, &key, crate::SigFlag::SigAll));
+        assert_eq!(wallet.unlock_and_swap(&key).unwrap(), 12);
+        assert_eq!(wallet.balance(), 12);
+        assert!(wallet
+            .proofs()
+            .as_ref()
+            .iter()
+            .all(|p| p.secret_kind() == crate::SecretKind::Plain));
+
+        // The signatures cover the request in the order it was sent, which is canonical
+        let request = mint.swaps.borrow().last().cloned().unwrap();
+        let mut message = Vec::new();
+        for input in request.inputs.as_ref() {
+            message.extend(input.secret.as_bytes());
+        }
+        for output in &request.outputs {
+            message.extend(hex::encode(&output.blinded_message).into_bytes());
+        }
+        assert!(request
+            .inputs
+            .as_ref()
+            .iter()
+            .all(|p| p.verify_p2pk(&message)));
+        let mut inputs = request.inputs.clone();
+        inputs.sort_canonical();
+        assert_eq!(inputs, request.inputs);
+        let mut outputs = request.outputs.clone();
+        crate::sort_outputs_canonical(&mut outputs);
+        assert_eq!(outputs, request.outputs);
+    }
+
+    #[test]
+    fn test_output_limits() {
+        let mint = MockMint::new("mint");
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.set_output_limits(OutputLimits {
+            max_order: Some(4),
+            max_outputs: None,
+        });
+        let quote = wallet.mint_quote(100).unwrap();
+        assert_eq!(wallet.mint(&quote, 100).unwrap(), 100);
+        let sent = wallet.send(40).unwrap();
+        assert_eq!(sent.total(), 40);
+        assert_eq!(wallet.balance(), 60);
+        assert!(sent
+            .as_ref()
+            .iter()
+            .chain(wallet.proofs().as_ref())
+            .all(|p| p.amount <= 16));
+    }
+
+    #[test]
+    fn test_max_outputs() {
+        let mut mint = MockMint::new("mint");
+        mint.max_outputs = Some(3);
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.set_output_limits(OutputLimits {
+            max_order: Some(2),
+            max_outputs: Some(3),
+        });
+        wallet.store.insert(mint.mint_proofs(&[4; 8]));
+
+        // 21 takes six outputs of at most 4, so it's sent over several swaps
+        let sent = wallet.send(21).unwrap();
+        assert_eq!(sent.total(), 21);
+        assert_eq!(wallet.balance(), 11);
+        assert!(sent
+            .as_ref()
+            .iter()
+            .chain(wallet.proofs().as_ref())
+            .all(|p| p.amount <= 4));
+
+        // A target can't be kept over several swaps
+        assert!(matches!(
+            wallet.send_with_privacy(5, &BTreeMap::from([(1, 5)])),
+            Err(crate::Error::TooManyOutputs { max: 3, .. })
+        ));
+        assert_eq!(wallet.balance(), 11);
+
+        // The outputs of a mint all go in its single request
+        let quote = wallet.mint_quote(21).unwrap();
+        assert!(matches!(
+            wallet.mint(&quote, 21),
+            Err(crate::Error::TooManyOutputs { count: 6, max: 3 })
+        ));
+        let quote = wallet.mint_quote(12).unwrap();
+        assert_eq!(wallet.mint(&quote, 12).unwrap(), 12);
+
+        // Received proofs are swapped in runs too
+        let token = crate::TokenBuilder::new()
+            .add_proofs(
+                "https://8333.space:3338".parse().unwrap(),
+                mint.mint_proofs(&[4; 8]),
+            )
+            .build();
+        assert_eq!(wallet.receive(token).unwrap(), 32);
+        assert_eq!(wallet.balance(), 55);
+    }
+
+    #[test]
+    fn test_max_order() {
+        let mint = MockMint::new("mint");
+        let keyset = Keyset {
+            id: mint.keyset.id.clone(),
+            keys: mint
+                .keyset
+                .keys
+                .range(..=8)
+                .map(|(a, k)| (*a, *k))
+                .collect(),
+        };
+        assert_eq!(keyset.max_order(), Some(3));
+        let mut wallet = Wallet::new(&mint, &SEED, vec![keyset], &keyset_infos(&mint)).unwrap();
+        wallet.store.insert(mint.mint_proofs(&[64]));
+
+        // Outputs split other than by the keyset's limit would be caught before they're sent
+        let output = crate::BlindedMessage {
+            amount: 16,
+            id: Some(mint.keyset.id.to_string()),
+            blinded_message: vec![],
+        };
+        let keyset = wallet.output_keyset().unwrap();
+        assert!(matches!(
+            wallet.check_max_order(&keyset, &[output]),
+            Err(crate::Error::AmountExceedsMaxOrder {
+                index: 0,
+                amount: 16,
+                max_order: 3
+            })
+        ));
+
+        // The keyset has no key for 16, so 21 is sent as 1, 4, 8 and 8, and a target asking for
+        // a 16 gets the same
+        let sent = wallet.send(21).unwrap();
+        assert_eq!(sent.total(), 21);
+        let private = wallet
+            .send_with_privacy(21, &BTreeMap::from([(16, 1)]))
+            .unwrap();
+        assert_eq!(
+            private.denomination_histogram(),
+            sent.denomination_histogram()
+        );
+        assert!(sent
+            .as_ref()
+            .iter()
+            .chain(private.as_ref())
+            .chain(wallet.proofs().as_ref())
+            .all(|p| p.amount <= 8));
+
+        // A limit from the mint's keyset list overrides the keys
+        let infos = [KeysetInfo {
+            id: mint.keyset.id.to_string(),
+            unit: Unit::Sat,
+            active: true,
+            input_fee_ppk: 0,
+            max_order: Some(1),
+        }];
+        wallet.set_keyset_infos(&infos);
+        let quote = wallet.mint_quote(4).unwrap();
+        assert_eq!(wallet.mint(&quote, 4).unwrap(), 4);
+        assert_eq!(wallet.balance(), 26);
+        assert!(wallet.proofs().as_ref().iter().all(|p| p.amount <= 8));
+
+        // A wallet made with the list takes the limit from the start
+        let mut limited = Wallet::new(&mint, &[4; 64], vec![mint.keyset.clone()], &infos).unwrap();
+        let quote = limited.mint_quote(4).unwrap();
+        assert_eq!(limited.mint(&quote, 4).unwrap(), 4);
+        assert!(limited.proofs().as_ref().iter().all(|p| p.amount <= 2));
+    }
+
+    #[test]
+    fn test_consolidate() {
+        let mut mint = MockMint::new("mint");
+        mint.input_fee_ppk = 100;
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+
+        // Too few proofs to bother
+        wallet
+            .store
+            .insert(mint.mint_proofs(&[1; CONSOLIDATION_THRESHOLD]));
+        wallet.consolidate().unwrap();
+        assert_eq!(wallet.proofs().as_ref().len(), CONSOLIDATION_THRESHOLD);
+
+        // 40 proofs cost 4 to swap, and save 1 each on being spent alone
+        wallet
+            .store
+            .insert(mint.mint_proofs(&[1; 40 - CONSOLIDATION_THRESHOLD]));
+        wallet.consolidate().unwrap();
+        assert_eq!(wallet.balance(), 36);
+        assert_eq!(wallet.proofs().as_ref().len(), 2);
+    }
+
+    #[test]
+    fn test_consolidate_not_worthwhile() {
+        let mut mint = MockMint::new("mint");
+        mint.input_fee_ppk = 1000;
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+
+        // Swapping 40 proofs would cost 40, but only saves 38
+        wallet.store.insert(mint.mint_proofs(&[2; 40]));
+        wallet.consolidate().unwrap();
+        assert_eq!(wallet.balance(), 80);
+        assert_eq!(wallet.proofs().as_ref().len(), 40);
+    }
+
+    #[test]
+    fn test_send_with_fees() {
+        let mut mint = MockMint::new("mint");
+        mint.input_fee_ppk = 600;
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(mint.mint_proofs(&[8, 4, 2, 1]));
+
+        // 8 and 4 cover 12 but not the fee of 2 for spending both, so 2 is added without
+        // raising the fee
+        let sent = wallet.send(12).unwrap();
+        assert_eq!(sent.total(), 12);
+        assert_eq!(wallet.balance(), 1);
+    }
+
+    #[test]
+    fn test_estimate_fees() {
+        let mint = MockMint::new("mint");
+        let inputs = mint.mint_proofs(&[8, 4, 2]);
+        let fee_rates = HashMap::from([(mint.keyset.id.to_string(), 400)]);
+        assert_eq!(estimate_fees(&inputs, None, &fee_rates), 2);
+        assert_eq!(estimate_fees(&inputs, None, &HashMap::new()), 0);
+
+        let quote = MeltQuote {
+            quote: "quote".to_string(),
+            amount: 10,
+            fee_reserve: 3,
+            expiry: None,
+            state: None,
+            paid: None,
+            request: None,
+            payment_preimage: None,
+            change: None,
+            method: None,
+        };
+        assert_eq!(estimate_fees(&inputs, Some(&quote), &fee_rates), 5);
+        assert_eq!(estimate_fees(&inputs, Some(&quote), &HashMap::new()), 3);
+    }
+
+    #[test]
+    fn test_melt_quote_amountless() {
+        let mut mint = MockMint::new("mint");
+        let invoice = crate::mock::invoice(None, [1; 32]);
+        {
+            let wallet = Wallet::new(
+                &mint,
+                &SEED,
+                vec![mint.keyset.clone()],
+                &keyset_infos(&mint),
+            )
+            .unwrap();
+            assert!(matches!(
+                wallet.melt_quote_amountless(&invoice, 21_000),
+                Err(crate::Error::AmountlessInvoice)
+            ));
+        }
+
+        mint.amountless = true;
+        let wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        let quote = wallet.melt_quote_amountless(&invoice, 21_000).unwrap();
+        assert_eq!(quote.amount, 21);
+        assert!(matches!(
+            wallet.melt_quote(&invoice),
+            Err(crate::Error::AmountlessInvoice)
+        ));
+        assert!(matches!(
+            wallet.melt_quote_amountless(&crate::mock::invoice(Some(21_000), [1; 32]), 21_000),
+            Err(crate::Error::RequestHasAmount)
+        ));
+    }
+
+    #[test]
+    fn test_quote_limits() {
+        let mut mint = MockMint::new("mint");
+        mint.max_amount = Some(1000);
+        let wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        assert!(matches!(
+            wallet.mint_quote(1001),
+            Err(crate::Error::AmountOutOfRange {
+                amount: 1001,
+                min: None,
+                max: Some(1000)
+            })
+        ));
+        assert!(wallet.mint_quote(1000).is_ok());
+        assert!(matches!(
+            wallet.melt_quote(&crate::mock::invoice(Some(1_001_000), [1; 32])),
+            Err(crate::Error::AmountOutOfRange { amount: 1001, .. })
+        ));
+    }
+
+    #[test]
+    fn test_quotes_without_info() {
+        let offer =
+            "lno1pqp9yzq2qe3k7enxv4j3vggry3jnatzrgjyqqtxqdwlm0ug0uxyerc6lnljrqtd75mfr20wq4vwq";
+        let mut mint = MockMint::new("mint");
+        mint.max_amount = Some(1000);
+        mint.no_info = true;
+        let wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+
+        // No limits are advertised, so none are checked
+        assert!(wallet.mint_quote(1001).is_ok());
+        let quote = wallet
+            .melt_quote(&crate::mock::invoice(Some(1_001_000), [1; 32]))
+            .unwrap();
+        assert_eq!(quote.amount, 1001);
+
+        // Nor is bolt12 support, which is left to the mint
+        assert!(matches!(
+            wallet.melt_quote(offer),
+            Err(crate::Error::Mint { code: 11000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_max_fee_reserve() {
+        let mint = MockMint::new("mint");
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        let invoice = crate::mock::invoice(Some(100_000_000), [1; 32]);
+
+        // The mock reserves 1%
+        wallet.set_max_fee_reserve_ppm(Some(5_000));
+        assert!(matches!(
+            wallet.melt_quote(&invoice),
+            Err(crate::Error::FeeReserveTooHigh {
+                reserve: 1000,
+                amount: 100_000
+            })
+        ));
+        wallet.set_max_fee_reserve_ppm(Some(10_000));
+        assert_eq!(wallet.melt_quote(&invoice).unwrap().fee_reserve, 1000);
+        wallet.set_max_fee_reserve_ppm(None);
+        assert!(wallet.melt_quote(&invoice).is_ok());
+    }
+
+    #[test]
+    fn test_melt_paid() {
+        let mint = MockMint::new("mint");
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(mint.mint_proofs(&[64]));
+
+        let quote = wallet
+            .melt_quote(&crate::mock::payable_invoice(21_000))
+            .unwrap();
+        assert_eq!(quote.total_amount(), 22);
+        let MeltOutcome::Paid { preimage, change } = wallet.melt(&quote).unwrap() else {
+            panic!("melt should be paid");
+        };
+        assert!(preimage.is_some());
+        assert_eq!(change, 43);
+        assert_eq!(wallet.balance(), 43);
+        assert!(wallet
+            .proofs()
+            .as_ref()
+            .iter()
+            .all(|p| !wallet.is_reserved(p)));
+
+        // The quote can't be paid twice
+        wallet.store.insert(mint.mint_proofs(&[32]));
+        assert!(matches!(
+            wallet.melt(&quote),
+            Err(crate::Error::Mint { code: 20005, .. })
+        ));
+        assert_eq!(wallet.select_proofs(75).unwrap().total(), 75);
+    }
+
+    #[test]
+    fn test_melt_bad_change() {
+        let mut mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[64]);
+        mint.signature_id = Some("00ffffffffffffff".parse().unwrap());
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(proofs);
+
+        let quote = wallet
+            .melt_quote(&crate::mock::payable_invoice(21_000))
+            .unwrap();
+        assert!(matches!(
+            wallet.melt(&quote),
+            Err(crate::Error::UnexpectedKeyset { .. })
+        ));
+        // The payment went through, so the inputs are gone even though the change is unusable
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_expired_quotes() {
+        let mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[64]);
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(proofs);
+
+        let quote = MintQuote {
+            expiry: Some(1),
+            ..wallet.mint_quote(8).unwrap()
+        };
+        assert!(matches!(
+            wallet.mint(&quote, 8),
+            Err(crate::Error::QuoteExpired { expiry: 1, .. })
+        ));
+        assert_eq!(wallet.counter(&mint.keyset.id), 0);
+
+        let quote = MeltQuote {
+            expiry: Some(1),
+            ..wallet
+                .melt_quote(&crate::mock::payable_invoice(21_000))
+                .unwrap()
+        };
+        assert!(matches!(
+            wallet.melt(&quote),
+            Err(crate::Error::QuoteExpired { expiry: 1, .. })
+        ));
+        assert_eq!(wallet.balance(), 64);
+        assert!(wallet
+            .proofs()
+            .as_ref()
+            .iter()
+            .all(|p| !wallet.is_reserved(p)));
+        assert_eq!(wallet.counter(&mint.keyset.id), 0);
+    }
+
+    #[test]
+    fn test_melt_wrong_preimage() {
+        let mut mint = MockMint::new("mint");
+        mint.preimage = [8; 32];
+        let proofs = mint.mint_proofs(&[64]);
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(proofs);
+
+        let quote = wallet
+            .melt_quote(&crate::mock::payable_invoice(21_000))
+            .unwrap();
+        assert!(matches!(
+            wallet.melt(&quote),
+            Err(crate::Error::InvalidPreimage(q)) if q == quote.quote
+        ));
+        // The inputs were spent all the same, and the change is kept
+        assert_eq!(wallet.balance(), 43);
+    }
+
+    #[test]
+    fn test_melt_fee_free() {
+        let mut mint = MockMint::new("mint");
+        mint.fee_free = true;
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(mint.mint_proofs(&[64, 16, 4, 1]));
+
+        let quote = wallet
+            .melt_quote(&crate::mock::payable_invoice(21_000))
+            .unwrap();
+        assert_eq!(quote.fee_reserve, 0);
+        assert!(matches!(
+            wallet.melt(&quote).unwrap(),
+            MeltOutcome::Paid { change: 0, .. }
+        ));
+
+        // The 16, 4 and 1 were spent, and no outputs were derived for change
+        assert_eq!(wallet.proofs().as_ref()[0].amount, 64);
+        assert_eq!(wallet.balance(), 64);
+        assert_eq!(wallet.counter(&mint.keyset.id), 0);
+    }
+
+    #[test]
+    fn test_melt_pending() {
+        let mut mint = MockMint::new("mint");
+        mint.melt_pending = true;
+        let inputs = mint.mint_proofs(&[32]);
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(inputs.clone());
+
+        let quote = wallet
+            .melt_quote(&crate::mock::payable_invoice(21_000))
+            .unwrap();
+        assert_eq!(
+            wallet.melt(&quote).unwrap(),
+            MeltOutcome::Pending {
+                quote_id: quote.quote.clone()
+            }
+        );
+        assert!(wallet.is_reserved(&inputs.as_ref()[0]));
+        assert_eq!(wallet.balance(), 32);
+
+        // Still pending, then settled
+        wallet.reconcile_pending().unwrap();
+        assert!(wallet.is_reserved(&inputs.as_ref()[0]));
+        mint.set_state(&inputs.as_ref()[0].y(), State::Spent);
+        wallet.reconcile_pending().unwrap();
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_melt_quote_method() {
+        let offer =
+            "lno1pqp9yzq2qe3k7enxv4j3vggry3jnatzrgjyqqtxqdwlm0ug0uxyerc6lnljrqtd75mfr20wq4vwq";
+        let mut mint = MockMint::new("mint");
+        let wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        let quote = wallet
+            .melt_quote(&crate::mock::invoice(Some(21_000), [1; 32]))
+            .unwrap();
+        assert_eq!(quote.amount, 21);
+        assert!(matches!(
+            wallet.melt_quote(offer),
+            Err(crate::Error::UnsupportedMeltMethod(MeltMethod::Bolt12))
+        ));
+
+        mint.bolt12 = true;
+        mint.omit_request = true;
+        let proofs = mint.mint_proofs(&[64]);
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(proofs);
+        let quote = wallet.melt_quote(offer).unwrap();
+        assert_eq!(quote.amount, 21);
+        assert_eq!(quote.method, Some(MeltMethod::Bolt12));
+
+        // The quote is melted the way it was asked for, though the mint didn't echo the offer
+        assert!(matches!(
+            wallet.melt(&quote).unwrap(),
+            MeltOutcome::Paid { change: 43, .. }
+        ));
+        assert!(matches!(
+            wallet.melt_quote(
+                "lno1pgrxxmmxvejk293pqvjx204vgdzgsqpvcp4mldl3plscny0rt707gvpdh6ndydfacz43c"
+            ),
+            Err(crate::Error::AmountlessOffer)
+        ));
+    }
+
+    #[test]
+    fn test_receive() {
+        let mint = MockMint::new("mint");
+        let received = mint.mint_proofs(&[1, 4, 8]);
+        let token = crate::TokenBuilder::new()
+            .add_proofs("https://8333.space:3338".parse().unwrap(), received.clone())
+            .build();
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        assert_eq!(wallet.receive(token).unwrap(), 13);
+        assert_eq!(wallet.balance(), 13);
+
+        // The wallet holds new proofs, and the sender's can no longer be spent
+        let held = wallet.proofs();
+        assert!(held
+            .as_ref()
+            .iter()
+            .all(|p| received.as_ref().iter().all(|r| r.secret != p.secret)));
+        assert!(matches!(
+            mint.swap(&SwapRequest {
+                inputs: received.clone(),
+                outputs: vec![],
+            }),
+            Err(crate::Error::Mint { code: 11001, .. })
+        ));
+
+        // Another wallet handed the same proofs learns they're spent
+        let token = crate::TokenBuilder::new()
+            .add_proofs("https://8333.space:3338".parse().unwrap(), received)
+            .build();
+        let mut other = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        assert!(matches!(
+            other.receive(token.clone()),
+            Err(crate::Error::Mint { code: 11001, .. })
+        ));
+        assert!(matches!(
+            other.can_redeem(&token),
+            Err(crate::Error::ProofSpent(0))
+        ));
+
+        // What it's learned carries over to a wallet restored from storage
+        let mut restored = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        restored.mark_spent(other.spent_ys().map(str::to_string));
+        assert!(matches!(
+            restored.can_redeem(&token),
+            Err(crate::Error::ProofSpent(0))
+        ));
+    }
+
+    #[test]
+    fn test_balance_by_unit() {
+        let mint = MockMint::new("mint");
+        let usd = MockMint::new("usd");
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone(), usd.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.set_keyset_infos(&[KeysetInfo {
+            id: usd.keyset.id.to_string(),
+            unit: Unit::Usd,
+            active: true,
+            input_fee_ppk: 0,
+            max_order: None,
+        }]);
+        wallet.store.insert(mint.mint_proofs(&[8, 2]));
+        wallet.store.insert(usd.mint_proofs(&[64]));
+        assert_eq!(
+            wallet.balance_by_unit().unwrap(),
+            HashMap::from([(Unit::Sat, 10), (Unit::Usd, 64)])
+        );
+
+        // Sats can only be sent from sats
+        assert!(matches!(
+            wallet.send(20),
+            Err(crate::Error::InsufficientFunds {
+                needed: 20,
+                available: 10
+            })
+        ));
+        assert_eq!(wallet.send(9).unwrap().total(), 9);
+        assert_eq!(wallet.balance_by_unit().unwrap()[&Unit::Usd], 64);
+
+        let token = crate::TokenBuilder::new()
+            .add_proofs(
+                "https://8333.space:3338".parse().unwrap(),
+                usd.mint_proofs(&[4]),
+            )
+            .build();
+        assert!(matches!(
+            wallet.receive(token),
+            Err(crate::Error::MixedUnits { units, .. }) if units == vec![Unit::Sat, Unit::Usd]
+        ));
+    }
+
+    #[test]
+    fn test_units_from_keyset_list() {
+        let mut mint = MockMint::new("usd");
+        mint.unit = Unit::Usd;
+        let other = MockMint::new("other");
+
+        // Nothing is fetched, so the wallet can be made offline from a stored keyset list
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        let proofs = mint.mint_proofs(&[4]);
+        assert_eq!(wallet.unit_of(&proofs.as_ref()[0]).unwrap(), Unit::Usd);
+        assert_eq!(wallet.output_unit().unwrap(), Unit::Usd);
+        assert!(matches!(
+            wallet.unit_of(&other.mint_proofs(&[4]).as_ref()[0]),
+            Err(crate::Error::UnknownUnit(id)) if id == other.keyset.id
+        ));
+
+        // Quotes are asked for in the wallet's unit
+        let quote = wallet.mint_quote(8).unwrap();
+        assert_eq!(wallet.mint(&quote, 8).unwrap(), 8);
+        assert_eq!(
+            wallet.balance_by_unit().unwrap(),
+            HashMap::from([(Unit::Usd, 8)])
+        );
+
+        let offline = Wallet::new(&mint, &SEED, vec![mint.keyset.clone()], &[]).unwrap();
+        assert!(matches!(
+            offline.mint_quote(8),
+            Err(crate::Error::UnknownUnit(_))
+        ));
+    }
+
+    #[test]
+    fn test_receive_checks_token() {
+        let mint = MockMint::new("mint");
+        let url: Url = "https://8333.space:3338".parse().unwrap();
+        let token = |proofs| {
+            crate::TokenBuilder::new()
+                .add_proofs(url.clone(), proofs)
+                .build()
+        };
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+
+        // Tokens that can't be redeemed never reach the mint
+        wallet.set_trusted_mints(Some(vec!["https://example.com".parse().unwrap()]));
+        assert!(matches!(
+            wallet.receive(token(mint.mint_proofs(&[1]))),
+            Err(crate::Error::MintNotAllowed(_))
+        ));
+        wallet.set_trusted_mints(None);
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let locked = locked_proofs(&mint, &key, crate::SigFlag::SigInputs);
+        assert!(matches!(
+            wallet.receive(token(locked.clone())),
+            Err(crate::Error::CannotUnlock(0))
+        ));
+        assert!(mint.swaps.borrow().is_empty());
+
+        // Proofs locked to one of the wallet's keys are signed for
+        wallet.add_p2pk_key(key);
+        assert_eq!(wallet.receive(token(locked)).unwrap(), 12);
+        assert_eq!(wallet.balance(), 12);
+        assert_eq!(wallet.locked_balance(), 0);
+    }
+
+    #[test]
+    fn test_receive_duplicate_secret() {
+        let mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[1, 4]);
+        let repeated = proofs.as_ref()[1].clone();
+        let token = crate::TokenBuilder::new()
+            .add_proofs("https://8333.space:3338".parse().unwrap(), proofs)
+            .add_proofs(
+                "https://example.com".parse().unwrap(),
+                Proofs::from(vec![repeated]),
+            )
+            .build();
+        assert!(token.has_duplicate_secrets());
+
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        assert!(matches!(
+            wallet.receive(token),
+            Err(crate::Error::DuplicateSecret)
+        ));
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_receive_unknown_keyset() {
+        let mint = MockMint::new("mint");
+        let other = MockMint::new("other");
+        let token = crate::TokenBuilder::new()
+            .add_proofs(
+                "https://8333.space:3338".parse().unwrap(),
+                other.mint_proofs(&[2]),
+            )
+            .build();
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        assert!(matches!(
+            wallet.receive(token),
+            Err(crate::Error::UnknownKeyset(id)) if id == other.keyset.id
+        ));
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_can_redeem() {
+        let mint = MockMint::new("mint");
+        let usd = MockMint::new("usd");
+        let url: Url = "https://8333.space:3338".parse().unwrap();
+        let token = |proofs| {
+            crate::TokenBuilder::new()
+                .add_proofs(url.clone(), proofs)
+                .build()
+        };
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone(), usd.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet
+            .can_redeem(&token(mint.mint_proofs(&[1, 4])))
+            .unwrap();
+
+        wallet.set_trusted_mints(Some(vec!["https://example.com".parse().unwrap()]));
+        assert!(matches!(
+            wallet.can_redeem(&token(mint.mint_proofs(&[1]))),
+            Err(crate::Error::MintNotAllowed(m)) if m == url
+        ));
+        wallet.set_trusted_mints(Some(vec![url.clone()]));
+
+        let mut unidentified = mint.mint_proofs(&[1, 2]);
+        unidentified.as_mut()[1].id = None;
+        assert!(matches!(
+            wallet.can_redeem(&token(unidentified)),
+            Err(crate::Error::MissingKeysetId(indices)) if indices == vec![1]
+        ));
+
+        wallet.set_keyset_infos(&[KeysetInfo {
+            id: usd.keyset.id.to_string(),
+            unit: Unit::Usd,
+            active: true,
+            input_fee_ppk: 0,
+            max_order: None,
+        }]);
+        assert!(matches!(
+            wallet.can_redeem(&token(usd.mint_proofs(&[2]))),
+            Err(crate::Error::MixedUnits { units, .. }) if units == vec![Unit::Sat, Unit::Usd]
+        ));
+
+        let key = SecretKey::random(&mut rand::thread_rng());
+        let mut locked = mint.mint_proofs(&[2]);
+        locked.append(locked_proofs(&mint, &key, crate::SigFlag::SigInputs));
+        assert!(matches!(
+            wallet.can_redeem(&token(locked.clone())),
+            Err(crate::Error::CannotUnlock(1))
+        ));
+        wallet.add_p2pk_key(key.clone());
+        wallet.can_redeem(&token(locked)).unwrap();
+
+        let htlc = crate::WellKnownSecret {
+            kind: "HTLC".to_string(),
+            data: crate::SecretData {
+                nonce: "00".to_string(),
+                data: hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"preimage")),
+                tags: vec![],
+            },
+        };
+        let mut proof = mint.mint_proof(4, serde_json::to_string(&htlc).unwrap());
+        assert!(matches!(
+            wallet.can_redeem(&token(Proofs::from(vec![proof.clone()]))),
+            Err(crate::Error::CannotUnlock(0))
+        ));
+        proof.witness = Some(
+            serde_json::to_string(&crate::HtlcWitness {
+                preimage: hex::encode(b"preimage"),
+                signatures: vec![],
+            })
+            .unwrap(),
+        );
+        wallet
+            .can_redeem(&token(Proofs::from(vec![proof])))
+            .unwrap();
+
+        // Conditions the wallet can't meet with its one key and the preimage
+        let hash = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"preimage"));
+        let pubkey = hex::encode(key.public_key().to_sec1_bytes());
+        let other = hex::encode(
+            SecretKey::random(&mut rand::thread_rng())
+                .public_key()
+                .to_sec1_bytes(),
+        );
+        let tag = |tag: &[&str]| tag.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let locked = |kind: &str, data: &str, tags: Vec<Vec<String>>| {
+            let secret = crate::WellKnownSecret {
+                kind: kind.to_string(),
+                data: crate::SecretData {
+                    nonce: "00".to_string(),
+                    data: data.to_string(),
+                    tags,
+                },
+            };
+            let mut proof = mint.mint_proof(4, serde_json::to_string(&secret).unwrap());
+            if kind == "HTLC" {
+                proof.witness = Some(format!(r#"{{"preimage":"{}"}}"#, hex::encode(b"preimage")));
+            }
+            proof
+        };
+        let cases = [
+            (
+                locked("P2PK", &pubkey, vec![tag(&["locktime", "4102444800"])]),
+                true,
+            ),
+            (
+                locked(
+                    "P2PK",
+                    &pubkey,
+                    vec![tag(&["pubkeys", &other]), tag(&["n_sigs", "1"])],
+                ),
+                true,
+            ),
+            (
+                locked(
+                    "P2PK",
+                    &pubkey,
+                    vec![tag(&["pubkeys", &other]), tag(&["n_sigs", "2"])],
+                ),
+                false,
+            ),
+            (
+                locked(
+                    "P2PK",
+                    &pubkey,
+                    vec![tag(&["locktime", "1"]), tag(&["refund", &other])],
+                ),
+                false,
+            ),
+            (
+                locked("P2PK", &pubkey, vec![tag(&["locktime", "soon"])]),
+                false,
+            ),
+            (
+                locked("HTLC", &hash, vec![tag(&["pubkeys", &pubkey])]),
+                false,
+            ),
+            (
+                locked(
+                    "HTLC",
+                    &hash,
+                    vec![tag(&["locktime", "1"]), tag(&["refund", &other])],
+                ),
+                false,
+            ),
+        ];
+        for (i, (proof, redeemable)) in cases.into_iter().enumerate() {
+            let result = wallet.can_redeem(&token(Proofs::from(vec![proof])));
+            if redeemable {
+                assert!(result.is_ok(), "case {i}");
+            } else {
+                assert!(
+                    matches!(result, Err(crate::Error::CannotUnlock(0))),
+                    "case {i}"
+                );
+            }
+        }
+
+        // Proofs the wallet spent itself are caught without asking the mint
+        let held = mint.mint_proofs(&[64]);
+        wallet.store.insert(held.clone());
+        wallet.send(21).unwrap();
+        assert!(matches!(
+            wallet.can_redeem(&token(held)),
+            Err(crate::Error::ProofSpent(0))
+        ));
+    }
+
+    #[test]
+    fn test_missing_signatures() {
+        let mut mint = MockMint::new("mint");
+        mint.missing_signatures = 1;
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        let quote = wallet.mint_quote(5).unwrap();
+        assert!(matches!(
+            wallet.mint(&quote, 5),
+            Err(crate::Error::InvalidResponse(_))
+        ));
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_send_missing_signatures() {
+        let mut mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[64]);
+        // Short of even the outputs for the amount being sent
+        mint.missing_signatures = 5;
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(proofs);
+        assert!(matches!(
+            wallet.send(21),
+            Err(crate::Error::InvalidResponse(_))
+        ));
+        assert_eq!(wallet.balance(), 0);
+    }
+
+    #[test]
+    fn test_unexpected_keyset() {
+        let mut mint = MockMint::new("mint");
+        let proofs = mint.mint_proofs(&[64]);
+        mint.signature_id = Some("00ffffffffffffff".parse().unwrap());
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(proofs.clone());
+        assert!(matches!(
+            wallet.send(21),
+            Err(crate::Error::UnexpectedKeyset { expected, found })
+                if expected == mint.keyset.id && found == "00ffffffffffffff"
+        ));
+        assert!(wallet
+            .proofs()
+            .as_ref()
+            .iter()
+            .all(|p| p.id.as_deref() != Some("00ffffffffffffff")));
+
+        let quote = wallet.mint_quote(8).unwrap();
+        assert!(matches!(
+            wallet.mint(&quote, 8),
+            Err(crate::Error::UnexpectedKeyset { .. })
+        ));
+        // The swap went through, so its inputs are gone with it
+        assert_eq!(wallet.balance(), 0);
+        assert!(wallet
+            .spent
+            .contains(&hex::encode(proofs.as_ref()[0].y().to_sec1_bytes())));
+    }
+
+    #[test]
+    fn test_reconcile_pending() {
+        let mint = MockMint::new("mint");
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        let proofs = mint.mint_proofs(&[1, 2, 4, 8]);
+        wallet.store.insert(proofs.clone());
+        wallet.reserve(&Proofs::from(proofs.as_ref()[..3].to_vec()));
+        let [unspent, pending, spent, _] = &proofs.as_ref()[..] else {
+            unreachable!()
+        };
+        mint.set_state(&unspent.y(), State::Pending);
+        mint.set_state(&pending.y(), State::Pending);
+        mint.set_state(&spent.y(), State::Pending);
+
+        // Nothing has settled yet
+        wallet.reconcile_pending().unwrap();
+        assert_eq!(wallet.balance(), 15);
+        assert!(proofs.as_ref()[..3].iter().all(|p| wallet.is_reserved(p)));
+
+        mint.set_state(&unspent.y(), State::Unspent);
+        mint.set_state(&spent.y(), State::Spent);
+        wallet.reconcile_pending().unwrap();
+        assert_eq!(wallet.balance(), 11);
+        assert!(!wallet.is_reserved(unspent));
+        assert!(wallet.is_reserved(pending));
+        assert_eq!(wallet.select_proofs(9).unwrap().total(), 9);
+    }
+
+    #[test]
+    fn test_send_with_privacy() {
+        let mint = MockMint::new("mint");
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(mint.mint_proofs(&[64]));
+
+        let target = BTreeMap::from([(1, 4), (2, 4), (4, 4)]);
+        let sent = wallet.send_with_privacy(21, &target).unwrap();
+        assert_eq!(sent.total(), 21);
+        assert_eq!(
+            sent.denomination_histogram(),
+            BTreeMap::from([(1, 1), (2, 2), (4, 4)])
+        );
+        assert_eq!(wallet.balance(), 43);
+    }
+
+    #[test]
+    fn test_migrate_to_active_keyset() {
+        let mut mint = MockMint::new("mint");
+        let old_proofs = mint.mint_proofs(&[8, 4, 1]);
+        let old_keyset = mint.keyset.clone();
+        mint.rotate_keyset("mint/rotated");
+        let new_proofs = mint.mint_proofs(&[2]);
+        let keysets = vec![mint.keyset.clone(), old_keyset.clone()];
+        let mut wallet = Wallet::new(&mint, &SEED, keysets, &keyset_infos(&mint)).unwrap();
+        wallet.store.insert(old_proofs.clone());
+        wallet.store.insert(new_proofs.clone());
+
+        wallet.migrate_to_active_keyset().unwrap();
+        assert_eq!(wallet.balance(), 15);
+        assert!(wallet
+            .proofs()
+            .as_ref()
+            .iter()
+            .all(|p| p.id.as_ref() == Some(&mint.keyset.id)));
+        assert!(wallet.proofs().as_ref().contains(&new_proofs.as_ref()[0]));
+
+        let ys = old_proofs
+            .as_ref()
+            .iter()
+            .map(|p| hex::encode(p.y().to_sec1_bytes()))
+            .collect();
+        let states = mint.check_state(&CheckStateRequest { ys }).unwrap();
+        assert!(states.states.iter().all(|s| s.state == State::Spent));
+
+        // Nothing is left to migrate
+        let before = wallet.proofs();
+        wallet.migrate_to_active_keyset().unwrap();
+        assert_eq!(wallet.proofs(), before);
+    }
+
+    #[test]
+    fn test_migrate_from_output_keyset() {
+        let mut mint = MockMint::new("a");
+        mint.input_fee_ppk = 1000;
+        let dust = mint.mint_proofs(&[1]);
+        let a = mint.keyset.clone();
+        mint.rotate_keyset("b");
+        let old_proofs = mint.mint_proofs(&[8, 4]);
+        let b = mint.keyset.clone();
+        mint.rotate_keyset("c");
+
+        // The wallet outputs to the first of its keysets, which the mint has retired
+        let mut wallet = Wallet::new(
+            &mint,
+            &SEED,
+            vec![a.clone(), b, mint.keyset.clone()],
+            &keyset_infos(&mint),
+        )
+        .unwrap();
+        wallet.store.insert(dust.clone());
+        wallet.store.insert(old_proofs);
+
+        // The 1 from the first keyset would be spent on its fee, so it stays where it is
+        wallet.migrate_to_active_keyset().unwrap();
+        assert_eq!(wallet.output_keyset().unwrap().id, mint.keyset.id);
+        assert_eq!(wallet.balance(), 11);
+        assert!(wallet.proofs().as_ref().contains(&dust.as_ref()[0]));
+        assert!(wallet
+            .proofs()
+            .as_ref()
+            .iter()
+            .all(|p| p.id == dust.as_ref()[0].id || p.id.as_ref() == Some(&mint.keyset.id)));
+    }
+}
@@ -0,0 +1,44 @@
+// Exercises `bdhke` from a no_std crate. std is only linked for the test harness, so the test
+// bodies themselves can't use anything from it.
+#![no_std]
+
+extern crate std;
+
+use cashu_test::bdhke;
+use k256::{PublicKey, SecretKey};
+
+fn key(byte: u8) -> SecretKey {
+    SecretKey::from_slice(&[byte; 32]).unwrap()
+}
+
+#[test]
+fn test_round_trip() {
+    let (r, k) = (key(1), key(7));
+    let y = bdhke::hash_to_curve(b"test_message");
+    let blinded_message = bdhke::blind(&y, &r).unwrap();
+    let blinded_key = bdhke::sign(&blinded_message, &k).unwrap();
+    let unblinded_key = bdhke::unblind(&blinded_key, &r, &k.public_key()).unwrap();
+    assert!(bdhke::verify(&y, &unblinded_key, &k));
+    assert!(!bdhke::verify(&y, &unblinded_key, &key(8)));
+}
+
+// https://github.com/cashubtc/nuts/blob/main/test-vectors/00-tests.md
+#[test]
+fn test_hash_to_curve_v2() {
+    let mut point = [0; 33];
+    hex_decode(
+        "024cce997d3b518f739663b757deaec95bcd9473c30a14ac2fd04023a739d1a725",
+        &mut point,
+    );
+    assert_eq!(
+        bdhke::hash_to_curve_v2(&[0; 32]),
+        PublicKey::from_sec1_bytes(&point).unwrap()
+    );
+}
+
+fn hex_decode(hex: &str, out: &mut [u8]) {
+    for (byte, pair) in out.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digit = |c: u8| (c as char).to_digit(16).unwrap() as u8;
+        *byte = digit(pair[0]) << 4 | digit(pair[1]);
+    }
+}